@@ -0,0 +1,10 @@
+//! Records the target this crate is compiled for. Since it is a proc macro, that is the host of
+//! the compiler that loads it, which is used to select the dylibs built with `targets`.
+
+fn main() {
+    println!(
+        "cargo:rustc-env=INLINE_PROC_HOST={}",
+        std::env::var("TARGET").unwrap()
+    );
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,133 @@
+//! The `cache_api` macro, which generates functions to list and remove the cached inner crates.
+
+use proc_macro::TokenStream as TokenStream1;
+use quote::quote;
+
+use crate::inline_proc::{CRATES_DIR_NAME, SHARED_CACHE_PREFIX};
+
+pub(crate) fn cache_api(input: TokenStream1) -> TokenStream1 {
+    syn::parse_macro_input!(input as syn::parse::Nothing);
+
+    quote! {
+        /// A cached inner crate of an inline proc macro module.
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct CacheEntry {
+            /// The package the module is in, or `None` for modules built with `shared_cache`.
+            pub package: ::std::option::Option<::std::string::String>,
+            /// The version component of the package: its major version, or `0.{minor}` for
+            /// versions before 1.0.
+            pub version: ::std::option::Option<::std::string::String>,
+            /// The sanitized name of the module, or `None` for modules built with `shared_cache`.
+            pub module: ::std::option::Option<::std::string::String>,
+            /// The hash of a module built with `shared_cache`.
+            pub hash: ::std::option::Option<::std::string::String>,
+            /// The directory of the inner crate.
+            pub path: ::std::path::PathBuf,
+            /// The total size of the files in `path`, in bytes.
+            pub size: u64,
+        }
+
+        /// List the cached inner crates. Crates in a `shared_workspace` are listed individually,
+        /// but the size of their shared target directory isn't included.
+        pub fn cache_entries() -> ::std::io::Result<::std::vec::Vec<CacheEntry>> {
+            fn dir_size(path: &::std::path::Path) -> ::std::io::Result<u64> {
+                let mut size = 0;
+                for entry in ::std::fs::read_dir(path)? {
+                    let entry = entry?;
+                    let file_type = entry.file_type()?;
+                    if file_type.is_dir() {
+                        size += dir_size(&entry.path())?;
+                    } else if file_type.is_file() {
+                        size += entry.metadata()?.len();
+                    }
+                }
+                ::std::result::Result::Ok(size)
+            }
+
+            // Split `{package}-{version}`, where the package may contain `-` but the version
+            // can't.
+            fn split_identifier(identifier: &str) -> ::std::option::Option<(::std::string::String, ::std::string::String)> {
+                let (package, version) = identifier.rsplit_once('-')?;
+                if !version.starts_with(|c: char| c.is_ascii_digit()) {
+                    return ::std::option::Option::None;
+                }
+                ::std::option::Option::Some((package.to_owned(), version.to_owned()))
+            }
+
+            let crates_dir = ::std::env::temp_dir().join(#CRATES_DIR_NAME);
+            let dir_entries = match ::std::fs::read_dir(&crates_dir) {
+                ::std::result::Result::Ok(dir_entries) => dir_entries,
+                ::std::result::Result::Err(e) if e.kind() == ::std::io::ErrorKind::NotFound => {
+                    return ::std::result::Result::Ok(::std::vec::Vec::new());
+                }
+                ::std::result::Result::Err(e) => return ::std::result::Result::Err(e),
+            };
+
+            let mut entries = ::std::vec::Vec::new();
+            for dir_entry in dir_entries {
+                let dir_entry = dir_entry?;
+                if !dir_entry.file_type()?.is_dir() {
+                    continue;
+                }
+                let path = dir_entry.path();
+                let name = dir_entry.file_name().to_string_lossy().into_owned();
+
+                if let ::std::option::Option::Some(hash) = name.strip_prefix(#SHARED_CACHE_PREFIX) {
+                    entries.push(CacheEntry {
+                        package: ::std::option::Option::None,
+                        version: ::std::option::Option::None,
+                        module: ::std::option::Option::None,
+                        hash: ::std::option::Option::Some(hash.to_owned()),
+                        size: dir_size(&path)?,
+                        path,
+                    });
+                } else if let ::std::option::Option::Some((package, version)) = split_identifier(&name) {
+                    // A shared workspace, with a directory for each module.
+                    for member in ::std::fs::read_dir(&path)? {
+                        let member = member?;
+                        let member_name = member.file_name().to_string_lossy().into_owned();
+                        if !member.file_type()?.is_dir() || member_name == "target" {
+                            continue;
+                        }
+                        let member_path = member.path();
+                        entries.push(CacheEntry {
+                            package: ::std::option::Option::Some(package.clone()),
+                            version: ::std::option::Option::Some(version.clone()),
+                            module: ::std::option::Option::Some(member_name),
+                            hash: ::std::option::Option::None,
+                            size: dir_size(&member_path)?,
+                            path: member_path,
+                        });
+                    }
+                } else if let ::std::option::Option::Some((identifier, module)) = name.rsplit_once('-') {
+                    // Module names are identifiers, so they don't contain `-`.
+                    let ::std::option::Option::Some((package, version)) = split_identifier(identifier) else {
+                        continue;
+                    };
+                    entries.push(CacheEntry {
+                        package: ::std::option::Option::Some(package),
+                        version: ::std::option::Option::Some(version),
+                        module: ::std::option::Option::Some(module.to_owned()),
+                        hash: ::std::option::Option::None,
+                        size: dir_size(&path)?,
+                        path,
+                    });
+                }
+            }
+            entries.sort_by(|a, b| a.path.cmp(&b.path));
+            ::std::result::Result::Ok(entries)
+        }
+
+        /// Remove a cached inner crate, so that its module is built from scratch the next time it
+        /// is expanded.
+        pub fn invalidate(entry: &CacheEntry) -> ::std::io::Result<()> {
+            match ::std::fs::remove_dir_all(&entry.path) {
+                ::std::result::Result::Err(e) if e.kind() == ::std::io::ErrorKind::NotFound => {
+                    ::std::result::Result::Ok(())
+                }
+                result => result,
+            }
+        }
+    }
+    .into()
+}
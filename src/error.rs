@@ -0,0 +1,99 @@
+//! Errors that can occur while building an inline proc macro.
+
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+use proc_macro2::Span;
+use proc_macro_error2::{emit_call_site_error, emit_error};
+
+pub(crate) type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// An error building an inline proc macro.
+///
+/// Errors in the metadata that can be attributed to a span are reported directly while parsing
+/// instead.
+#[derive(Debug)]
+pub(crate) enum Error {
+    /// The metadata is invalid.
+    Metadata(String),
+    /// A dependency could not be resolved.
+    Dependency(String),
+    /// The inner crate failed to compile. The diagnostics have already been emitted.
+    Compile,
+    /// Cargo could not be run, or it failed for a reason other than a compile error.
+    Cargo {
+        action: &'static str,
+        error: Option<io::Error>,
+    },
+    /// Reading or writing the files of the inner crate failed.
+    Io { action: String, error: io::Error },
+    /// The compiled dylib could not be used.
+    Dylib(String),
+    /// The `pre_build` command failed.
+    PreBuild(String),
+    /// Documenting the inner crate with `generate_docs` failed.
+    Docs(String),
+    /// An error caused by the metadata option at the span, which is reported there instead of at
+    /// the call site.
+    Spanned { span: Span, error: Box<Error> },
+}
+
+impl Error {
+    /// Create a function that converts an I/O error into an [`Error::Io`] for the given action.
+    pub(crate) fn io(action: impl Into<String>) -> impl FnOnce(io::Error) -> Self {
+        let action = action.into();
+        move |error| Self::Io { action, error }
+    }
+
+    /// Create a function that converts an I/O error into an [`Error::Cargo`] for the given action.
+    pub(crate) fn cargo(action: &'static str) -> impl FnOnce(io::Error) -> Self {
+        move |error| Self::Cargo {
+            action,
+            error: Some(error),
+        }
+    }
+
+    /// Attribute the error to the metadata option at the span, if it was found.
+    pub(crate) fn at(self, span: Option<Span>) -> Self {
+        match span {
+            Some(span) => Self::Spanned {
+                span,
+                error: Box::new(self),
+            },
+            None => self,
+        }
+    }
+
+    /// Report the error without aborting the macro.
+    pub(crate) fn emit(self) {
+        match self {
+            // The diagnostics of a compile error have already been emitted.
+            Self::Compile => {}
+            Self::Spanned { span, error } => emit_error!(span, "{}", error),
+            _ => emit_call_site_error!("{}", self),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Metadata(message)
+            | Self::Dependency(message)
+            | Self::Dylib(message)
+            | Self::PreBuild(message)
+            | Self::Docs(message) => f.write_str(message),
+            Self::Compile => f.write_str("Failed to compile the inline proc macro"),
+            Self::Cargo {
+                action,
+                error: Some(error),
+            } => write!(f, "Failed to {}: {}", action, error),
+            Self::Cargo {
+                action,
+                error: None,
+            } => write!(f, "Failed to {}", action),
+            Self::Io { action, error } => write!(f, "Failed to {}: {}", action, error),
+            Self::Spanned { error, .. } => error.fmt(f),
+        }
+    }
+}
@@ -2,521 +2,4200 @@
 
 use proc_macro::TokenStream as TokenStream1;
 use proc_macro2::{Delimiter, Group, Spacing, Span, TokenStream, TokenTree};
+use syn::ext::IdentExt;
 use syn::spanned::Spanned;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
 use std::fmt::{self, Display, Formatter, Write};
-use std::io::BufReader;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
-use std::{env, fs};
+use std::fs::{self, File};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, BufRead, BufReader};
+use std::iter;
+use std::num::NonZeroU32;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
 use cargo_metadata::diagnostic::{
     Diagnostic as CargoDiagnostic, DiagnosticLevel as CargoLevel, DiagnosticSpan as CargoSpan,
 };
 use cargo_metadata::Message as CargoMessage;
-use once_cell::sync::Lazy;
-use proc_macro_error2::{abort, abort_call_site, Diagnostic, Level};
-use quote::{format_ident, quote, ToTokens};
+use cargo_toml::Dependency as CargoDependency;
+use once_cell::sync::{Lazy, OnceCell};
+use proc_macro_error2::{abort, set_dummy, Diagnostic, Level};
+use quote::{format_ident, quote, quote_spanned, ToTokens};
 use serde::de::{self, Deserializer, Unexpected, Visitor};
 use serde::Deserialize;
 use syn::parse_macro_input;
 use syn::{
-    AttrStyle, Ident, Item, ItemMacro, ItemMod, MacroDelimiter, Path as RustPath, Visibility,
+    AttrStyle, Ident, Item, ItemMacro, ItemMod, MacroDelimiter, Path as RustPath, PathSegment,
+    ReturnType, Type, Visibility,
 };
 
-static TEMP_DIR: Lazy<PathBuf> = Lazy::new(env::temp_dir);
-static CRATES_DIR: Lazy<PathBuf> = Lazy::new(|| TEMP_DIR.join("inline-proc-crates"));
+use crate::error::{Error, Result};
+use crate::invoke::{open_library, SpanMode};
+use crate::timings::{report_cache_stats, CacheOutcome, Timings, TimingsFormat};
+use crate::{dylib_file_name, dylib_path, env_flag};
 
-pub(super) fn inline_proc(input: TokenStream1) -> TokenStream1 {
-    let (mod_name, metadata, content) = parse_mod(parse_macro_input!(input));
+static TEMP_DIR: Lazy<PathBuf> = Lazy::new(env::temp_dir);
+static CRATES_DIR: Lazy<PathBuf> = Lazy::new(|| TEMP_DIR.join(CRATES_DIR_NAME));
 
-    // assert_eq!(format!("{:?}", TokenString::from_tokens(generate_lib_rs(&metadata, content.clone())).tokens), format!("{:?}", generate_lib_rs(&metadata, content.clone()).to_string().replace("\\n", "\n")));
-    let lib_rs = generate_lib_rs(&metadata, content);
-    let cargo_toml = generate_cargo_toml(&metadata);
+/// The name of the directory in the temporary directory that the inner crates are put in.
+pub(crate) const CRATES_DIR_NAME: &str = "inline-proc-crates";
+/// The prefix of the directories of inner crates built with `shared_cache`, before their hash.
+pub(crate) const SHARED_CACHE_PREFIX: &str = "shared-";
 
-    let crate_root = CRATES_DIR.join(format!("{}-{}", CrateIdentifier, mod_name));
-    fs::create_dir_all(&crate_root)
-        .unwrap_or_else(|e| abort_call_site!("Failed to create crate root: {}", e));
+/// The version of the layout of `CRATES_DIR`. Bump this whenever the paths or contents of the
+/// generated crates change incompatibly, so that the old crates are deleted instead of reused.
+const CACHE_LAYOUT_VERSION: u32 = 1;
+static CACHE_LAYOUT_CHECKED: OnceCell<File> = OnceCell::new();
 
-    let cargo_toml_path = crate_root.join("Cargo.toml");
-    let lib_rs_path = crate_root.join("lib.rs");
+pub(super) fn inline_proc(args: AttributeArgs, input: TokenStream1) -> TokenStream1 {
+    let (mod_name, mut metadata, content) = parse_mod(parse_macro_input!(input));
+    args.apply(&mut metadata);
+    metadata.module_name = mod_name.clone();
 
-    fs::write(&cargo_toml_path, &cargo_toml)
-        .unwrap_or_else(|e| abort_call_site!("Failed to write Cargo.toml: {}", e));
-    fs::write(&lib_rs_path, &lib_rs.to_string())
-        .unwrap_or_else(|e| abort_call_site!("Failed to write lib.rs: {}", e));
+    let track_defaults = track_defaults_file(&metadata);
+    let invoker_reexport = generate_invoker_reexport(&metadata);
+    let exclusive_checks = generate_exclusive_checks(&metadata);
+    match build(&mod_name, &mut metadata, content) {
+        Ok(macros) => quote!(#macros #invoker_reexport #exclusive_checks #track_defaults).into(),
+        // The placeholders are output alongside the errors, so that uses of the macros don't
+        // cause more errors.
+        Err(e) => {
+            let placeholders = generate_placeholder_macros(&metadata);
+            set_dummy(quote!(#placeholders #invoker_reexport #exclusive_checks #track_defaults));
+            e.emit();
+            TokenStream1::new()
+        }
+    }
+}
 
-    let mut cargo = Command::new(&metadata.cargo)
-        .arg(if metadata.clippy { "clippy" } else { "check" })
-        .arg("--manifest-path")
-        .arg(&cargo_toml_path)
-        .arg("--message-format=json")
-        .arg("--color")
-        .arg(if metadata.color { "always" } else { "never" })
-        // If running clippy on the outside and clippy inside here Rustup can terminate our
-        // process because it thinks there is recursion.
-        // Removing this env var prevents this.
-        .env_remove("RUST_RECURSION_COUNT")
-        .stdout(Stdio::piped())
-        .spawn()
-        .unwrap_or_else(|e| abort_call_site!("Failed to launch Cargo: {}", e));
+/// The arguments of the `#[inline_proc]` attribute, such as `#[inline_proc(keep, verbose)]`. These
+/// are toggles for debugging, which override the metadata.
+#[derive(Default)]
+pub(super) struct AttributeArgs {
+    /// Keep a copy of the inner crate outside of the cache.
+    keep: bool,
+    /// Show Cargo's progress output, like `quiet: false`.
+    verbose: bool,
+    /// Check the code with Clippy, like `clippy: true`.
+    clippy: bool,
+}
 
-    CargoMessage::parse_stream(BufReader::new(cargo.stdout.as_mut().unwrap()))
-        .filter_map(|message| {
-            message
-                .map(|message| match message {
-                    CargoMessage::CompilerMessage(message) => Some(message.message),
-                    _ => None,
-                })
-                .transpose()
-        })
-        .map(|message| match message {
-            Ok(message) => cargo_diagnostic_to_diagnostic(message, &lib_rs),
-            Err(e) => Diagnostic::new(Level::Error, format!("Failed to read Cargo stdout: {}", e)),
-        })
-        .for_each(|diagnostic| diagnostic.emit());
+/// The arguments `#[inline_proc]` accepts.
+const ATTRIBUTE_ARGS: &[&str] = &["keep", "verbose", "clippy"];
 
-    let cargo_exit_code = cargo
-        .wait()
-        .unwrap_or_else(|e| abort_call_site!("Failed to wait on Cargo check: {}", e));
+impl syn::parse::Parse for AttributeArgs {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let mut args = Self::default();
+        let idents = syn::punctuated::Punctuated::<Ident, syn::Token![,]>::parse_terminated(input)?;
+        for ident in idents {
+            let flag = match &*ident.to_string() {
+                "keep" => &mut args.keep,
+                "verbose" => &mut args.verbose,
+                "clippy" => &mut args.clippy,
+                _ => {
+                    return Err(syn::Error::new(
+                        ident.span(),
+                        format!(
+                            "Unknown `inline_proc` argument `{}`, expected one of {}",
+                            ident,
+                            ATTRIBUTE_ARGS.join(", ")
+                        ),
+                    ))
+                }
+            };
+            *flag = true;
+        }
+        Ok(args)
+    }
+}
 
-    proc_macro_error2::abort_if_dirty();
-    if !cargo_exit_code.success() {
-        // An error with Cargo, not rustc
-        abort_call_site!("Cargo build failed.");
+impl AttributeArgs {
+    fn apply(self, metadata: &mut Metadata) {
+        metadata.keep |= self.keep;
+        metadata.quiet &= !self.verbose;
+        metadata.clippy |= self.clippy;
     }
+}
 
-    Command::new("cargo")
-        .arg("build")
-        .arg("--manifest-path")
-        .arg(&cargo_toml_path)
-        // See above `env_remove`
-        .env_remove("RUST_RECURSION_COUNT")
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .unwrap_or_else(|e| abort_call_site!("Failed to wait on Cargo build: {}", e));
-
-    let mut dylib_path = crate_root.join("target/debug");
-    // dylib_path.push("debug");
-    dylib_path.push(libloading::library_filename("inline_proc_macro"));
-
-    let dylib_path = dylib_path
-        .into_os_string()
-        .into_string()
-        .unwrap_or_else(|path| {
-            abort_call_site!(
-                "Failed to convert path {} to string",
-                PathBuf::from(path).display()
-            )
-        });
+/// Make the compiler track the defaults file merged into the metadata, if there is one, so that
+/// the module is rebuilt when it changes. Stable Rust can only track a file that is included, so
+/// this is an unnamed constant, which includes it relative to the file of the module.
+fn track_defaults_file(metadata: &Metadata) -> Option<TokenStream> {
+    let path = metadata.defaults_file.as_ref()?;
+    let path = proc_macro::Span::call_site()
+        .local_file()
+        .and_then(|file| {
+            let file = env::current_dir().ok()?.join(file);
+            relative_path(file.parent()?, path)
+        })
+        .unwrap_or_else(|| path.clone());
+    let path = path.to_string_lossy();
+    Some(quote!(
+        const _: &[u8] = ::core::include_bytes!(#path);
+    ))
+}
 
-    generate_user_macros(&metadata, &dylib_path).into()
+/// The path of `path` relative to the directory `base`, if they are both absolute and on the same
+/// drive.
+fn relative_path(base: &Path, path: &Path) -> Option<PathBuf> {
+    let mut base = base.components().peekable();
+    let mut path = path.components().peekable();
+    if base.peek() != path.peek() {
+        return None;
+    }
+    while base.peek().is_some() && base.peek() == path.peek() {
+        base.next();
+        path.next();
+    }
+    let mut relative: PathBuf = base.map(|_| Component::ParentDir).collect();
+    relative.extend(path);
+    Some(relative)
 }
 
-struct CrateIdentifier;
-impl Display for CrateIdentifier {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        let name = env::var("CARGO_PKG_NAME").unwrap();
-        let major_version: u64 = env::var("CARGO_PKG_VERSION_MAJOR")
-            .unwrap()
-            .parse()
-            .unwrap();
-        write!(f, "{}-{}", name, major_version)?;
-        if major_version == 0 {
-            write!(
-                f,
-                ".{}",
-                env::var("CARGO_PKG_VERSION_MINOR")
-                    .unwrap()
-                    .parse::<u64>()
-                    .unwrap()
-            )?;
+pub(super) fn generated_cargo_toml(input: TokenStream1) -> TokenStream1 {
+    let item = Item::Macro(ItemMacro {
+        attrs: Vec::new(),
+        ident: None,
+        mac: parse_macro_input!(input),
+        semi_token: None,
+    });
+    let mut metadata = parse_metadata(item, Span::call_site());
+
+    let cargo_toml = resolve_workspace_dependencies(
+        &metadata.cargo,
+        &mut metadata.dependencies,
+        &metadata.spans,
+    )
+    .and_then(|()| validate_dependencies(&metadata.dependencies, &metadata.spans))
+    .and_then(|()| generate_cargo_toml(&metadata, "inline-proc-macro"));
+    match cargo_toml {
+        Ok(cargo_toml) => match track_defaults_file(&metadata) {
+            Some(track_defaults) => quote!({ #track_defaults #cargo_toml }).into(),
+            None => quote!(#cargo_toml).into(),
+        },
+        Err(e) => {
+            e.emit();
+            TokenStream1::new()
         }
-        Ok(())
     }
 }
 
-/// Parse the input module. Returns the module name, metadata, and content tokens.
-///
-// If no features are enabled then it will produce unnecessary warnings
-#[cfg_attr(
-    not(any(feature = "json", feature = "ron")),
-    allow(unreachable_code, unused_variables, clippy::match_single_binding)
-)]
-fn parse_mod(module: ItemMod) -> (String, Metadata, TokenStream) {
-    if !matches!(module.vis, Visibility::Inherited) {
-        abort!(
-            module.vis,
-            "Inline proc module must not have a visibility modifier"
+/// Build the inner crate, returning the user-facing macros.
+fn build(mod_name: &str, metadata: &mut Metadata, content: TokenStream) -> Result<TokenStream> {
+    CACHE_LAYOUT_CHECKED.get_or_try_init(check_cache_layout)?;
+
+    resolve_workspace_dependencies(&metadata.cargo, &mut metadata.dependencies, &metadata.spans)?;
+    validate_dependencies(&metadata.dependencies, &metadata.spans)?;
+    check_dependency_policy(mod_name, metadata)?;
+    validate_exports(&metadata.exports, &metadata.spans)?;
+    validate_groups(&metadata.exports, &metadata.spans)?;
+    validate_reexport_invoker(metadata)?;
+    if metadata.cargo_subcommand.is_empty() {
+        return Err(
+            Error::Metadata("`cargo_subcommand` can't be empty".to_owned())
+                .at(metadata.spans.get(&["cargo_subcommand"])),
         );
     }
 
-    let (braces, mut module_content) = match module.content {
-        Some(content) => content,
-        None => abort!(module.semi.unwrap(), "Module must contain content"),
-    };
+    if metadata.shared_cache && metadata.shared_workspace {
+        return Err(Error::Metadata(
+            "`shared_cache` and `shared_workspace` can't be used together".to_owned(),
+        )
+        .at(metadata.spans.get(&["shared_cache"])));
+    }
+    // Cargo ignores the resolver of workspace members.
+    if metadata.resolver.is_some() && metadata.shared_workspace {
+        return Err(Error::Metadata(
+            "`resolver` can't be used with `shared_workspace`, which always uses resolver 2"
+                .to_owned(),
+        )
+        .at(metadata.spans.get(&["resolver"])));
+    }
 
-    if module_content.is_empty() {
-        abort!(braces.span.span(), "Missing metadata information");
+    // Passthrough macros don't use the dylib, so if every macro is one there is nothing to build
+    // and the dylib path is never used. The module's code is then not checked either.
+    let mut macros = metadata.exports.all_macros().peekable();
+    if macros.peek().is_some() && macros.all(|(_, mac, _)| mac.passthrough) {
+        return Ok(generate_user_macros(metadata, "", None));
     }
 
-    let (metadata_format, metadata_source) = match module_content.remove(0) {
-        Item::Macro(ItemMacro {
-            ident: None, mac, ..
-        }) if mac.path.segments.first().map_or(false, |seg| {
-            seg.ident == "metadata" && seg.arguments.is_empty()
-        }) =>
-        {
-            if mac.path.segments.len() > 2 {
-                abort!(mac.path, "Expected two segments");
-            }
-            let mut segments = mac.path.segments.into_iter();
-            let metadata_segment = segments.next().unwrap();
-            let format = match segments.next() {
-                Some(segment) if segment.arguments.is_empty() => segment.ident,
-                Some(segment) => abort!(segment.arguments, "Extra arguments in metadata format"),
-                None => abort!(metadata_segment, "Missing metadata format"),
-            };
+    let dir_name = short_name(mod_name);
 
-            let (group_span, delimiter) = match mac.delimiter {
-                MacroDelimiter::Paren(paren) => (paren.span, Delimiter::Parenthesis),
-                MacroDelimiter::Brace(brace) => (brace.span, Delimiter::Brace),
-                MacroDelimiter::Bracket(bracket) => (bracket.span, Delimiter::Bracket),
-            };
-            let mut group = Group::new(delimiter, mac.tokens);
-            group.set_span(group_span.span());
+    // In a shared workspace every inner crate needs a distinct name, since they share a target
+    // directory.
+    let package_name = if metadata.shared_workspace {
+        format!("inline-proc-macro-{}", dir_name)
+    } else {
+        "inline-proc-macro".to_owned()
+    };
 
-            // (format, TokenString::from_token(group))
-            (format, group.to_string())
-        }
-        _ => abort!(module.mod_token, "Missing metadata information"),
+    let lib_rs = generate_lib_rs(metadata, content)?;
+    let cargo_toml = generate_cargo_toml(metadata, &package_name)?;
+    let build_rs = generate_build_rs(metadata)?;
+    let harness = generate_harness(metadata, &package_name.replace('-', "_"))?;
+    let mut config_args = cargo_config_args(metadata)?;
+
+    let (crate_root, target_root) = if metadata.shared_workspace {
+        let workspace_root = CRATES_DIR.join(CrateIdentifier.to_string());
+        (workspace_root.join(&*dir_name), workspace_root)
+    } else if metadata.shared_cache {
+        // Everything that affects the build is hashed, so that identical modules from any package
+        // share a crate.
+        let parts = [
+            Some(&lib_rs.tokens),
+            Some(&cargo_toml),
+            build_rs.as_ref(),
+            harness.as_ref(),
+        ];
+        let key = shared_cache_key(parts.into_iter().chain(config_args.iter().map(Some)));
+        let crate_root = CRATES_DIR.join(format!("{}{:016x}", SHARED_CACHE_PREFIX, key));
+        (crate_root.clone(), crate_root)
+    } else {
+        let crate_root = CRATES_DIR.join(format!("{}-{}", CrateIdentifier, dir_name));
+        (crate_root.clone(), crate_root)
     };
 
-    let metadata: Metadata = match metadata_format {
-        #[cfg(feature = "json")]
-        format if format == "json" => serde_json::from_str(&metadata_source)
-            .unwrap_or_else(|e| abort!(TokenString::from_tokens(metadata_source).char_spans[e.column() - 1], e)),
-        #[cfg(feature = "ron")]
-        format if format == "ron" => ron::from_str(&metadata_source)
-            .unwrap_or_else(|e| abort!(TokenString::from_tokens(metadata_source).byte_spans[e.position.col], e)),
-        format => Diagnostic::spanned(
-            format.span(),
-            Level::Error,
-            format!("Unknown format {}", format),
+    check_dependency_cycle(mod_name, &crate_root, metadata)?;
+    check_host_dependency(mod_name, &crate_root, metadata)?;
+
+    // Added after `shared_cache` hashes the config, since it depends on the crate's path.
+    if metadata.reproducible {
+        let remap = format!("--remap-path-prefix={}=/inline-proc", target_root.display());
+        config_args.push("--config".to_owned());
+        config_args.push(format!(
+            "build.rustflags={}",
+            toml::Value::Array(vec![toml::Value::String(remap)])
+        ));
+    }
+
+    fs::create_dir_all(&crate_root).map_err(Error::io("create crate root"))?;
+
+    let mut lib_rs_source = lib_rs.tokens.clone();
+    if let Some(outputs_hash) = run_pre_build(metadata, &crate_root)? {
+        // Comments don't move the tokens before them, so the spans of diagnostics are unaffected.
+        write!(
+            lib_rs_source,
+            "\n// Pre-build outputs: {:016x}\n",
+            outputs_hash
         )
-        .help({
-            const SUPPORTED_FORMATS: &[&str] = &[
-               #[cfg(feature = "json")]
-               "json",
-               #[cfg(feature = "ron")]
-               "ron",
-            ];
-            if SUPPORTED_FORMATS.is_empty() {
-                "There are no supported formats. Try enabling the `json` or `ron` features of this crate.".to_owned()
+        .unwrap();
+    }
+
+    let cargo_toml_path = crate_root.join("Cargo.toml");
+    let lib_rs_path = crate_root.join("lib.rs");
+
+    write_if_changed(&cargo_toml_path, &cargo_toml).map_err(Error::io("write Cargo.toml"))?;
+    write_if_changed(&lib_rs_path, &lib_rs_source).map_err(Error::io("write lib.rs"))?;
+
+    // Cargo uses `build.rs` automatically if it exists, so it is removed when it isn't needed.
+    let build_rs_path = crate_root.join("build.rs");
+    match build_rs {
+        Some(build_rs) => {
+            write_if_changed(&build_rs_path, &build_rs).map_err(Error::io("write build.rs"))?
+        }
+        None if build_rs_path.exists() => {
+            fs::remove_file(&build_rs_path).map_err(Error::io("remove build.rs"))?
+        }
+        None => {}
+    }
+
+    if let Some(harness) = &harness {
+        write_if_changed(&crate_root.join("harness.rs"), harness)
+            .map_err(Error::io("write harness.rs"))?;
+    }
+
+    if metadata.shared_workspace {
+        update_shared_workspace(&target_root)?;
+    }
+
+    if metadata.check_transitive_dependencies {
+        check_transitive_dependency_policy(mod_name, metadata, &cargo_toml_path, &config_args)?;
+    }
+
+    let crate_name = package_name.replace('-', "_");
+    let dylib_path = dylib_path(&target_root.join("target"), &crate_name);
+    let timings_format = TimingsFormat::from_env();
+    let cold = !dylib_path.exists();
+    let check_start = Instant::now();
+    let mut dependencies_time = Duration::ZERO;
+    // Whether Cargo found the inner crate to be up to date.
+    let mut fresh = false;
+
+    let jobs_args: Vec<String> = jobs(metadata)
+        .into_iter()
+        .flat_map(|jobs| ["--jobs".to_owned(), jobs.to_string()])
+        .collect();
+    let rustc_wrapper = rustc_wrapper(metadata);
+    // This overrides `build.incremental` in the Cargo config, and the profile's `incremental`.
+    let incremental = if metadata.incremental { "1" } else { "0" };
+    let source_date_epoch = metadata
+        .reproducible
+        .then(|| source_date_epoch().unwrap_or(0).to_string());
+    let mut attempt = 0;
+    let check_time = loop {
+        let mut cargo = Command::new(&metadata.cargo)
+            .arg(if use_clippy(metadata) { "clippy" } else { "check" })
+            .args(&config_args)
+            .arg("--manifest-path")
+            .arg(&cargo_toml_path)
+            .arg("--message-format=json")
+            .args(&jobs_args)
+            .envs(metadata.rustc.as_ref().map(|rustc| ("RUSTC", rustc)))
+            .envs(
+                rustc_wrapper
+                    .as_ref()
+                    .map(|wrapper| ("RUSTC_WRAPPER", wrapper)),
+            )
+            .env("CARGO_INCREMENTAL", incremental)
+            .envs(
+                source_date_epoch
+                    .as_ref()
+                    .map(|epoch| ("SOURCE_DATE_EPOCH", epoch)),
+            )
+            .arg("--color")
+            .arg(if use_color(metadata) {
+                "always"
             } else {
-                format!("Supported formats are: {}", SUPPORTED_FORMATS.join(", "))
+                "never"
+            })
+            .args(use_quiet(metadata).then_some("--quiet"))
+            // If running clippy on the outside and clippy inside here Rustup can terminate our
+            // process because it thinks there is recursion.
+            // Removing this env var prevents this.
+            .env_remove("RUST_RECURSION_COUNT")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(Error::cargo("launch Cargo"))?;
+
+        // Cargo's errors are shown as it prints them, and kept to tell whether they are transient.
+        let stderr = cargo.stderr.take().unwrap();
+        let stderr_thread = thread::spawn(move || {
+            let mut captured = String::new();
+            for line in BufReader::new(stderr).lines().map_while(|line| line.ok()) {
+                eprintln!("{}", line);
+                captured.push_str(&line);
+                captured.push('\n');
             }
-        })
-        .abort(),
+            captured
+        });
+
+        let diagnostics_style = diagnostics_style(metadata);
+        // Whether rustc reported an error, and whether any error was emitted at all.
+        let mut seen_error = false;
+        let mut failed = false;
+        CargoMessage::parse_stream(BufReader::new(cargo.stdout.as_mut().unwrap()))
+            .filter_map(|message| {
+                message
+                    .map(|message| match message {
+                        CargoMessage::CompilerMessage(message) => Some(message.message),
+                        CargoMessage::CompilerArtifact(artifact) => {
+                            if artifact.target.name.replace('-', "_") != crate_name {
+                                dependencies_time = check_start.elapsed();
+                            } else {
+                                fresh = artifact.fresh;
+                            }
+                            None
+                        }
+                        _ => None,
+                    })
+                    .transpose()
+            })
+            // With `fail_fast`, only the first error is shown since the rest are often caused by it.
+            .filter(|message| {
+                let is_error = matches!(
+                    message,
+                    Ok(CargoDiagnostic {
+                        level: CargoLevel::Error | CargoLevel::Ice,
+                        ..
+                    })
+                );
+                let skip = metadata.fail_fast && is_error && seen_error;
+                seen_error |= is_error;
+                failed |= is_error || message.is_err();
+                !skip
+            })
+            .map(|message| match message {
+                Ok(message) => cargo_diagnostic_to_diagnostic(
+                    message,
+                    &lib_rs,
+                    diagnostics_style,
+                    &metadata.edition,
+                    (!use_quiet(metadata)).then_some(&*metadata.pretty_printer),
+                ),
+                Err(e) => {
+                    Diagnostic::new(Level::Error, format!("Failed to read Cargo stdout: {}", e))
+                }
+            })
+            .for_each(|diagnostic| diagnostic.emit());
+
+        let cargo_exit_code = cargo.wait().map_err(Error::cargo("wait on Cargo check"))?;
+        let stderr = stderr_thread.join().unwrap_or_default();
+
+        if failed {
+            return Err(Error::Compile);
+        }
+        if cargo_exit_code.success() {
+            break check_start.elapsed();
+        }
+        if !retry(metadata, &mut attempt, &stderr, "check", mod_name) {
+            return Err(Error::Cargo {
+                action: "check the inline proc macro",
+                error: None,
+            });
+        }
     };
 
-    #[allow(unreachable_code)]
-    let content = {
-        let mut content = TokenStream::new();
-        for attr in module.attrs {
-            if let AttrStyle::Inner(_) = attr.style {
-                attr.to_tokens(&mut content);
+    let build_start = Instant::now();
+    let (build_program, build_args) = build_program(metadata);
+    let build_command = |target: Option<&str>| {
+        let mut command = Command::new(&build_program);
+        command
+            .args(build_args)
+            .args(&config_args)
+            .arg("--manifest-path")
+            .arg(&cargo_toml_path)
+            .args(target.into_iter().flat_map(|target| ["--target", target]))
+            .args(&jobs_args)
+            .envs(metadata.rustc.as_ref().map(|rustc| ("RUSTC", rustc)))
+            .envs(
+                rustc_wrapper
+                    .as_ref()
+                    .map(|wrapper| ("RUSTC_WRAPPER", wrapper)),
+            )
+            .env("CARGO_INCREMENTAL", incremental)
+            .envs(
+                source_date_epoch
+                    .as_ref()
+                    .map(|epoch| ("SOURCE_DATE_EPOCH", epoch)),
+            )
+            // See above `env_remove`
+            .env_remove("RUST_RECURSION_COUNT");
+        command
+    };
+    if metadata.cargo_subcommand == default_cargo_subcommand() {
+        let mut attempt = 0;
+        loop {
+            let output = build_command(None)
+                .output()
+                .map_err(Error::cargo("wait on Cargo build"))?;
+            if output.status.success() {
+                break;
+            }
+            // The check succeeded, so this doesn't fail because of compile errors.
+            let stderr = strip_control_codes(&String::from_utf8_lossy(&output.stderr));
+            if !retry(metadata, &mut attempt, &stderr, "build", mod_name) {
+                return Err(Error::Dylib(format!(
+                    "Failed to build the macro\n{}",
+                    stderr.trim_end()
+                )));
             }
         }
-        for item in module_content {
-            item.to_tokens(&mut content);
+    } else {
+        let output = build_command(None)
+            .output()
+            .map_err(Error::cargo("wait on Cargo build"))?;
+        check_custom_build(metadata, &output, &dylib_path)?;
+    }
+
+    let targets: Vec<&str> = metadata
+        .targets
+        .iter()
+        .map(|target| resolve_target(target))
+        .collect();
+    remove_unlisted_targets(&crate_root.join(DIST_DIR), &targets)?;
+    for &target in &targets {
+        let output = build_command(Some(target))
+            .output()
+            .map_err(Error::cargo("wait on Cargo build"))?;
+        if !output.status.success() {
+            return Err(Error::Dylib(format!(
+                "Failed to build the macro for target `{}`\n{}",
+                target,
+                strip_control_codes(&String::from_utf8_lossy(&output.stderr)).trim_end()
+            ))
+            .at(metadata.spans.get(&["targets"])));
         }
-        content
+        let target_dylib = target_root
+            .join("target")
+            .join(target)
+            .join("debug")
+            .join(dylib_file_name(target, &crate_name));
+        let dist_path = crate_root
+            .join(DIST_DIR)
+            .join(target)
+            .join(target_dylib.file_name().unwrap());
+        copy_if_changed(&target_dylib, &dist_path)?;
+    }
+
+    let docs_index = if metadata.generate_docs {
+        Some(generate_docs(
+            metadata,
+            &cargo_toml_path,
+            &config_args,
+            &target_root.join("target"),
+            &crate_name,
+        )?)
+    } else {
+        None
     };
 
-    (module.ident.to_string(), metadata, content)
-}
+    let dylib_path = if metadata.reproducible {
+        reproducible_dylib(&dylib_path, &crate_root)?
+    } else {
+        dylib_path
+    };
 
-/// Metadata for an inline proc macro.
-#[derive(Deserialize)]
-struct Metadata {
-    #[serde(default = "default_cargo")]
-    cargo: PathBuf,
-    #[serde(default = "return_true")]
-    color: bool,
-    #[serde(default)]
-    clippy: bool,
-    #[serde(default = "default_edition")]
-    edition: String,
-    dependencies: cargo_toml::DepsSet,
-    #[serde(default = "default_inline_proc_path")]
-    inline_proc_path: DeserializePath,
-    exports: Exports,
-}
+    if let Some(depfile_path) = depfile_path(metadata, mod_name) {
+        write_depfile(&depfile_path, &dylib_path, metadata, &crate_root)?;
+    }
 
-fn default_cargo() -> PathBuf {
-    PathBuf::from(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
-}
-fn default_edition() -> String {
-    "2015".to_owned()
-}
-const fn return_true() -> bool {
-    true
+    if metadata.keep {
+        let kept_root = keep_inner_crate(&crate_root, &dylib_path)?;
+        eprintln!(
+            "inline-proc: a copy of the inner crate of `{}` is kept in {}",
+            mod_name,
+            kept_root.display()
+        );
+    }
+
+    if let Some(docs_index) = docs_index {
+        eprintln!(
+            "inline-proc: the documentation of `{}` is in {}",
+            mod_name,
+            docs_index.display()
+        );
+    }
+
+    if let Some(format) = timings_format {
+        Timings {
+            module: mod_name,
+            cold,
+            dependencies: dependencies_time,
+            check: check_time,
+            build: build_start.elapsed(),
+        }
+        .report(format);
+    }
+
+    if env_flag("INLINE_PROC_STATS") {
+        let outcome = if cold {
+            CacheOutcome::Cold
+        } else if fresh {
+            CacheOutcome::Hit
+        } else {
+            CacheOutcome::Rebuilt
+        };
+        report_cache_stats(mod_name, outcome, check_start.elapsed());
+    }
+
+    let dylib_path = dylib_path.into_os_string().into_string().map_err(|path| {
+        Error::Dylib(format!(
+            "Failed to convert path {} to string",
+            PathBuf::from(path).display()
+        ))
+    })?;
+
+    // The invoker prefers the dylib in `dist` over the usual one, so it's only told about `dist`
+    // when the host is one of the targets.
+    let dist_dir = targets
+        .contains(&HOST_TARGET)
+        .then(|| crate_root.join(DIST_DIR));
+    Ok(generate_user_macros(
+        metadata,
+        &dylib_path,
+        dist_dir.as_deref().map(|dir| (dir, &*crate_name)),
+    ))
 }
-fn default_inline_proc_path() -> DeserializePath {
-    DeserializePath(syn::parse2(quote!(::inline_proc)).unwrap())
+
+/// The `SOURCE_DATE_EPOCH` of the environment, in seconds since the Unix epoch, if it is set.
+fn source_date_epoch() -> Option<u64> {
+    env::var("SOURCE_DATE_EPOCH").ok()?.trim().parse().ok()
 }
 
-#[derive(Default, Deserialize)]
-#[serde(default)]
-struct Exports {
-    bang_macros: HashMap<DeserializeIdent, Macro>,
-    derives: HashMap<DeserializeIdent, Macro>,
-    attributes: HashMap<DeserializeIdent, Macro>,
+/// Copy the dylib built by Cargo into the crate root with its modification time set to
+/// `SOURCE_DATE_EPOCH`, or the Unix epoch if it isn't set, returning the path of the copy.
+///
+/// Cargo's own output keeps its modification time, since Cargo uses it to decide whether the
+/// crate needs to be rebuilt. The copy is only rewritten when the dylib changes.
+fn reproducible_dylib(dylib_path: &Path, crate_root: &Path) -> Result<PathBuf> {
+    let copy_path = crate_root.join(dylib_path.file_name().unwrap());
+    copy_if_changed(dylib_path, &copy_path)?;
+    let mtime = UNIX_EPOCH + Duration::from_secs(source_date_epoch().unwrap_or(0));
+    File::options()
+        .write(true)
+        .open(&copy_path)
+        .and_then(|file| file.set_modified(mtime))
+        .map_err(Error::io("set the modification time of the dylib"))?;
+    Ok(copy_path)
 }
 
-impl Exports {
-    fn all_macros(&self) -> impl Iterator<Item = (&Ident, &Macro, &'static str)> {
-        let add_macro_type = |macro_type| move |(name, mac)| (name, mac, macro_type);
+/// Where to write the depfile of the module, if anywhere. `INLINE_PROC_DEPFILE_DIR` takes
+/// precedence over `depfile`.
+fn depfile_path(metadata: &Metadata, mod_name: &str) -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("INLINE_PROC_DEPFILE_DIR").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir).join(format!(
+            "{}-{}.d",
+            CrateIdentifier,
+            short_name(mod_name)
+        )));
+    }
+    let path = metadata.depfile.as_ref()?;
+    Some(PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap_or_default()).join(path))
+}
 
-        self.bang_macros
+/// Write a depfile in the format of rustc's `--emit dep-info`, with the dylib as the target and the
+/// files it is built from as its dependencies: the file containing the module, the defaults file
+/// and the outputs of `pre_build`.
+fn write_depfile(
+    depfile_path: &Path,
+    dylib_path: &Path,
+    metadata: &Metadata,
+    crate_root: &Path,
+) -> Result<()> {
+    let mut dependencies = Vec::new();
+    // Paths from the compiler are relative to its working directory.
+    if let Some(file) = proc_macro::Span::call_site().local_file() {
+        dependencies.push(env::current_dir().unwrap_or_default().join(file));
+    }
+    dependencies.extend(metadata.defaults_file.clone());
+    dependencies.extend(
+        metadata
+            .pre_build_outputs
             .iter()
-            .map(add_macro_type("bang"))
-            .chain(self.derives.iter().map(add_macro_type("derive")))
-            .chain(self.attributes.iter().map(add_macro_type("attribute")))
-            .map(|(DeserializeIdent(ident), mac, macro_type)| (ident, mac, macro_type))
+            .map(|path| crate_root.join(path)),
+    );
+
+    let escape = |path: &Path| path.display().to_string().replace(' ', "\\ ");
+    let mut depfile = format!("{}:", escape(dylib_path));
+    for dependency in &dependencies {
+        write!(depfile, " {}", escape(dependency)).unwrap();
+    }
+    depfile.push('\n');
+    for dependency in &dependencies {
+        write!(depfile, "\n{}:\n", escape(dependency)).unwrap();
     }
-}
 
-#[derive(Deserialize)]
-#[serde(from = "MacroOptions")]
-struct Macro {
-    function: DeserializePath,
-    export: bool,
+    if let Some(parent) = depfile_path.parent() {
+        fs::create_dir_all(parent).map_err(Error::io("create the directory of the depfile"))?;
+    }
+    write_if_changed(depfile_path, &depfile).map_err(Error::io("write depfile"))
 }
 
-/// Support both `{ function = "function_name", export = true }` and shorthand `"function_name"`.
-#[derive(Deserialize)]
-#[serde(untagged)]
-enum MacroOptions {
-    Function(DeserializePath),
-    Full {
-        function: DeserializePath,
-        export: bool,
-    },
+/// Copy a dylib unless the copy is already up to date, creating the directory it is copied to.
+fn copy_if_changed(from: &Path, to: &Path) -> Result<()> {
+    let dylib = fs::read(from).map_err(Error::io("read dylib"))?;
+    if fs::read(to).ok().as_ref() == Some(&dylib) {
+        return Ok(());
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).map_err(Error::io("create dylib directory"))?;
+    }
+    fs::copy(from, to).map_err(Error::io("copy dylib"))?;
+    Ok(())
 }
 
-impl From<MacroOptions> for Macro {
-    fn from(options: MacroOptions) -> Self {
-        match options {
-            MacroOptions::Function(function) => Self {
-                function,
-                export: false,
-            },
-            MacroOptions::Full { function, export } => Self { function, export },
+/// Remove the directories in `dist` of targets that are no longer in `targets`, so that the
+/// dylibs left in them aren't mistaken for current ones.
+fn remove_unlisted_targets(dist_dir: &Path, targets: &[&str]) -> Result<()> {
+    let Ok(entries) = fs::read_dir(dist_dir) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let entry = entry.map_err(Error::io("read dist directory"))?;
+        if !targets.iter().any(|target| entry.file_name() == **target) {
+            fs::remove_dir_all(entry.path()).map_err(Error::io("remove old target dylib"))?;
         }
     }
+    Ok(())
 }
 
-#[derive(PartialEq, Eq, Hash)]
-struct DeserializeIdent(Ident);
-impl<'de> Deserialize<'de> for DeserializeIdent {
-    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-        struct IdentVisitor;
+/// Copy the sources of the inner crate and its dylib into `KEPT_DIR_NAME` in the temporary
+/// directory, where clearing or migrating the cache doesn't delete them, returning the copy's path.
+fn keep_inner_crate(crate_root: &Path, dylib_path: &Path) -> Result<PathBuf> {
+    fn copy_sources(from: &Path, to: &Path) -> io::Result<()> {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                if entry.file_name() != "target" {
+                    copy_sources(&path, &to.join(entry.file_name()))?;
+                }
+            } else {
+                fs::copy(&path, to.join(entry.file_name()))?;
+            }
+        }
+        Ok(())
+    }
+
+    let kept_root = TEMP_DIR
+        .join(KEPT_DIR_NAME)
+        .join(crate_root.file_name().unwrap());
+    copy_sources(crate_root, &kept_root).map_err(Error::io("copy inner crate"))?;
+    copy_if_changed(dylib_path, &kept_root.join(dylib_path.file_name().unwrap()))?;
+    Ok(kept_root)
+}
+
+/// The directory in the temporary directory that `#[inline_proc(keep)]` copies inner crates into.
+const KEPT_DIR_NAME: &str = "inline-proc-kept";
+
+/// The directory of the inner crate that the dylibs built for `targets` are copied into, with a
+/// directory for each target triple.
+pub(crate) const DIST_DIR: &str = "dist";
+
+/// The target triple of the compiler this crate is loaded into, which is the target the crate
+/// itself was compiled for.
+pub(crate) const HOST_TARGET: &str = env!("INLINE_PROC_HOST");
+
+/// Document the inner crate with `cargo doc`, passing it `rustdoc_flags`, and return the path of
+/// the documentation's front page.
+fn generate_docs(
+    metadata: &Metadata,
+    cargo_toml_path: &Path,
+    config_args: &[String],
+    target_dir: &Path,
+    crate_name: &str,
+) -> Result<PathBuf> {
+    let output = Command::new(&metadata.cargo)
+        .arg("doc")
+        .arg("--no-deps")
+        .arg("--lib")
+        .args(config_args)
+        .arg("--manifest-path")
+        .arg(cargo_toml_path)
+        .envs(metadata.rustc.as_ref().map(|rustc| ("RUSTC", rustc)))
+        // The encoded form keeps flags containing spaces intact.
+        .env(
+            "CARGO_ENCODED_RUSTDOCFLAGS",
+            metadata.rustdoc_flags.join("\x1f"),
+        )
+        .env_remove("RUSTDOCFLAGS")
+        // See `env_remove` in `build`.
+        .env_remove("RUST_RECURSION_COUNT")
+        .output()
+        .map_err(Error::cargo("launch Cargo doc"))?;
+    if !output.status.success() {
+        return Err(Error::Docs(format!(
+            "Failed to document the macro\n{}",
+            strip_control_codes(&String::from_utf8_lossy(&output.stderr)).trim_end()
+        ))
+        .at(metadata.spans.get(&["generate_docs"])));
+    }
+    Ok(target_dir.join("doc").join(crate_name).join("index.html"))
+}
+
+/// The program that builds the inner crate and the arguments before Cargo's, from
+/// `cargo_subcommand`. A subcommand given as a path is run in place of Cargo, relative to the
+/// package; otherwise `cargo` runs it, finding external subcommands like `zigbuild` on the `PATH`.
+fn build_program(metadata: &Metadata) -> (PathBuf, &[String]) {
+    match metadata.cargo_subcommand.split_first() {
+        Some((program, args)) if Path::new(program).components().count() > 1 => {
+            let manifest_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap_or_default());
+            (manifest_dir.join(program), args)
+        }
+        _ => (metadata.cargo.clone(), &metadata.cargo_subcommand),
+    }
+}
+
+/// Check that a build with a custom `cargo_subcommand` succeeded and produced a dylib that can be
+/// loaded, since a wrapper may put it elsewhere or build it for a platform the compiler can't load.
+fn check_custom_build(metadata: &Metadata, output: &Output, dylib_path: &Path) -> Result<()> {
+    let subcommand = metadata.cargo_subcommand.join(" ");
+    let error = if !output.status.success() {
+        format!(
+            "Failed to build the macro with `{}`\n{}",
+            subcommand,
+            strip_control_codes(&String::from_utf8_lossy(&output.stderr)).trim_end()
+        )
+    } else if !dylib_path.is_file() {
+        format!(
+            "`{}` didn't produce the dylib at {}",
+            subcommand,
+            dylib_path.display()
+        )
+    } else {
+        match unsafe { open_library(&dylib_path.to_string_lossy(), metadata.lazy_binding) } {
+            Ok(_) => return Ok(()),
+            Err(e) => format!(
+                "The dylib produced by `{}` can't be loaded: {}",
+                subcommand, e
+            ),
+        }
+    };
+    Err(Error::Dylib(error).at(metadata.spans.get(&["cargo_subcommand"])))
+}
+
+/// Resolve `host-tuple` in `targets` to the host's target triple, like Cargo's `--target`.
+fn resolve_target(target: &str) -> &str {
+    if target == "host-tuple" {
+        HOST_TARGET
+    } else {
+        target
+    }
+}
+
+/// How long to wait before the first retry of a failed Cargo invocation. This doubles with each
+/// retry.
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Parts of Cargo's errors that mean the failure is likely to be transient, like a network error
+/// or a file that another process has locked.
+const TRANSIENT_ERRORS: &[&str] = &[
+    "failed to download",
+    "failed to fetch",
+    "failed to update",
+    "failed to get",
+    "spurious network error",
+    "network failure",
+    "timed out",
+    "Connection reset",
+    "Connection refused",
+    "could not lock",
+    "failed to lock",
+    "failed to open",
+    "Resource temporarily unavailable",
+];
+
+/// Whether the errors Cargo printed look like a transient failure that is worth retrying.
+fn is_transient_failure(stderr: &str) -> bool {
+    let stderr = strip_control_codes(stderr);
+    TRANSIENT_ERRORS.iter().any(|error| stderr.contains(error))
+}
+
+/// Decide whether to retry a Cargo command that failed with the errors, after waiting for the
+/// backoff. Retries are only reported when Cargo's output is shown.
+fn retry(
+    metadata: &Metadata,
+    attempt: &mut u32,
+    stderr: &str,
+    action: &str,
+    mod_name: &str,
+) -> bool {
+    if *attempt >= metadata.build_retries || !is_transient_failure(stderr) {
+        return false;
+    }
+    *attempt += 1;
+    if !use_quiet(metadata) {
+        eprintln!(
+            "inline-proc: Cargo failed to {} `{}`, retrying ({} of {})",
+            action, mod_name, attempt, metadata.build_retries
+        );
+    }
+    thread::sleep(RETRY_BACKOFF * 2_u32.pow(*attempt - 1));
+    true
+}
+
+/// Write a file only if its contents changed, so that Cargo doesn't consider the crate dirty.
+fn write_if_changed(path: &Path, contents: &str) -> io::Result<()> {
+    if fs::read_to_string(path).is_ok_and(|old| old == contents) {
+        return Ok(());
+    }
+    fs::write(path, contents)
+}
+
+/// Run the `pre_build` command in the crate root, returning the hash of the declared outputs if
+/// there are any.
+fn run_pre_build(metadata: &Metadata, crate_root: &Path) -> Result<Option<u64>> {
+    let Some((program, args)) = metadata.pre_build.split_first() else {
+        return Ok(None);
+    };
+
+    let output = Command::new(program)
+        .args(args)
+        .current_dir(crate_root)
+        .env("INLINE_PROC_CRATE_DIR", crate_root)
+        .output()
+        .map_err(Error::io(format!("run pre-build command `{}`", program)))?;
+    if !output.status.success() {
+        let command_output = strip_control_codes(&format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr),
+        ));
+        return Err(Error::PreBuild(format!(
+            "Pre-build command `{}` failed with {}\n{}",
+            metadata.pre_build.join(" "),
+            output.status,
+            command_output.trim_end(),
+        )));
+    }
+
+    if metadata.pre_build_outputs.is_empty() {
+        return Ok(None);
+    }
+    let mut hasher = DefaultHasher::new();
+    for path in &metadata.pre_build_outputs {
+        let contents = fs::read(crate_root.join(path)).map_err(Error::io(format!(
+            "read pre-build output {}",
+            path.display()
+        )))?;
+        contents.hash(&mut hasher);
+    }
+    Ok(Some(hasher.finish()))
+}
+
+/// Whether to warn about macros that are never invoked, taking environment overrides into account.
+fn use_warn_unused(metadata: &Metadata) -> bool {
+    metadata.warn_unused || env_flag("INLINE_PROC_WARN_UNUSED")
+}
+
+/// Whether to check the code with Clippy, taking environment overrides into account.
+fn use_clippy(metadata: &Metadata) -> bool {
+    if env_flag("INLINE_PROC_NO_CLIPPY") {
+        false
+    } else if env_flag("INLINE_PROC_FORCE_CLIPPY") {
+        true
+    } else {
+        metadata.clippy
+    }
+}
+
+/// Check that the path dependencies of a module don't lead back to its own inner crate through
+/// the inner crates of other modules, which Cargo would report as an obscure cyclic dependency, or
+/// deadlock on when they share a workspace.
+///
+/// The dependencies of other modules are those in the `Cargo.toml` their inner crate was last
+/// written with.
+fn check_dependency_cycle(mod_name: &str, crate_root: &Path, metadata: &Metadata) -> Result<()> {
+    let dependencies = path_dependencies(crate_root, metadata);
+    let own_dependencies: Vec<PathBuf> =
+        dependencies.iter().map(|(_, path)| path.clone()).collect();
+
+    let mut cycle = vec![crate_root.to_owned()];
+    if !find_dependency_cycle(&own_dependencies, &mut cycle, &mut HashSet::new()) {
+        return Ok(());
+    }
+
+    let names: Vec<String> = cycle
+        .iter()
+        .map(|path| {
+            if path == crate_root {
+                return format!("`{}`", mod_name);
+            }
+            // Inner crates are named `{package}-{version}-{module}`, and those of shared
+            // workspaces after their module.
+            let dir_name = path.file_name().unwrap_or_default().to_string_lossy();
+            let module = match dir_name.rsplit_once('-') {
+                Some((_, module)) if !dir_name.starts_with(SHARED_CACHE_PREFIX) => module,
+                _ => &dir_name,
+            };
+            format!("`{}`", module)
+        })
+        .collect();
+    let first_dependency = dependencies
+        .iter()
+        .find(|(_, path)| *path == cycle[1])
+        .map(|(name, _)| *name)
+        .unwrap_or_default();
+    Err(Error::Dependency(format!(
+        "Module `{}` depends on itself through the path dependencies of inline modules: {}",
+        mod_name,
+        names.join(" -> ")
+    ))
+    .at(metadata
+        .spans
+        .get(&["dependencies", first_dependency, "path"])))
+}
+
+/// Whether `allowed_dependencies` and `denied_dependencies` allow depending on the package.
+fn dependency_allowed(metadata: &Metadata, package: &str) -> bool {
+    !metadata
+        .denied_dependencies
+        .iter()
+        .any(|denied| denied == package)
+        && metadata
+            .allowed_dependencies
+            .as_ref()
+            .is_none_or(|allowed| allowed.iter().any(|allowed| allowed == package))
+}
+
+/// Check the direct dependencies of a module against `allowed_dependencies` and
+/// `denied_dependencies`, by the name of the package they refer to. The inner crates of other
+/// modules are always allowed.
+fn check_dependency_policy(mod_name: &str, metadata: &Metadata) -> Result<()> {
+    let disallowed: Vec<&str> = metadata
+        .dependencies
+        .iter()
+        .filter(|(name, dep)| {
+            let package = dep.package().unwrap_or(name);
+            package != "inline-proc-macro"
+                && !package.starts_with("inline-proc-macro-")
+                && !dependency_allowed(metadata, package)
+        })
+        .map(|(name, _)| &**name)
+        .collect();
+    let Some(first) = disallowed.first() else {
+        return Ok(());
+    };
+    let list: Vec<String> = disallowed
+        .iter()
+        .map(|name| format!("`{}`", name))
+        .collect();
+    Err(Error::Dependency(format!(
+        "Module `{}` depends on crates that aren't allowed: {}",
+        mod_name,
+        list.join(", ")
+    ))
+    .at(metadata.spans.get(&["dependencies", first])))
+}
+
+/// Check every package the inner crate depends on, directly or not, against
+/// `allowed_dependencies` and `denied_dependencies`, as resolved by `cargo metadata`.
+fn check_transitive_dependency_policy(
+    mod_name: &str,
+    metadata: &Metadata,
+    cargo_toml_path: &Path,
+    config_args: &[String],
+) -> Result<()> {
+    let mut command = cargo_metadata::MetadataCommand::new()
+        .cargo_path(&metadata.cargo)
+        .manifest_path(cargo_toml_path)
+        .other_options(config_args.to_vec())
+        .cargo_command();
+    // See `env_remove` in `build`.
+    let output = command
+        .env_remove("RUST_RECURSION_COUNT")
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(Error::cargo("launch Cargo metadata"))?;
+    if !output.status.success() {
+        return Err(Error::Cargo {
+            action: "resolve the dependencies of the inline proc macro",
+            error: None,
+        });
+    }
+    let resolved = cargo_metadata::MetadataCommand::parse(String::from_utf8_lossy(&output.stdout))
+        .map_err(|e| {
+            Error::Dependency(format!(
+                "Failed to read the output of Cargo metadata: {}",
+                e
+            ))
+        })?;
+
+    let packages: HashMap<_, _> = resolved.packages.iter().map(|p| (&p.id, p)).collect();
+    let nodes: HashMap<_, _> = resolved
+        .resolve
+        .iter()
+        .flat_map(|resolve| &resolve.nodes)
+        .map(|node| (&node.id, node))
+        .collect();
+    // Without the root, nothing could be checked, so fail rather than let everything through.
+    let Some(root) = resolved
+        .resolve
+        .as_ref()
+        .and_then(|resolve| resolve.root.as_ref())
+    else {
+        return Err(Error::Dependency(
+            "Cargo metadata did not report the inline proc crate, so its dependencies can't be \
+             checked against `allowed_dependencies` and `denied_dependencies`"
+                .to_owned(),
+        ));
+    };
+
+    // Walk the dependency graph from the inner crate, remembering which package first required
+    // each one. The crates of other inline modules are checked when they are built themselves.
+    let mut required_by = HashMap::new();
+    let mut queue = vec![root];
+    let mut disallowed = Vec::new();
+    while let Some(id) = queue.pop() {
+        for dep in nodes.get(id).map_or(&[][..], |node| &node.deps) {
+            let Some(package) = packages.get(&dep.pkg) else {
+                continue;
+            };
+            if required_by.contains_key(&dep.pkg) || package.manifest_path.starts_with(&*CRATES_DIR)
+            {
+                continue;
+            }
+            required_by.insert(&dep.pkg, id);
+            if !dependency_allowed(metadata, &package.name) {
+                disallowed.push(match packages.get(id) {
+                    Some(parent) if id != root => {
+                        format!("`{}` (required by `{}`)", package.name, parent.name)
+                    }
+                    _ => format!("`{}`", package.name),
+                });
+            }
+            queue.push(&dep.pkg);
+        }
+    }
+    if disallowed.is_empty() {
+        return Ok(());
+    }
+    disallowed.sort();
+    Err(Error::Dependency(format!(
+        "Module `{}` depends on crates that aren't allowed: {}",
+        mod_name,
+        disallowed.join(", ")
+    ))
+    .at(metadata.spans.get(&["check_transitive_dependencies"])))
+}
+
+/// The names and normalized paths of the path dependencies of a module's inner crate.
+fn path_dependencies<'a>(crate_root: &Path, metadata: &'a Metadata) -> Vec<(&'a str, PathBuf)> {
+    metadata
+        .dependencies
+        .iter()
+        .filter_map(|(name, dep)| match dep {
+            CargoDependency::Detailed(detail) => Some((
+                &**name,
+                normalize_path(&crate_root.join(detail.path.as_ref()?)),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Check that the path dependencies of a module, followed through their own `Cargo.toml`s, don't
+/// lead to the package the module is in. Building the inner crate would then build the package,
+/// which expands the module again, so Cargo would fail with a cyclic dependency or never finish.
+fn check_host_dependency(mod_name: &str, crate_root: &Path, metadata: &Metadata) -> Result<()> {
+    let Some(host_root) = env::var_os("CARGO_MANIFEST_DIR") else {
+        return Ok(());
+    };
+    let host_root = normalize_path(Path::new(&host_root));
+
+    for (name, path) in path_dependencies(crate_root, metadata) {
+        let mut chain = vec![path];
+        if !find_host_dependency(&host_root, &mut chain, &mut HashSet::new()) {
+            continue;
+        }
+        let host_package = env::var("CARGO_PKG_NAME").unwrap_or_default();
+        let mut names = vec![format!("`{}`", name)];
+        names.extend(chain[1..chain.len() - 1].iter().map(|path| {
+            format!(
+                "`{}`",
+                path.file_name().unwrap_or_default().to_string_lossy()
+            )
+        }));
+        names.push(format!("`{}`", host_package));
+        return Err(Error::Dependency(format!(
+            "Module `{}` depends on the package it is in, `{}`, through its path dependencies: \
+             {}. The macro is built before the package, so it can't use it; move the code both \
+             need into a crate of its own",
+            mod_name,
+            host_package,
+            names.join(" -> ")
+        ))
+        .at(metadata.spans.get(&["dependencies", name])));
+    }
+    Ok(())
+}
+
+/// Search the path dependencies of the last crate of `chain` for the host package, adding the
+/// crates on the way to `chain`.
+fn find_host_dependency(
+    host_root: &Path,
+    chain: &mut Vec<PathBuf>,
+    visited: &mut HashSet<PathBuf>,
+) -> bool {
+    let current = chain.last().unwrap().clone();
+    if current == host_root {
+        return true;
+    }
+    if !visited.insert(current.clone()) {
+        return false;
+    }
+    for dependency in manifest_path_dependencies(&current) {
+        chain.push(dependency);
+        if find_host_dependency(host_root, chain, visited) {
+            return true;
+        }
+        chain.pop();
+    }
+    false
+}
+
+/// Search the path dependencies of the last crate of `cycle` for the first crate, adding the
+/// crates on the way to `cycle`. Only inner crates are searched, since other crates can't depend
+/// on them.
+fn find_dependency_cycle(
+    own_dependencies: &[PathBuf],
+    cycle: &mut Vec<PathBuf>,
+    visited: &mut HashSet<PathBuf>,
+) -> bool {
+    let current = cycle.last().unwrap();
+    let dependencies = if cycle.len() == 1 {
+        own_dependencies.to_vec()
+    } else {
+        manifest_path_dependencies(current)
+    };
+    for dependency in dependencies {
+        if !dependency.starts_with(&*CRATES_DIR) {
+            continue;
+        }
+        if dependency == cycle[0] {
+            cycle.push(dependency);
+            return true;
+        }
+        if visited.insert(dependency.clone()) {
+            cycle.push(dependency);
+            if find_dependency_cycle(own_dependencies, cycle, visited) {
+                return true;
+            }
+            cycle.pop();
+        }
+    }
+    false
+}
+
+/// The path dependencies in the `Cargo.toml` of a crate, such as an inner crate if it has been
+/// written.
+fn manifest_path_dependencies(crate_root: &Path) -> Vec<PathBuf> {
+    let Some(manifest) = fs::read_to_string(crate_root.join("Cargo.toml"))
+        .ok()
+        .and_then(|manifest| manifest.parse::<toml::Table>().ok())
+    else {
+        return Vec::new();
+    };
+    let Some(dependencies) = manifest
+        .get("dependencies")
+        .and_then(|deps| deps.as_table())
+    else {
+        return Vec::new();
+    };
+    dependencies
+        .values()
+        .filter_map(|dep| dep.get("path")?.as_str())
+        .map(|path| normalize_path(&crate_root.join(path)))
+        .collect()
+}
+
+/// Resolve `.` and `..` in a path without touching the filesystem, since the crates it points to
+/// may not exist yet.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Delete the contents of `CRATES_DIR` if they were created by an incompatible version of this
+/// crate, as recorded by a marker file. Returns a shared lock on `CRATES_DIR`, which the compiler
+/// holds for as long as it runs, so that no other compiler deletes the inner crates it uses.
+fn check_cache_layout() -> Result<File> {
+    fs::create_dir_all(&*CRATES_DIR).map_err(Error::io("create crates directory"))?;
+
+    // The lock is outside of `CRATES_DIR` so that it isn't deleted while held.
+    let lock = File::create(TEMP_DIR.join("inline-proc-crates.lock"))
+        .map_err(Error::io("create crates directory lock"))?;
+    lock.lock_shared()
+        .map_err(Error::io("lock crates directory"))?;
+
+    let marker_path = CRATES_DIR.join(".layout-version");
+    let current_version = || {
+        fs::read_to_string(&marker_path)
+            .ok()
+            .and_then(|version| version.trim().parse::<u32>().ok())
+    };
+    if current_version() == Some(CACHE_LAYOUT_VERSION) {
+        return Ok(lock);
+    }
+
+    // Wait until the compilers using the old crates are done, and check again in case another
+    // compiler deleted them in the meantime.
+    lock.unlock()
+        .map_err(Error::io("unlock crates directory"))?;
+    lock.lock().map_err(Error::io("lock crates directory"))?;
+    if current_version() != Some(CACHE_LAYOUT_VERSION) {
+        delete_crates()?;
+        fs::write(&marker_path, CACHE_LAYOUT_VERSION.to_string())
+            .map_err(Error::io("write cache layout version"))?;
+    }
+    lock.unlock()
+        .map_err(Error::io("unlock crates directory"))?;
+    lock.lock_shared()
+        .map_err(Error::io("lock crates directory"))?;
+    Ok(lock)
+}
+
+/// Delete everything in `CRATES_DIR`.
+fn delete_crates() -> Result<()> {
+    let entries = fs::read_dir(&*CRATES_DIR).map_err(Error::io("read crates directory"))?;
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let result = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        result.map_err(Error::io(format!("remove old crate {}", path.display())))?;
+    }
+    Ok(())
+}
+
+/// Regenerate the manifest of a shared workspace so that it lists every inner crate in it.
+fn update_shared_workspace(workspace_root: &Path) -> Result<()> {
+    // Several modules may be expanded in parallel, so serialize updates to the manifest.
+    let lock =
+        File::create(workspace_root.join(".lock")).map_err(Error::io("create workspace lock"))?;
+    lock.lock().map_err(Error::io("lock workspace"))?;
+
+    let mut members: Vec<String> = fs::read_dir(workspace_root)
+        .map_err(Error::io("read workspace directory"))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().join("Cargo.toml").is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .map(|member| format!("'{}'", member))
+        .collect();
+    members.sort();
+
+    let manifest = format!(
+        "workspace={{members=[{}],resolver='2'}}\n",
+        members.join(",")
+    );
+    let manifest_path = workspace_root.join("Cargo.toml");
+    if fs::read_to_string(&manifest_path).is_ok_and(|old| old == manifest) {
+        return Ok(());
+    }
+    // The Cargo processes of other modules may be reading the manifest, so it is replaced as a
+    // whole instead of being rewritten in place.
+    let temp_path = workspace_root.join("Cargo.toml.tmp");
+    fs::write(&temp_path, manifest).map_err(Error::io("write workspace Cargo.toml"))?;
+    fs::rename(&temp_path, &manifest_path).map_err(Error::io("replace workspace Cargo.toml"))
+}
+
+/// The `--config` arguments to pass to Cargo for the metadata's `cargo_config`.
+fn cargo_config_args(metadata: &Metadata) -> Result<Vec<String>> {
+    // The panic strategy comes first so that `cargo_config` can still override it, but not the
+    // host's Cargo config or `CARGO_PROFILE_DEV_PANIC`, which are inherited by the inner crate.
+    let mut args = vec![
+        "--config".to_owned(),
+        format!(
+            "profile.dev.panic={}",
+            toml::Value::String(metadata.panic.as_str().to_owned())
+        ),
+    ];
+    for (key, value) in &metadata.cargo_config {
+        let valid_key = key.split('.').all(|segment| {
+            !segment.is_empty()
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        });
+        if !valid_key {
+            return Err(
+                Error::Metadata(format!("Invalid Cargo config key `{}`", key))
+                    .at(metadata.spans.get(&["cargo_config", key])),
+            );
+        }
+        if let toml::Value::Table(_) = value {
+            return Err(Error::Metadata(format!(
+                "Cargo config `{}` must be a value, not a table; set its keys individually",
+                key
+            ))
+            .at(metadata.spans.get(&["cargo_config", key])));
+        }
+        args.push("--config".to_owned());
+        args.push(format!("{}={}", key, value));
+    }
+    Ok(args)
+}
+
+/// The number of jobs each Cargo invocation runs in parallel, taking environment overrides into
+/// account. `None` uses Cargo's default.
+fn jobs(metadata: &Metadata) -> Option<NonZeroU32> {
+    env::var("INLINE_PROC_JOBS")
+        .ok()
+        .and_then(|jobs| jobs.parse().ok())
+        .or(metadata.jobs)
+}
+
+/// The `RUSTC_WRAPPER` to run Cargo with. Relative paths are relative to the host package, while
+/// bare names such as `sccache` are left for Cargo to look up in `PATH`. An empty path disables
+/// the wrapper.
+fn rustc_wrapper(metadata: &Metadata) -> Option<PathBuf> {
+    let wrapper = metadata.rustc_wrapper.as_ref()?;
+    if wrapper.is_relative() && wrapper.components().count() > 1 {
+        if let Some(manifest_dir) = env::var_os("CARGO_MANIFEST_DIR") {
+            return Some(Path::new(&manifest_dir).join(wrapper));
+        }
+    }
+    Some(wrapper.clone())
+}
+
+/// Whether to pass `--color=always` to Cargo, following the `NO_COLOR` convention: any non-empty
+/// value disables color, unless `FORCE_COLOR` is also set.
+fn use_color(metadata: &Metadata) -> bool {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    metadata.color && (!no_color || env_flag("FORCE_COLOR"))
+}
+
+/// Whether to pass `--quiet` to Cargo, taking environment overrides into account.
+fn use_quiet(metadata: &Metadata) -> bool {
+    metadata.quiet && !env_flag("INLINE_PROC_VERBOSE")
+}
+
+/// How to display the diagnostics, taking environment overrides into account.
+fn diagnostics_style(metadata: &Metadata) -> DiagnosticsStyle {
+    match env::var("INLINE_PROC_DIAGNOSTICS").as_deref() {
+        Ok("short") => DiagnosticsStyle::Short,
+        Ok("full") => DiagnosticsStyle::Full,
+        _ => metadata.diagnostics,
+    }
+}
+
+/// The longest package or module name used as is in the paths of the inner crates.
+const MAX_NAME_LEN: usize = 24;
+
+/// Shorten a package or module name longer than `MAX_NAME_LEN` to its start and a hash of the
+/// whole name. The paths in the target directory of an inner crate with a deep dependency tree
+/// can otherwise exceed the 260 character limit of Windows, failing the build. The result
+/// contains no `-` that the name didn't, so the cache API can still split it, and the hash is
+/// stable, so the paths don't change with the version of Rust.
+fn short_name(name: &str) -> Cow<'_, str> {
+    if name.len() <= MAX_NAME_LEN {
+        return Cow::Borrowed(name);
+    }
+    let hash = format!("{:08x}", stable_hash(name.as_bytes()) as u32);
+    let prefix_len = MAX_NAME_LEN - hash.len() - 1;
+    let prefix_end = name
+        .char_indices()
+        .map(|(i, c)| i + c.len_utf8())
+        .take_while(|&end| end <= prefix_len)
+        .last()
+        .unwrap_or(0);
+    Cow::Owned(format!("{}_{}", &name[..prefix_end], hash))
+}
+
+struct CrateIdentifier;
+impl Display for CrateIdentifier {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = env::var("CARGO_PKG_NAME").unwrap();
+        let name = short_name(&name);
+        let major_version: u64 = env::var("CARGO_PKG_VERSION_MAJOR")
+            .unwrap()
+            .parse()
+            .unwrap();
+        write!(f, "{}-{}", name, major_version)?;
+        if major_version == 0 {
+            write!(
+                f,
+                ".{}",
+                env::var("CARGO_PKG_VERSION_MINOR")
+                    .unwrap()
+                    .parse::<u64>()
+                    .unwrap()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse the input module. Returns the module name, metadata, and content tokens.
+///
+// If no features are enabled then it will produce unnecessary warnings
+#[cfg_attr(
+    not(any(feature = "json", feature = "ron")),
+    allow(unreachable_code, unused_variables, clippy::match_single_binding)
+)]
+fn parse_mod(module: ItemMod) -> (String, Metadata, TokenStream) {
+    if !matches!(module.vis, Visibility::Inherited) {
+        abort!(
+            module.vis,
+            "Inline proc module must not have a visibility modifier"
+        );
+    }
+
+    let (braces, mut module_content) = match module.content {
+        Some(content) => content,
+        None => abort!(module.semi.unwrap(), "Module must contain content"),
+    };
+
+    if module_content.is_empty() {
+        abort!(braces.span.span(), "Missing metadata information");
+    }
+
+    let mut metadata = parse_metadata(module_content.remove(0), module.mod_token.span);
+
+    expand_registrations(&mut metadata, &mut module_content);
+    expand_native_macros(&mut metadata, &mut module_content);
+    check_reserved_names(&metadata, &module_content);
+    check_return_types(&metadata, &module_content);
+
+    #[allow(unreachable_code)]
+    let content = {
+        let mut content = TokenStream::new();
+        for attr in module.attrs {
+            if let AttrStyle::Inner(_) = attr.style {
+                attr.to_tokens(&mut content);
+            }
+        }
+        for item in module_content {
+            item.to_tokens(&mut content);
+        }
+        content
+    };
+
+    (sanitize_module_name(&module.ident), metadata, content)
+}
+
+/// Replace the `export_bang!`, `export_derive!` and `export_attribute!` registrations in a module
+/// with the functions they define, and add those functions to the exports.
+///
+/// Like `metadata`, `export_*` isn't a real path, so anything before it is ignored.
+fn expand_registrations(metadata: &mut Metadata, items: &mut [Item]) {
+    for item in items {
+        let Item::Macro(ItemMacro {
+            attrs,
+            ident: None,
+            mac,
+            ..
+        }) = item
+        else {
+            continue;
+        };
+        let Some(last) = mac.path.segments.last() else {
+            continue;
+        };
+        let (exports, args) = match &*last.ident.to_string() {
+            "export_bang" => (&mut metadata.exports.bang_macros, vec!["input"]),
+            "export_derive" => (&mut metadata.exports.derives, vec!["item"]),
+            "export_attribute" => (&mut metadata.exports.attributes, vec!["attr", "item"]),
+            _ => continue,
+        };
+        let args: Vec<Ident> = args
+            .into_iter()
+            .map(|arg| format_ident!("{}", arg))
+            .collect();
+
+        let Registration { name, function } = mac
+            .parse_body()
+            .unwrap_or_else(|e| abort!(e.span(), "{}", e));
+        let proc_macro2 = uses_proc_macro2(&function);
+        let token_stream = if proc_macro2 {
+            quote!(::proc_macro2::TokenStream)
+        } else {
+            quote!(::proc_macro::TokenStream)
+        };
+        let types = args.iter().map(|_| &token_stream);
+        if exports
+            .keys()
+            .any(|DeserializeIdent(export)| *export == name)
+        {
+            abort!(
+                name,
+                "Macro `{}` is registered here, but it is also in the `exports` of the metadata",
+                name
+            );
+        }
+
+        // Coercing the closure to a function pointer gives its parameters their types.
+        *item = syn::parse_quote! {
+            #(#attrs)*
+            #[allow(non_snake_case)]
+            pub fn #name(#(#args: #token_stream),*) -> #token_stream {
+                let function: fn(#(#types),*) -> #token_stream = #function;
+                function(#(#args),*)
+            }
+        };
+        let mut export = Macro::from(MacroOptions::Function(DeserializePath(name.clone().into())));
+        export.proc_macro2 = proc_macro2;
+        exports.insert(DeserializeIdent(name), export);
+    }
+}
+
+/// Whether the function of a registration is a closure whose first parameter is annotated with a
+/// type from `proc_macro2`, such as `|input: proc_macro2::TokenStream| ...`.
+fn uses_proc_macro2(function: &syn::Expr) -> bool {
+    let syn::Expr::Closure(closure) = function else {
+        return false;
+    };
+    let Some(syn::Pat::Type(parameter)) = closure.inputs.first() else {
+        return false;
+    };
+    let syn::Type::Path(path) = &*parameter.ty else {
+        return false;
+    };
+    path.path
+        .segments
+        .first()
+        .is_some_and(|segment| segment.ident == "proc_macro2")
+}
+
+/// Export the functions marked with `#[proc_macro]`, `#[proc_macro_derive(Name)]` or
+/// `#[proc_macro_attribute]` in a module, named like they would be in a proc macro crate, and remove
+/// the attributes, which only work in proc macro crates.
+fn expand_native_macros(metadata: &mut Metadata, items: &mut [Item]) {
+    for item in items {
+        let Item::Fn(function) = item else {
+            continue;
+        };
+        let Some(index) = function.attrs.iter().position(|attr| {
+            NATIVE_MACRO_ATTRIBUTES
+                .iter()
+                .any(|native| attr.path().is_ident(native))
+        }) else {
+            continue;
+        };
+        let attr = function.attrs.remove(index);
+        let function_name = function.sig.ident.clone();
+
+        let (exports, name) = if attr.path().is_ident("proc_macro") {
+            (&mut metadata.exports.bang_macros, function_name.clone())
+        } else if attr.path().is_ident("proc_macro_attribute") {
+            (&mut metadata.exports.attributes, function_name.clone())
+        } else {
+            // Helper attributes are accepted for parity, but the item isn't output again by a
+            // derive so there is nothing to remove them from.
+            let name = attr
+                .parse_args_with(|input: syn::parse::ParseStream| {
+                    let name = input.call(Ident::parse_any)?;
+                    input.parse::<TokenStream>()?;
+                    Ok(name)
+                })
+                .unwrap_or_else(|e| abort!(e.span(), "{}", e));
+            (&mut metadata.exports.derives, name)
+        };
+        if exports
+            .keys()
+            .any(|DeserializeIdent(export)| *export == name)
+        {
+            abort!(
+                attr,
+                "Macro `{}` is defined here, but it is also in the `exports` of the metadata",
+                name
+            );
+        }
+        exports.insert(
+            DeserializeIdent(name),
+            Macro::from(MacroOptions::Function(DeserializePath(
+                function_name.into(),
+            ))),
+        );
+    }
+}
+
+/// The attributes that define macros in a proc macro crate.
+const NATIVE_MACRO_ATTRIBUTES: &[&str] =
+    &["proc_macro", "proc_macro_derive", "proc_macro_attribute"];
+
+/// The input of `export_bang!(name, |input| ...)` and the other registration macros: the name of
+/// the macro, as an identifier or string, and its function.
+struct Registration {
+    name: Ident,
+    function: syn::Expr,
+}
+
+impl syn::parse::Parse for Registration {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let name = if input.peek(syn::LitStr) {
+            input.parse::<syn::LitStr>()?.parse()?
+        } else {
+            input.call(Ident::parse_any)?
+        };
+        input.parse::<syn::Token![,]>()?;
+        let function = input.parse()?;
+        input.parse::<Option<syn::Token![,]>>()?;
+        Ok(Self { name, function })
+    }
+}
+
+/// Parse the `metadata::format!(...)` item at the start of an inline module.
+// Without any formats every path aborts.
+#[cfg_attr(
+    not(any(feature = "json", feature = "ron")),
+    allow(unreachable_code, unused_variables)
+)]
+fn parse_metadata(item: Item, missing_span: Span) -> Metadata {
+    let (metadata_format, metadata_source, metadata_spans, defaults_file) = match item {
+        Item::Macro(ItemMacro {
+            ident: None, mac, ..
+        }) if metadata_segment_index(&mac.path).is_some() => {
+            // `metadata` isn't a real path, so anything before it (such as the path `inline_proc`
+            // was imported as) is ignored.
+            let index = metadata_segment_index(&mac.path).unwrap();
+            let mut segments = mac.path.segments.into_iter().skip(index);
+            let metadata_segment = segments.next().unwrap();
+            let format = match segments.next() {
+                Some(segment) if segment.arguments.is_empty() => segment.ident,
+                Some(segment) => abort!(segment.arguments, "Extra arguments in metadata format"),
+                None => abort!(metadata_segment, "Missing metadata format"),
+            };
+
+            let (group_span, delimiter) = match mac.delimiter {
+                MacroDelimiter::Paren(paren) => (paren.span, Delimiter::Parenthesis),
+                MacroDelimiter::Brace(brace) => (brace.span, Delimiter::Brace),
+                MacroDelimiter::Bracket(bracket) => (bracket.span, Delimiter::Bracket),
+            };
+            let (tokens, defaults_file) = apply_defaults(mac.tokens);
+            let metadata_spans = MetadataSpans::new(tokens.clone());
+            let mut group = Group::new(delimiter, tokens);
+            group.set_span(group_span.span());
+
+            (
+                format,
+                TokenString::from_token(group),
+                metadata_spans,
+                defaults_file,
+            )
+        }
+        _ => abort!(missing_span, "Missing metadata information"),
+    };
+
+    let mut metadata: Metadata = match metadata_format {
+        #[cfg(feature = "json")]
+        format if format == "json" => serde_json::from_str(&metadata_source.tokens)
+            .unwrap_or_else(|e| abort!(metadata_source.char_span(e.column().saturating_sub(1)), e)),
+        #[cfg(feature = "ron")]
+        format if format == "ron" => ron::Options::default()
+            // Allow optional values to be written without `Some(...)`
+            .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+            .from_str(&metadata_source.tokens)
+            .unwrap_or_else(|e| abort!(metadata_source.char_span(e.position.col.saturating_sub(1)), e)),
+        format => Diagnostic::spanned(
+            format.span(),
+            Level::Error,
+            format!("Unknown format {}", format),
+        )
+        .help({
+            const SUPPORTED_FORMATS: &[&str] = &[
+               #[cfg(feature = "json")]
+               "json",
+               #[cfg(feature = "ron")]
+               "ron",
+            ];
+            if SUPPORTED_FORMATS.is_empty() {
+                "There are no supported formats. Try enabling the `json` or `ron` features of this crate.".to_owned()
+            } else {
+                format!("Supported formats are: {}", SUPPORTED_FORMATS.join(", "))
+            }
+        })
+        .abort(),
+    };
+
+    metadata.spans = metadata_spans;
+    metadata.defaults_file = defaults_file;
+    metadata
+}
+
+/// Find the `metadata` segment of the path of a `metadata::format!(...)` item. It is the
+/// second-to-last segment, or the last if the format is missing.
+fn metadata_segment_index(path: &RustPath) -> Option<usize> {
+    let is_metadata = |seg: &PathSegment| seg.ident == "metadata" && seg.arguments.is_empty();
+    let len = path.segments.len();
+    (len.saturating_sub(2)..len).find(|&i| is_metadata(&path.segments[i]))
+}
+
+/// The spans of the keys written in the metadata, by their path from the top level, so that errors
+/// in the metadata can point at the option that caused them. The elements of lists are keyed by
+/// their index.
+#[derive(Default)]
+struct MetadataSpans(BTreeMap<Vec<String>, Span>);
+
+impl MetadataSpans {
+    /// Find the keys in the metadata tokens, in both RON and JSON.
+    fn new(metadata: TokenStream) -> Self {
+        let mut spans = Self::default();
+        spans.add_keys(&mut Vec::new(), metadata);
+        spans
+    }
+
+    fn add_keys(&mut self, path: &mut Vec<String>, tokens: TokenStream) {
+        let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+
+        // The keys may be wrapped in a JSON object or RON struct.
+        if let [TokenTree::Group(group)] = &*tokens {
+            match group.delimiter() {
+                Delimiter::Brace | Delimiter::Parenthesis => {
+                    return self.add_keys(path, group.stream());
+                }
+                Delimiter::Bracket => return self.add_elements(path, group.stream()),
+                Delimiter::None => {}
+            }
+        }
+
+        for entry in
+            tokens.split(|token| matches!(token, TokenTree::Punct(punct) if punct.as_char() == ','))
+        {
+            let Some((key, span)) = entry_key(entry) else {
+                continue;
+            };
+            path.push(key);
+            self.0.insert(path.clone(), span);
+            self.add_value(path, &entry[2..]);
+            path.pop();
+        }
+    }
+
+    fn add_elements(&mut self, path: &mut Vec<String>, tokens: TokenStream) {
+        let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+        let elements = tokens
+            .split(|token| matches!(token, TokenTree::Punct(punct) if punct.as_char() == ','))
+            .filter(|element| !element.is_empty());
+        for (i, element) in elements.enumerate() {
+            path.push(i.to_string());
+            self.0.insert(path.clone(), element[0].span());
+            self.add_value(path, element);
+            path.pop();
+        }
+    }
+
+    /// Add the keys of a value, which may be a map, a list, or a RON struct with its name.
+    fn add_value(&mut self, path: &mut Vec<String>, value: &[TokenTree]) {
+        match value {
+            [TokenTree::Group(group)] | [TokenTree::Ident(_), TokenTree::Group(group)] => {
+                self.add_keys(path, TokenStream::from(TokenTree::Group(group.clone())));
+            }
+            _ => {}
+        }
+    }
+
+    /// Get the span of the key at the given path, or if it wasn't written out (for example because
+    /// it has a default) of its closest parent that was.
+    fn get(&self, path: &[&str]) -> Option<Span> {
+        (1..=path.len()).rev().find_map(|len| {
+            let key: Vec<String> = path[..len].iter().map(|&s| s.to_owned()).collect();
+            self.0.get(&key).copied()
+        })
+    }
+
+    /// Get the span of an option of an exported macro, of the given macro type.
+    fn get_macro(&self, name: &Ident, macro_type: &str, option: &str) -> Option<Span> {
+        let exports_key = match macro_type {
+            "bang" => "bang_macros",
+            "derive" => "derives",
+            _ => "attributes",
+        };
+        self.get(&["exports", exports_key, &name.to_string(), option])
+    }
+}
+
+/// The key of an entry of the metadata, written as `key: value` in RON or `"key": value` in either
+/// format.
+fn entry_key(entry: &[TokenTree]) -> Option<(String, Span)> {
+    match entry {
+        [TokenTree::Ident(key), TokenTree::Punct(colon), ..] if colon.as_char() == ':' => {
+            Some((key.unraw().to_string(), key.span()))
+        }
+        [TokenTree::Literal(key), TokenTree::Punct(colon), ..] if colon.as_char() == ':' => {
+            syn::parse2::<syn::LitStr>(key.to_token_stream())
+                .ok()
+                .map(|key| (key.value(), key.span()))
+        }
+        _ => None,
+    }
+}
+
+/// Merge the file named by the module's `defaults` option into its metadata. Returns the merged
+/// metadata and the path of the defaults file, if there is one.
+fn apply_defaults(metadata: TokenStream) -> (TokenStream, Option<PathBuf>) {
+    let (wrapper, mut entries) = split_entries(metadata.clone());
+    let Some(i) = entries
+        .iter()
+        .position(|entry| entry_key(entry).is_some_and(|(key, _)| key == "defaults"))
+    else {
+        return (metadata, None);
+    };
+    let entry = entries.remove(i);
+    let path = match &entry[2..] {
+        [TokenTree::Literal(path)] => syn::parse2::<syn::LitStr>(path.to_token_stream()).ok(),
+        _ => None,
+    };
+    let Some(path) = path else {
+        abort!(entry[0], "`defaults` must be the path of a file");
+    };
+    let manifest_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap_or_default());
+    let full_path = manifest_dir.join(path.value());
+    if !full_path.is_file() {
+        abort!(
+            path,
+            "The defaults file {} doesn't exist",
+            full_path.display()
+        );
+    }
+
+    let defaults = fs::read_to_string(&full_path)
+        .unwrap_or_else(|e| abort!(path, "Failed to read {}: {}", full_path.display(), e));
+    let defaults = defaults.parse::<TokenStream>().unwrap_or_else(|e| {
+        abort!(
+            path,
+            "Failed to parse the defaults file {}: {}",
+            full_path.display(),
+            e
+        )
+    });
+    // The exports are the module's own, since their functions are in its code.
+    let (_, default_entries) = split_entries(defaults.clone());
+    if default_entries
+        .iter()
+        .any(|entry| entry_key(entry).is_some_and(|(key, _)| key == "exports"))
+    {
+        abort!(
+            path,
+            "The defaults file {} can't set `exports`",
+            full_path.display()
+        );
+    }
+
+    (
+        merge_metadata(join_entries(wrapper, entries), defaults),
+        Some(full_path),
+    )
+}
+
+/// Merge two pieces of metadata, using the entries of `defaults` whose keys aren't in `metadata`.
+/// Maps and structs that are in both are merged recursively; any other value in `metadata` replaces
+/// the one in `defaults`.
+fn merge_metadata(metadata: TokenStream, defaults: TokenStream) -> TokenStream {
+    let (wrapper, mut entries) = split_entries(metadata);
+    let (_, defaults) = split_entries(defaults);
+
+    for entry in &mut entries {
+        let Some((key, _)) = entry_key(entry) else {
+            continue;
+        };
+        let Some(default) = defaults
+            .iter()
+            .find(|default| entry_key(default).is_some_and(|(default_key, _)| default_key == key))
+        else {
+            continue;
+        };
+        if let ([TokenTree::Group(value)], [TokenTree::Group(default)]) =
+            (&entry[2..], &default[2..])
+        {
+            if value.delimiter() == default.delimiter()
+                && matches!(value.delimiter(), Delimiter::Brace | Delimiter::Parenthesis)
+            {
+                let mut merged = Group::new(
+                    value.delimiter(),
+                    merge_metadata(value.stream(), default.stream()),
+                );
+                merged.set_span(value.span());
+                entry[2] = merged.into();
+            }
+        }
+    }
+
+    let keys: Vec<String> = entries
+        .iter()
+        .filter_map(|entry| Some(entry_key(entry)?.0))
+        .collect();
+    entries.extend(
+        defaults
+            .into_iter()
+            .filter(|default| entry_key(default).is_some_and(|(key, _)| !keys.contains(&key))),
+    );
+    join_entries(wrapper, entries)
+}
+
+/// Split metadata into its entries. If they are wrapped in a JSON object or RON struct, the
+/// wrapper is returned too so that it can be put back with [`join_entries`].
+fn split_entries(metadata: TokenStream) -> (Option<Group>, Vec<Vec<TokenTree>>) {
+    let tokens: Vec<TokenTree> = metadata.into_iter().collect();
+    if let [TokenTree::Group(group)] = &*tokens {
+        if matches!(group.delimiter(), Delimiter::Brace | Delimiter::Parenthesis) {
+            let (_, entries) = split_entries(group.stream());
+            return (Some(group.clone()), entries);
+        }
+    }
+    let entries = tokens
+        .split(|token| matches!(token, TokenTree::Punct(punct) if punct.as_char() == ','))
+        .filter(|entry| !entry.is_empty())
+        .map(<[TokenTree]>::to_vec)
+        .collect();
+    (None, entries)
+}
+
+/// Join entries split with [`split_entries`] back together.
+fn join_entries(wrapper: Option<Group>, entries: Vec<Vec<TokenTree>>) -> TokenStream {
+    // JSON doesn't allow trailing commas.
+    let mut tokens = TokenStream::new();
+    for (i, entry) in entries.into_iter().enumerate() {
+        if i > 0 {
+            tokens.extend(quote!(,));
+        }
+        tokens.extend(entry);
+    }
+    match wrapper {
+        Some(wrapper) => {
+            let mut group = Group::new(wrapper.delimiter(), tokens);
+            group.set_span(wrapper.span());
+            group.into_token_stream()
+        }
+        None => tokens,
+    }
+}
+
+/// Turn the module name into a string that can be used in paths and Cargo package names.
+///
+/// Module names can contain any Unicode identifier characters, so non-ASCII characters are
+/// replaced with `_u{hex code point}`. Since an ASCII name can be written like that too, a hash of
+/// the original name is appended if any character was replaced, so that `café` and `caf_ue9` get
+/// different crates.
+fn sanitize_module_name(ident: &Ident) -> String {
+    let original = ident.unraw().to_string();
+    let mut name = String::new();
+    for c in original.chars() {
+        if c.is_ascii() {
+            name.push(c);
+        } else {
+            write!(name, "_u{:x}", u32::from(c)).unwrap();
+        }
+    }
+    if name != original {
+        write!(name, "_{:08x}", stable_hash(original.as_bytes()) as u32).unwrap();
+    }
+    name
+}
+
+/// Hash bytes with 64-bit FNV-1a. Unlike `DefaultHasher`, the hash never changes between Rust
+/// versions, so it can be used in the paths of the inner crates. Those paths must also stay the
+/// same between versions of this crate, or every module would be rebuilt, so the tests pin the
+/// names made with it.
+fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The hash of the parts of a crate with `shared_cache`, which names its directory. Each part is
+/// prefixed with its length, or is a single zero byte if it's missing, so that different parts
+/// can't hash the same by moving bytes from one to another.
+fn shared_cache_key<'a>(parts: impl Iterator<Item = Option<&'a String>>) -> u64 {
+    let mut bytes = Vec::new();
+    for part in parts {
+        match part {
+            Some(part) => {
+                bytes.push(1);
+                bytes.extend((part.len() as u64).to_le_bytes());
+                bytes.extend(part.as_bytes());
+            }
+            None => bytes.push(0),
+        }
+    }
+    stable_hash(&bytes)
+}
+
+/// The prefix of the functions generated in the inner crate for each exported macro.
+const RESERVED_PREFIX: &str = "__exported_macro_";
+
+/// Abort if an item in the module would collide with the code added to the inner crate.
+fn check_reserved_names(metadata: &Metadata, items: &[Item]) {
+    let macro_output = metadata
+        .exports
+        .all_macros()
+        .any(|(_, mac, _)| mac.macro_output);
+    for item in items {
+        let ident = match item {
+            Item::ExternCrate(item) => {
+                if item.rename.is_none() && item.ident == "proc_macro" {
+                    abort!(
+                        item,
+                        "`extern crate proc_macro;` is already added to the inline proc crate"
+                    );
+                }
+                continue;
+            }
+            Item::Const(item) => &item.ident,
+            Item::Enum(item) => &item.ident,
+            Item::Fn(item) => &item.sig.ident,
+            Item::Macro(ItemMacro {
+                ident: Some(ident), ..
+            }) => ident,
+            Item::Mod(item) => &item.ident,
+            Item::Static(item) => &item.ident,
+            Item::Struct(item) => &item.ident,
+            Item::Trait(item) => &item.ident,
+            Item::TraitAlias(item) => &item.ident,
+            Item::Type(item) => &item.ident,
+            Item::Union(item) => &item.ident,
+            _ => continue,
+        };
+        if ident.to_string().starts_with(RESERVED_PREFIX) {
+            abort!(
+                ident,
+                "`{}` collides with the names reserved by inline-proc",
+                ident;
+                help = "names starting with `{}` are reserved", RESERVED_PREFIX
+            );
+        }
+        if macro_output && ident == "inline_proc" {
+            abort!(
+                ident,
+                "`inline_proc` collides with the module added for macros with `macro_output`"
+            );
+        }
+    }
+}
+
+/// Check that the exported functions defined directly in the module return a token stream, since
+/// otherwise the error would point at the generated glue code.
+fn check_return_types(metadata: &Metadata, items: &[Item]) {
+    for (_, mac, _) in metadata.exports.all_macros() {
+        let Some(function) = &mac.function else {
+            continue;
+        };
+        let Some(function_name) = function.0.get_ident() else {
+            continue;
+        };
+        let Some(sig) = items.iter().find_map(|item| match item {
+            Item::Fn(item) if item.sig.ident == *function_name => Some(&item.sig),
+            _ => None,
+        }) else {
+            continue;
+        };
+
+        // Any path could be an alias of `TokenStream`, so only types that can't be are rejected.
+        let found = match &sig.output {
+            ReturnType::Default => "()".to_owned(),
+            ReturnType::Type(_, ty) => match &**ty {
+                Type::Array(_)
+                | Type::BareFn(_)
+                | Type::ImplTrait(_)
+                | Type::Never(_)
+                | Type::Ptr(_)
+                | Type::Reference(_)
+                | Type::Slice(_)
+                | Type::TraitObject(_)
+                | Type::Tuple(_) => ty.to_token_stream().to_string(),
+                _ => continue,
+            },
+        };
+        let expected = if mac.proc_macro2 {
+            "proc_macro2::TokenStream"
+        } else {
+            "proc_macro::TokenStream"
+        };
+        abort!(
+            sig,
+            "exported macro function `{}` must return `{}`, found `{}`",
+            function_name,
+            expected,
+            found
+        );
+    }
+}
+
+/// Metadata for an inline proc macro.
+#[derive(Deserialize)]
+struct Metadata {
+    #[serde(default = "default_cargo")]
+    cargo: PathBuf,
+    #[serde(default = "default_cargo_subcommand")]
+    cargo_subcommand: Vec<String>,
+    #[serde(default = "default_rustc")]
+    rustc: Option<PathBuf>,
+    #[serde(default = "default_rustc_wrapper")]
+    rustc_wrapper: Option<PathBuf>,
+    #[serde(default = "return_true")]
+    color: bool,
+    #[serde(default = "return_true")]
+    quiet: bool,
+    #[serde(default)]
+    clippy: bool,
+    #[serde(default)]
+    fail_fast: bool,
+    #[serde(default)]
+    build_retries: u32,
+    #[serde(default)]
+    jobs: Option<NonZeroU32>,
+    #[serde(default)]
+    incremental: bool,
+    #[serde(default)]
+    lazy_binding: bool,
+    #[serde(default)]
+    reproducible: bool,
+    #[serde(default)]
+    panic: PanicStrategy,
+    #[serde(default)]
+    diagnostics: DiagnosticsStyle,
+    #[serde(default)]
+    shared_workspace: bool,
+    #[serde(default)]
+    shared_cache: bool,
+    #[serde(default)]
+    rlib: bool,
+    #[serde(default)]
+    embed_source: bool,
+    #[serde(default)]
+    pre_build: Vec<String>,
+    #[serde(default)]
+    pre_build_outputs: Vec<PathBuf>,
+    #[serde(default)]
+    depfile: Option<PathBuf>,
+    #[serde(default)]
+    targets: Vec<String>,
+    #[serde(default)]
+    pretty_printer: Vec<String>,
+    #[serde(default)]
+    links: Vec<String>,
+    #[serde(default)]
+    link_search: Vec<String>,
+    #[serde(default)]
+    wrap_output: Option<DeserializeIdent>,
+    #[serde(default)]
+    output_attributes: Vec<DeserializeMeta>,
+    #[serde(default)]
+    harness: Option<Harness>,
+    #[serde(default)]
+    cargo_config: BTreeMap<String, toml::Value>,
+    #[serde(default = "default_edition")]
+    edition: String,
+    #[serde(default)]
+    rust_version: Option<String>,
+    #[serde(default)]
+    resolver: Option<String>,
+    #[serde(default)]
+    package_metadata: BTreeMap<String, toml::Value>,
+    dependencies: cargo_toml::DepsSet,
+    #[serde(default = "default_inline_proc_path")]
+    inline_proc_path: DeserializePath,
+    #[serde(default)]
+    reexport_invoker: Option<DeserializePath>,
+    #[serde(default)]
+    warn_unused: bool,
+    #[serde(default)]
+    allowed_dependencies: Option<Vec<String>>,
+    #[serde(default)]
+    denied_dependencies: Vec<String>,
+    #[serde(default)]
+    check_transitive_dependencies: bool,
+    #[serde(default)]
+    generate_docs: bool,
+    #[serde(default)]
+    rustdoc_flags: Vec<String>,
+    exports: Exports,
+    #[serde(skip)]
+    spans: MetadataSpans,
+    /// The defaults file merged into the metadata, which the compiler is told to track.
+    #[serde(skip)]
+    defaults_file: Option<PathBuf>,
+    /// Whether to keep a copy of the inner crate, set by `#[inline_proc(keep)]`.
+    #[serde(skip)]
+    keep: bool,
+    /// The name of the module, which names its re-export of `invoke_inline_macro`.
+    #[serde(skip)]
+    module_name: String,
+}
+
+/// A binary that runs one of the macros on sample input, for debugging.
+#[derive(Deserialize)]
+struct Harness {
+    #[serde(rename = "macro")]
+    macro_name: DeserializeIdent,
+    input: String,
+    /// The attribute parameters, for attribute macros.
+    #[serde(default)]
+    attr: String,
+}
+
+/// How the diagnostics from compiling the macro are displayed.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+enum DiagnosticsStyle {
+    /// Show the notes and help messages attached to each diagnostic.
+    #[default]
+    Full,
+    /// Only show the main message of each diagnostic.
+    Short,
+}
+
+/// Parse from a string rather than an enum, since RON doesn't write enums as strings.
+impl TryFrom<String> for DiagnosticsStyle {
+    type Error = String;
+    fn try_from(style: String) -> Result<Self, Self::Error> {
+        match &*style {
+            "full" => Ok(Self::Full),
+            "short" => Ok(Self::Short),
+            _ => Err(format!(
+                "unknown diagnostics style `{}`, expected `full` or `short`",
+                style
+            )),
+        }
+    }
+}
+
+/// The panic strategy the inner crate is built with.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+enum PanicStrategy {
+    /// Unwind, so that panics in a macro don't abort the compiler that loaded it.
+    #[default]
+    Unwind,
+    /// Abort the process on a panic.
+    Abort,
+}
+
+impl PanicStrategy {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Unwind => "unwind",
+            Self::Abort => "abort",
+        }
+    }
+}
+
+impl TryFrom<String> for PanicStrategy {
+    type Error = String;
+    fn try_from(strategy: String) -> Result<Self, Self::Error> {
+        match &*strategy {
+            "unwind" => Ok(Self::Unwind),
+            "abort" => Ok(Self::Abort),
+            _ => Err(format!(
+                "unknown panic strategy `{}`, expected `unwind` or `abort`",
+                strategy
+            )),
+        }
+    }
+}
+
+fn default_cargo() -> PathBuf {
+    PathBuf::from(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+}
+fn default_cargo_subcommand() -> Vec<String> {
+    vec!["build".to_owned()]
+}
+/// The rustc that is compiling the host crate, so that the dylib is built by the same compiler that
+/// loads it. `RUSTC` takes precedence if it's set. Otherwise this is the running executable if it's
+/// rustc, or else the `rustc` next to it if it was built from the same commit, since `rustdoc` and
+/// `clippy-driver` also expand macros but needn't come from the toolchain next to them.
+fn default_rustc() -> Option<PathBuf> {
+    if let Some(rustc) = env::var_os("RUSTC") {
+        return Some(PathBuf::from(rustc));
+    }
+    let exe = env::current_exe().ok()?;
+    if exe.file_stem() == Some("rustc".as_ref()) {
+        return Some(exe);
+    }
+    let rustc = exe.with_file_name(format!("rustc{}", env::consts::EXE_SUFFIX));
+    let commit_hash = |program: &Path| {
+        let output = Command::new(program).arg("-vV").output().ok()?;
+        let output = String::from_utf8(output.stdout).ok()?;
+        output
+            .lines()
+            .find_map(|line| line.strip_prefix("commit-hash: "))
+            .map(str::to_owned)
+    };
+    if !rustc.is_file() {
+        return None;
+    }
+    (commit_hash(&rustc)? == commit_hash(&exe)?).then_some(rustc)
+}
+/// The wrapper Cargo would use for the host crate, if it is set in the environment. Wrappers set in
+/// the host's Cargo config aren't visible to proc macros.
+fn default_rustc_wrapper() -> Option<PathBuf> {
+    env::var_os("RUSTC_WRAPPER")
+        .filter(|wrapper| !wrapper.is_empty())
+        .map(PathBuf::from)
+}
+fn default_edition() -> String {
+    "2015".to_owned()
+}
+const fn return_true() -> bool {
+    true
+}
+fn default_inline_proc_path() -> DeserializePath {
+    DeserializePath(syn::parse2(quote!(::inline_proc)).unwrap())
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Exports {
+    bang_macros: BTreeMap<DeserializeIdent, Macro>,
+    derives: BTreeMap<DeserializeIdent, Macro>,
+    attributes: BTreeMap<DeserializeIdent, Macro>,
+}
+
+impl Exports {
+    fn all_macros(&self) -> impl Iterator<Item = (&Ident, &Macro, &'static str)> {
+        let add_macro_type = |macro_type| move |(name, mac)| (name, mac, macro_type);
+
+        self.bang_macros
+            .iter()
+            .map(add_macro_type("bang"))
+            .chain(self.derives.iter().map(add_macro_type("derive")))
+            .chain(self.attributes.iter().map(add_macro_type("attribute")))
+            .map(|(DeserializeIdent(ident), mac, macro_type)| (ident, mac, macro_type))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(from = "MacroOptions")]
+struct Macro {
+    /// The function implementing the macro, which is `None` for passthrough macros.
+    function: Option<DeserializePath>,
+    passthrough: bool,
+    export: bool,
+    proc_macro2: bool,
+    feature_gate: Option<String>,
+    /// The group of macros the macro is in, such as alternative backends.
+    group: Option<String>,
+    /// Whether at most one macro of the group can be enabled at a time, selected by their
+    /// feature gates.
+    exclusive: bool,
+    spans: SpanMode,
+    visibility: Option<Box<DeserializeVisibility>>,
+    doc: Option<String>,
+    deprecated: Option<String>,
+    /// Whether the macro function takes a path to the crate that defines the macro.
+    host_crate: bool,
+    location: bool,
+    macro_output: bool,
+    /// The `syn` type to parse the input, or the item of attribute macros, into.
+    parse: Option<DeserializePath>,
+    /// The format and type to deserialize the input of a bang macro into.
+    deserialize: Option<Box<DeserializeInput>>,
+    /// The fragment specifier that the input of a bang macro is matched with, instead of any
+    /// tokens.
+    input_fragment: Option<String>,
+    output_module: bool,
+    /// Whether a bang macro can be invoked with `include "path"`, to pass it the contents of the
+    /// file instead.
+    include: bool,
+}
+
+/// Support both `{ function = "function_name", export = true }` and shorthand `"function_name"`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MacroOptions {
+    Function(DeserializePath),
+    Full {
+        #[serde(default)]
+        function: Option<DeserializePath>,
+        #[serde(default)]
+        passthrough: bool,
+        #[serde(default)]
+        export: bool,
+        #[serde(default)]
+        proc_macro2: bool,
+        #[serde(default)]
+        feature_gate: Option<String>,
+        #[serde(default)]
+        group: Option<String>,
+        #[serde(default)]
+        exclusive: bool,
+        #[serde(default)]
+        spans: SpanMode,
+        #[serde(default)]
+        visibility: Option<Box<DeserializeVisibility>>,
+        #[serde(default)]
+        doc: Option<String>,
+        #[serde(default)]
+        deprecated: Option<String>,
+        #[serde(default)]
+        host_crate: bool,
+        #[serde(default)]
+        location: bool,
+        #[serde(default)]
+        macro_output: bool,
+        #[serde(default)]
+        parse: Option<DeserializePath>,
+        #[serde(default)]
+        deserialize: Option<Box<DeserializeInput>>,
+        #[serde(default)]
+        input_fragment: Option<String>,
+        #[serde(default)]
+        output_module: bool,
+        #[serde(default)]
+        include: bool,
+    },
+}
+
+impl From<MacroOptions> for Macro {
+    fn from(options: MacroOptions) -> Self {
+        match options {
+            MacroOptions::Function(function) => Self {
+                function: Some(function),
+                passthrough: false,
+                export: false,
+                proc_macro2: false,
+                feature_gate: None,
+                group: None,
+                exclusive: false,
+                spans: SpanMode::default(),
+                visibility: None,
+                doc: None,
+                deprecated: None,
+                host_crate: false,
+                location: false,
+                macro_output: false,
+                parse: None,
+                deserialize: None,
+                input_fragment: None,
+                output_module: false,
+                include: false,
+            },
+            MacroOptions::Full {
+                function,
+                passthrough,
+                export,
+                proc_macro2,
+                feature_gate,
+                group,
+                exclusive,
+                spans,
+                visibility,
+                doc,
+                deprecated,
+                host_crate,
+                location,
+                macro_output,
+                parse,
+                deserialize,
+                input_fragment,
+                output_module,
+                include,
+            } => Self {
+                function,
+                passthrough,
+                export,
+                proc_macro2,
+                feature_gate,
+                group,
+                exclusive,
+                spans,
+                visibility,
+                doc,
+                deprecated,
+                host_crate,
+                location,
+                macro_output,
+                parse,
+                deserialize,
+                input_fragment,
+                output_module,
+                include,
+            },
+        }
+    }
+}
+
+/// The input of a bang macro with `deserialize`, such as
+/// `deserialize: (format: "ron", type: "Config")`.
+#[derive(Deserialize)]
+struct DeserializeInput {
+    format: InputFormat,
+    #[serde(rename = "type")]
+    ty: DeserializePath,
+}
+
+/// The format of the input of a bang macro with `deserialize`.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum InputFormat {
+    Ron,
+    Json,
+}
+
+impl InputFormat {
+    /// The package of the dependency that deserializes the format.
+    fn package(self) -> &'static str {
+        match self {
+            Self::Ron => "ron",
+            Self::Json => "serde_json",
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct DeserializeIdent(Ident);
+// Ordered by name so that the generated code is the same every time.
+impl PartialOrd for DeserializeIdent {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for DeserializeIdent {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.to_string().cmp(&other.0.to_string())
+    }
+}
+impl<'de> Deserialize<'de> for DeserializeIdent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct IdentVisitor;
         impl<'de> Visitor<'de> for IdentVisitor {
             type Value = Ident;
             fn expecting(&self, f: &mut Formatter) -> fmt::Result {
                 f.write_str("an identifier")
             }
-            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
-                syn::parse_str(v).map_err(|_| E::invalid_value(Unexpected::Str(v), &self))
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                syn::parse_str(v).map_err(|_| E::invalid_value(Unexpected::Str(v), &self))
+            }
+        }
+        deserializer.deserialize_str(IdentVisitor).map(Self)
+    }
+}
+
+struct DeserializePath(RustPath);
+impl<'de> Deserialize<'de> for DeserializePath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PathVisitor;
+        impl<'de> Visitor<'de> for PathVisitor {
+            type Value = RustPath;
+            fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+                f.write_str("a Rust path")
+            }
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                syn::parse_str(v).map_err(|_| E::invalid_value(Unexpected::Str(v), &self))
+            }
+        }
+        deserializer.deserialize_str(PathVisitor).map(Self)
+    }
+}
+
+struct DeserializeVisibility(syn::Visibility);
+impl<'de> Deserialize<'de> for DeserializeVisibility {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct VisibilityVisitor;
+        impl<'de> Visitor<'de> for VisibilityVisitor {
+            type Value = syn::Visibility;
+            fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+                f.write_str("a visibility")
+            }
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                syn::parse_str(v).map_err(|_| E::invalid_value(Unexpected::Str(v), &self))
+            }
+        }
+        deserializer.deserialize_str(VisibilityVisitor).map(Self)
+    }
+}
+
+struct DeserializeMeta(syn::Meta);
+impl<'de> Deserialize<'de> for DeserializeMeta {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MetaVisitor;
+        impl<'de> Visitor<'de> for MetaVisitor {
+            type Value = syn::Meta;
+            fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+                f.write_str("the contents of an attribute")
+            }
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                syn::parse_str(v).map_err(|_| E::invalid_value(Unexpected::Str(v), &self))
+            }
+        }
+        deserializer.deserialize_str(MetaVisitor).map(Self)
+    }
+}
+
+/// A string made from writing out tokens.
+#[derive(Default)]
+struct TokenString {
+    /// The span for each byte of the string.
+    byte_spans: Vec<Span>,
+    /// The span for each char of the string.
+    char_spans: Vec<Span>,
+    /// The string itself.
+    tokens: String,
+    /// Whether the previous token was a punctuation token and joint.
+    joint: bool,
+    /// The byte ranges of the code generated by `inline_proc` after the module's own code, one for
+    /// each item or group of items.
+    generated: Vec<Range<usize>>,
+}
+
+impl TokenString {
+    fn from_tokens(tokens: impl ToTokens) -> Self {
+        let mut this = Self::default();
+        this.push_tokens(tokens);
+        this
+    }
+    fn from_token(token: impl Into<TokenTree>) -> Self {
+        let mut this = Self::default();
+        this.push_token(token.into());
+        this
+    }
+    fn push(&mut self, item: impl Display, span: Span) {
+        let old_len = self.tokens.len();
+        write!(self.tokens, "{}", item).unwrap();
+        let written = &self.tokens[old_len..];
+        self.byte_spans.extend(written.bytes().map(|_| span));
+        self.char_spans.extend(written.chars().map(|_| span));
+    }
+    /// Get the span of the char at the given index, falling back to the last span.
+    #[cfg_attr(not(any(feature = "json", feature = "ron")), allow(dead_code))]
+    fn char_span(&self, index: usize) -> Span {
+        self.char_spans
+            .get(index)
+            .or_else(|| self.char_spans.last())
+            .copied()
+            .unwrap_or_else(Span::call_site)
+    }
+    /// Push code generated by `inline_proc`, recording where it is so that diagnostics in it can
+    /// show it.
+    fn push_generated(&mut self, tokens: impl ToTokens) {
+        let start = self.tokens.len();
+        self.push_tokens(tokens);
+        self.generated.push(start..self.tokens.len());
+    }
+    /// The generated code containing the byte at the index, if it is in generated code.
+    fn generated_code(&self, index: usize) -> Option<&str> {
+        let range = self.generated.iter().find(|range| range.contains(&index))?;
+        Some(self.tokens[range.clone()].trim())
+    }
+    fn extend_prev(&mut self, item: impl Display) {
+        self.push(item, *self.byte_spans.last().unwrap());
+    }
+
+    fn push_tokens(&mut self, tokens: impl ToTokens) {
+        for token in tokens.into_token_stream() {
+            self.push_token(token);
+        }
+    }
+    fn push_token(&mut self, token: TokenTree) {
+        if !self.joint && !self.tokens.is_empty() {
+            self.extend_prev(" ");
+        }
+
+        match &token {
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::None => ("", ""),
+                };
+
+                self.push(open, group.span_open());
+                self.push_tokens(group.stream());
+                self.push(close, group.span_close());
+            }
+            TokenTree::Literal(lit) => {
+                let old_len = self.tokens.len();
+                write!(self.tokens, "{}", lit).unwrap();
+                let written = &self.tokens[old_len..];
+                let fallback_span = lit.span();
+                self.byte_spans.extend(
+                    (0..written.len()).map(|i| lit.subspan(i..=i).unwrap_or(fallback_span)),
+                );
+                self.char_spans.extend(
+                    written
+                        .char_indices()
+                        .map(|(i, c)| lit.subspan(i..i + c.len_utf8()).unwrap_or(fallback_span)),
+                );
+            }
+            _ => {
+                self.push(&token, token.span());
+            }
+        }
+
+        self.joint = matches!(token, TokenTree::Punct(p) if p.spacing() == Spacing::Joint);
+    }
+}
+
+/// Replace dependencies declared with `workspace: true` by their definitions in the host
+/// workspace's `[workspace.dependencies]`, since the inner crate is not part of that workspace.
+fn resolve_workspace_dependencies(
+    cargo: &Path,
+    dependencies: &mut cargo_toml::DepsSet,
+    spans: &MetadataSpans,
+) -> Result<()> {
+    if !dependencies
+        .values()
+        .any(|dep| matches!(dep, CargoDependency::Inherited(_)))
+    {
+        return Ok(());
+    }
+
+    let output = Command::new(cargo)
+        .args(["locate-project", "--workspace", "--message-format", "plain"])
+        .current_dir(env::var_os("CARGO_MANIFEST_DIR").unwrap_or_else(|| ".".into()))
+        .env_remove("RUST_RECURSION_COUNT")
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(Error::cargo("launch Cargo"))?;
+    if !output.status.success() {
+        return Err(Error::Cargo {
+            action: "locate the workspace root",
+            error: None,
+        });
+    }
+    let workspace_manifest_path = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let workspace_root = workspace_manifest_path.parent().unwrap_or(Path::new("."));
+
+    let workspace_manifest = fs::read(&workspace_manifest_path)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| cargo_toml::Manifest::from_slice(&bytes).map_err(|e| e.to_string()))
+        .map_err(|e| {
+            Error::Dependency(format!(
+                "Failed to read workspace manifest {}: {}",
+                workspace_manifest_path.display(),
+                e
+            ))
+        })?;
+    let workspace_dependencies = workspace_manifest
+        .workspace
+        .map(|workspace| workspace.dependencies)
+        .unwrap_or_default();
+
+    for (name, dep) in dependencies {
+        let overrides = match dep {
+            CargoDependency::Inherited(overrides) => overrides.clone(),
+            _ => continue,
+        };
+        *dep = workspace_dependencies.get(name).cloned().ok_or_else(|| {
+            Error::Dependency(format!(
+                "Dependency `{}` is not in the workspace dependencies of {}",
+                name,
+                workspace_manifest_path.display()
+            ))
+            .at(spans.get(&["dependencies", name, "workspace"]))
+        })?;
+
+        if overrides.optional || !overrides.features.is_empty() {
+            let detail = dep.detail_mut();
+            detail.optional |= overrides.optional;
+            detail.features.extend(overrides.features);
+        }
+        if let CargoDependency::Detailed(detail) = dep {
+            if let Some(path) = &mut detail.path {
+                *path = workspace_root.join(&*path).display().to_string();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check the parts of the dependencies that Cargo would otherwise report confusingly.
+fn validate_dependencies(dependencies: &cargo_toml::DepsSet, spans: &MetadataSpans) -> Result<()> {
+    for (name, dep) in dependencies {
+        if let Some(package) = dep.package() {
+            if !is_valid_crate_name(package) {
+                return Err(Error::Dependency(format!(
+                    "Dependency `{}` renames invalid crate name `{}`",
+                    name, package
+                ))
+                .at(spans.get(&["dependencies", name, "package"])));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The fragment specifiers of `macro_rules!` that `input_fragment` can be.
+const FRAGMENT_SPECIFIERS: &[&str] = &[
+    "block",
+    "expr",
+    "expr_2021",
+    "ident",
+    "item",
+    "lifetime",
+    "literal",
+    "meta",
+    "pat",
+    "pat_param",
+    "path",
+    "stmt",
+    "tt",
+    "ty",
+    "vis",
+];
+
+/// Check that the macros of each group agree on whether it is exclusive, and that every macro of an
+/// exclusive group has a feature gate of its own to select it.
+fn validate_groups(exports: &Exports, spans: &MetadataSpans) -> Result<()> {
+    let mut groups: BTreeMap<&str, Vec<(&Ident, &Macro, &str)>> = BTreeMap::new();
+    for (name, mac, macro_type) in exports.all_macros() {
+        match &mac.group {
+            Some(group) => groups
+                .entry(group)
+                .or_default()
+                .push((name, mac, macro_type)),
+            None if mac.exclusive => {
+                return Err(Error::Metadata(format!(
+                    "Macro `{}` is `exclusive` but isn't in a `group`",
+                    name
+                ))
+                .at(spans.get_macro(name, macro_type, "exclusive")));
+            }
+            None => {}
+        }
+    }
+
+    for (group, members) in groups {
+        let exclusive = members.iter().any(|(_, mac, _)| mac.exclusive);
+        if !exclusive {
+            continue;
+        }
+        let mut features = HashMap::new();
+        for &(name, mac, macro_type) in &members {
+            let span_of = |option| spans.get_macro(name, macro_type, option);
+            if !mac.exclusive {
+                return Err(Error::Metadata(format!(
+                    "Macro `{}` must be `exclusive` like the other macros of group `{}`",
+                    name, group
+                ))
+                .at(span_of("group")));
+            }
+            let Some(feature) = &mac.feature_gate else {
+                return Err(Error::Metadata(format!(
+                    "Macro `{}` of exclusive group `{}` needs a `feature_gate` to select it",
+                    name, group
+                ))
+                .at(span_of("group")));
+            };
+            if let Some(other) = features.insert(feature, name) {
+                return Err(Error::Metadata(format!(
+                    "Macros `{}` and `{}` of exclusive group `{}` have the same feature gate `{}`, \
+                     so they are always enabled together",
+                    other, name, group, feature
+                ))
+                .at(span_of("feature_gate")));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Generate an error for every pair of macros of an exclusive group whose features are enabled
+/// together, at the `group` of the second macro.
+fn generate_exclusive_checks(metadata: &Metadata) -> TokenStream {
+    let members: Vec<_> = metadata
+        .exports
+        .all_macros()
+        .filter(|(_, mac, _)| mac.exclusive)
+        .filter_map(|(name, mac, macro_type)| {
+            Some((
+                name,
+                mac.group.as_ref()?,
+                mac.feature_gate.as_ref()?,
+                macro_type,
+            ))
+        })
+        .collect();
+
+    let mut checks = TokenStream::new();
+    for (i, &(first, group, first_feature, _)) in members.iter().enumerate() {
+        for &(second, _, second_feature, macro_type) in members[i + 1..]
+            .iter()
+            .filter(|(_, other_group, _, _)| *other_group == group)
+        {
+            let message = format!(
+                "Only one macro of exclusive group `{}` can be enabled, but `{}` (feature `{}`) \
+                 and `{}` (feature `{}`) both are",
+                group, first, first_feature, second, second_feature
+            );
+            let span = metadata
+                .spans
+                .get_macro(second, macro_type, "group")
+                .unwrap_or_else(Span::call_site);
+            checks.extend(quote_spanned! {span=>
+                #[cfg(all(feature = #first_feature, feature = #second_feature))]
+                ::core::compile_error!(#message);
+            });
+        }
+    }
+    checks
+}
+
+/// Check that `reexport_invoker` is a path from the crate root, which exported macros can follow
+/// from `$crate`.
+fn validate_reexport_invoker(metadata: &Metadata) -> Result<()> {
+    let Some(DeserializePath(path)) = &metadata.reexport_invoker else {
+        return Ok(());
+    };
+    let from_root = path.leading_colon.is_none()
+        && path
+            .segments
+            .first()
+            .is_some_and(|segment| segment.ident == "crate")
+        && path
+            .segments
+            .iter()
+            .all(|segment| segment.arguments.is_none());
+    if from_root {
+        Ok(())
+    } else {
+        Err(Error::Metadata(format!(
+            "`reexport_invoker` must be the path of a module starting with `crate`, found `{}`",
+            path.to_token_stream().to_string().replace(' ', "")
+        ))
+        .at(metadata.spans.get(&["reexport_invoker"])))
+    }
+}
+
+/// Check for combinations of macro options that can't be used together.
+fn validate_exports(exports: &Exports, spans: &MetadataSpans) -> Result<()> {
+    for (name, mac, macro_type) in exports.all_macros() {
+        let span_of = |option| spans.get_macro(name, macro_type, option);
+        match (&mac.function, mac.passthrough) {
+            (None, false) => {
+                return Err(Error::Metadata(format!(
+                    "Macro `{}` needs a `function`, or `passthrough: true`",
+                    name
+                ))
+                .at(span_of("function")));
+            }
+            (Some(_), true) => {
+                return Err(Error::Metadata(format!(
+                    "Passthrough macro `{}` can't have a `function`",
+                    name
+                ))
+                .at(span_of("function")));
+            }
+            _ => {}
+        }
+        if mac.passthrough {
+            // These only affect the function or its output, which passthrough macros don't have.
+            let unused = [
+                ("spans", mac.spans != SpanMode::default()),
+                ("location", mac.location),
+                ("host_crate", mac.host_crate),
+                ("macro_output", mac.macro_output),
+                ("proc_macro2", mac.proc_macro2),
+                ("output_module", mac.output_module),
+            ];
+            if let Some((option, _)) = unused.into_iter().find(|(_, set)| *set) {
+                return Err(Error::Metadata(format!(
+                    "Passthrough macro `{}` can't have `{}`, since it doesn't call a function",
+                    name, option
+                ))
+                .at(span_of(option)));
+            }
+        }
+        if mac.passthrough && mac.parse.is_some() {
+            return Err(Error::Metadata(format!(
+                "Passthrough macro `{}` can't have a `parse` type",
+                name
+            ))
+            .at(span_of("parse")));
+        }
+        if mac.deserialize.is_some() {
+            let message = if macro_type != "bang" {
+                Some("only function-like macros can have `deserialize`")
+            } else if mac.passthrough {
+                Some("a passthrough macro can't have `deserialize`")
+            } else if mac.parse.is_some() {
+                Some("a macro can't have both `parse` and `deserialize`")
+            } else {
+                None
+            };
+            if let Some(message) = message {
+                return Err(Error::Metadata(format!("Macro `{}`: {}", name, message))
+                    .at(span_of("deserialize")));
+            }
+        }
+        if let Some(fragment) = &mac.input_fragment {
+            if macro_type != "bang" {
+                return Err(Error::Metadata(format!(
+                    "Macro `{}`: only function-like macros can have an `input_fragment`",
+                    name
+                ))
+                .at(span_of("input_fragment")));
+            }
+            if !FRAGMENT_SPECIFIERS.contains(&&**fragment) {
+                return Err(Error::Metadata(format!(
+                    "Unknown fragment specifier `{}` for `input_fragment` of macro `{}`, expected \
+                     one of {}",
+                    fragment,
+                    name,
+                    FRAGMENT_SPECIFIERS.join(", ")
+                ))
+                .at(span_of("input_fragment")));
+            }
+        }
+        if mac.include {
+            let message = if macro_type != "bang" {
+                Some("only function-like macros can have `include`")
+            } else if mac.passthrough {
+                Some("a passthrough macro can't have `include`")
+            } else {
+                None
+            };
+            if let Some(message) = message {
+                return Err(Error::Metadata(format!("Macro `{}`: {}", name, message))
+                    .at(span_of("include")));
+            }
+        }
+        if mac.spans == SpanMode::Argument && macro_type != "bang" {
+            return Err(Error::Metadata(format!(
+                "Macro `{}`: only function-like macros can have `spans: \"argument\"`",
+                name
+            ))
+            .at(span_of("spans")));
+        }
+        // A derive can only add items, so passing its input through would duplicate the item.
+        if mac.passthrough && macro_type == "derive" {
+            return Err(Error::Metadata(format!(
+                "Derive macro `{}` can't be a passthrough macro",
+                name
+            ))
+            .at(span_of("passthrough")));
+        }
+        if let Some(visibility) = &mac.visibility {
+            // `macro_rules!` macros can only be re-exported within the crate.
+            let message = if mac.export {
+                Some("can't have a `visibility` since it is exported with `export: true`")
+            } else {
+                match visibility.0 {
+                    syn::Visibility::Inherited => Some("has an empty `visibility`"),
+                    syn::Visibility::Public(_) => Some(
+                        "can't have `visibility: \"pub\"`, since only exported macros can be used \
+                         outside the crate; use `export: true` instead",
+                    ),
+                    syn::Visibility::Restricted(_) => None,
+                }
+            };
+            if let Some(message) = message {
+                return Err(Error::Metadata(format!("Macro `{}` {}", name, message))
+                    .at(span_of("visibility")));
+            }
+        }
+        // Rustdoc only documents macros that are exported or re-exported.
+        if mac.doc.is_some() && !mac.export && mac.visibility.is_none() {
+            return Err(Error::Metadata(format!(
+                "Macro `{}` has a `doc` but is neither exported with `export: true` nor \
+                 re-exported with a `visibility`, so its documentation wouldn't be shown",
+                name
+            ))
+            .at(span_of("doc")));
+        }
+    }
+    Ok(())
+}
+
+fn is_valid_crate_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn generate_cargo_toml(metadata: &Metadata, package_name: &str) -> Result<String> {
+    #[derive(serde::Serialize)]
+    struct DependenciesWrapper<'a> {
+        dependencies: &'a cargo_toml::DepsSet,
+    }
+
+    // Macros with `proc_macro2` return the byte ranges of the spans of their output.
+    let mut dependencies = metadata.dependencies.clone();
+    if metadata
+        .exports
+        .all_macros()
+        .any(|(_, mac, _)| mac.proc_macro2 && mac.function.is_some())
+    {
+        for (name, dependency) in &mut dependencies {
+            if dependency.package().unwrap_or(name) != "proc-macro2" {
+                continue;
+            }
+            if let Ok(detail) = dependency.try_detail_mut() {
+                detail.features.push("span-locations".to_owned());
             }
         }
-        deserializer.deserialize_str(IdentVisitor).map(Self)
     }
+    let dependencies = toml::to_string(&DependenciesWrapper {
+        dependencies: &dependencies,
+    })
+    .unwrap();
+
+    let rust_version = match &metadata.rust_version {
+        Some(version) => {
+            let parts: Vec<&str> = version.split('.').collect();
+            let valid = (1..=3).contains(&parts.len())
+                && parts
+                    .iter()
+                    .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()));
+            if !valid {
+                return Err(
+                    Error::Metadata(format!("Invalid rust_version `{}`", version))
+                        .at(metadata.spans.get(&["rust_version"])),
+                );
+            }
+            format!(",rust-version='{}'", version)
+        }
+        None => String::new(),
+    };
+
+    let resolver = match metadata.resolver.as_deref() {
+        Some(resolver @ ("1" | "2" | "3")) => format!(",resolver='{}'", resolver),
+        Some(resolver) => {
+            return Err(Error::Metadata(format!(
+                "Invalid resolver `{}`, expected \"1\", \"2\" or \"3\"",
+                resolver
+            ))
+            .at(metadata.spans.get(&["resolver"])));
+        }
+        None => String::new(),
+    };
+
+    let mut package_metadata = String::new();
+    for (key, value) in &metadata.package_metadata {
+        let valid_key = !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_key {
+            return Err(
+                Error::Metadata(format!("Invalid package metadata key `{}`", key))
+                    .at(metadata.spans.get(&["package_metadata", key])),
+            );
+        }
+        // These are set by inline-proc or by other metadata options.
+        if ["name", "version", "edition", "rust-version", "resolver"].contains(&&**key) {
+            return Err(
+                Error::Metadata(format!("Package metadata can't set `{}`", key))
+                    .at(metadata.spans.get(&["package_metadata", key])),
+            );
+        }
+        write!(package_metadata, ",{}={}", key, value).unwrap();
+    }
+    // The inner crate is never meant to be published.
+    let publish = if metadata.package_metadata.contains_key("publish") {
+        ""
+    } else {
+        ",publish=false"
+    };
+
+    Ok(format!(
+        "\
+package={{name='{package_name}',version='0.0.0',edition='{edition}'{publish}{rust_version}{resolver}{package_metadata}}}
+lib={{crate-type=[{crate_types}],path='lib.rs'}}
+{harness}{dependencies}\
+        ",
+        package_name = package_name,
+        edition = metadata.edition,
+        rust_version = rust_version,
+        publish = publish,
+        resolver = resolver,
+        package_metadata = package_metadata,
+        // The harness links to the `rlib`, since it can't link to a `dylib` without a copy of the
+        // standard library.
+        crate_types = if metadata.rlib || metadata.harness.is_some() {
+            "'dylib','rlib'"
+        } else {
+            "'dylib'"
+        },
+        harness = if metadata.harness.is_some() {
+            "bin=[{name='harness',path='harness.rs'}]\n"
+        } else {
+            ""
+        },
+        dependencies = dependencies,
+    ))
 }
 
-struct DeserializePath(RustPath);
-impl<'de> Deserialize<'de> for DeserializePath {
-    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-        struct PathVisitor;
-        impl<'de> Visitor<'de> for PathVisitor {
-            type Value = RustPath;
-            fn expecting(&self, f: &mut Formatter) -> fmt::Result {
-                f.write_str("a Rust path")
+/// Generate the `main` of a binary that runs the `harness` macro on its sample input and prints the
+/// output, if there is one.
+fn generate_harness(metadata: &Metadata, crate_name: &str) -> Result<Option<String>> {
+    let Some(harness) = &metadata.harness else {
+        return Ok(None);
+    };
+
+    let Some((_, mac, macro_type)) = metadata
+        .exports
+        .all_macros()
+        .find(|(name, _, _)| **name == harness.macro_name.0)
+    else {
+        return Err(Error::Metadata(format!(
+            "The harness macro `{}` is not exported",
+            harness.macro_name.0
+        ))
+        .at(metadata.spans.get(&["harness", "macro"])));
+    };
+    if !mac.proc_macro2 {
+        return Err(Error::Metadata(format!(
+            "The harness macro `{}` must use `proc_macro2: true`, since `proc_macro` can only be \
+             used inside a procedural macro",
+            harness.macro_name.0
+        ))
+        .at(metadata.spans.get(&["harness", "macro"])));
+    }
+
+    let Some(function) = &mac.function else {
+        return Err(Error::Metadata(format!(
+            "The harness macro `{}` is a passthrough macro, so there is nothing to run",
+            harness.macro_name.0
+        ))
+        .at(metadata.spans.get(&["harness", "macro"])));
+    };
+    let function = &function.0;
+    let crate_name = Ident::new(crate_name, Span::call_site());
+    let proc_macro2 = proc_macro2_crate(metadata)?;
+    let input = &harness.input;
+    // There is no invocation, so the harness passes its own crate and location.
+    let host_crate = mac.host_crate.then(|| quote!(, parse("crate")));
+    let location = mac
+        .location
+        .then(|| quote!(, file!(), line!() as usize, column!() as usize));
+    // The `parse` type is inferred, since its path is relative to the library and not the harness.
+    let [ron, serde_json] = format_crates(metadata)?;
+    let input = match (syn_crate(metadata)?, &mac.deserialize) {
+        (Some(syn), _) if mac.parse.is_some() => {
+            quote!(::#syn::parse2(parse(#input)).expect("invalid harness input"))
+        }
+        // The harness input is deserialized directly, without going through tokens.
+        (_, Some(deserialize)) => {
+            let format_crate = match deserialize.format {
+                InputFormat::Ron => ron,
+                InputFormat::Json => serde_json,
+            };
+            quote!(::#format_crate::from_str(#input).expect("invalid harness input"))
+        }
+        _ => quote!(parse(#input)),
+    };
+    let call = if macro_type == "attribute" {
+        let attr = &harness.attr;
+        quote!(#crate_name::#function(parse(#attr), #input #host_crate #location))
+    } else {
+        quote!(#crate_name::#function(#input #host_crate #location))
+    };
+    let call = if mac.macro_output {
+        quote! {{
+            let output = #call;
+            for diagnostic in &output.diagnostics {
+                let level = match diagnostic.level {
+                    crate::inline_proc::Level::Error => "error",
+                    crate::inline_proc::Level::Warning => "warning",
+                };
+                ::std::eprintln!("{}: {}", level, diagnostic.message);
             }
-            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
-                syn::parse_str(v).map_err(|_| E::invalid_value(Unexpected::Str(v), &self))
+            output.tokens
+        }}
+    } else {
+        call
+    };
+
+    Ok(Some(
+        quote! {
+            #[allow(dead_code)]
+            fn parse(tokens: &str) -> ::#proc_macro2::TokenStream {
+                tokens.parse().expect("invalid harness input")
+            }
+
+            fn main() {
+                ::std::println!("{}", #call);
             }
         }
-        deserializer.deserialize_str(PathVisitor).map(Self)
+        .to_string(),
+    ))
+}
+
+/// Generate a build script that links the native libraries in `links` and `link_search`, if there
+/// are any.
+fn generate_build_rs(metadata: &Metadata) -> Result<Option<String>> {
+    if metadata.links.is_empty() && metadata.link_search.is_empty() {
+        return Ok(None);
+    }
+
+    const LIB_KINDS: &[&str] = &["static", "dylib", "framework"];
+    const SEARCH_KINDS: &[&str] = &["dependency", "crate", "native", "framework", "all"];
+
+    let mut build_rs = "fn main() {\n".to_owned();
+    let directives = metadata
+        .links
+        .iter()
+        .enumerate()
+        .map(|(i, lib)| ("links", "rustc-link-lib", i, lib, LIB_KINDS))
+        .chain(
+            metadata
+                .link_search
+                .iter()
+                .enumerate()
+                .map(|(i, path)| ("link_search", "rustc-link-search", i, path, SEARCH_KINDS)),
+        );
+    for (option, directive, i, value, kinds) in directives {
+        validate_link_option(option, value, kinds)
+            .map_err(|e| e.at(metadata.spans.get(&[option, &i.to_string()])))?;
+        writeln!(
+            build_rs,
+            "    println!(\"cargo:{}={{}}\", {:?});",
+            directive, value
+        )
+        .unwrap();
     }
+    build_rs.push_str("}\n");
+
+    Ok(Some(build_rs))
 }
 
-/// A string made from writing out tokens.
-#[derive(Default)]
-struct TokenString {
-    /// The span for each byte of the string.
-    byte_spans: Vec<Span>,
-    /// The span for each char of the string.
-    char_spans: Vec<Span>,
-    /// The string itself.
-    tokens: String,
-    /// Whether the previous token was a punctuation token and joint.
-    joint: bool,
+/// Check a `links` or `link_search` entry of the form `[KIND[:MODIFIERS]=]VALUE`.
+fn validate_link_option(option: &str, value: &str, kinds: &[&str]) -> Result<()> {
+    let invalid = |reason: &str| {
+        Err(Error::Metadata(format!(
+            "Invalid {} entry `{}`: {}",
+            option, value, reason
+        )))
+    };
+
+    let (kind, rest) = match value.split_once('=') {
+        Some((kind, rest)) => (Some(kind), rest),
+        None => (None, value),
+    };
+    if let Some(kind) = kind {
+        let kind = kind.split(':').next().unwrap();
+        if !kinds.contains(&kind) {
+            return invalid(&format!(
+                "unknown kind `{}`, expected one of {}",
+                kind,
+                kinds.join(", ")
+            ));
+        }
+    }
+    if rest.is_empty() {
+        return invalid("it is empty");
+    }
+    // Cargo reads the build script's output line by line.
+    if value.contains(['\n', '\r']) {
+        return invalid("it contains a line break");
+    }
+    Ok(())
 }
 
-impl TokenString {
-    fn from_tokens(tokens: impl ToTokens + std::fmt::Debug) -> Self {
-        let mut this = Self::default();
-        this.push_tokens(tokens);
-        this
+fn generate_lib_rs(metadata: &Metadata, code: TokenStream) -> Result<TokenString> {
+    let mut lib_rs = TokenString::from_tokens(&code);
+
+    if metadata.embed_source {
+        let source = code.to_string();
+        let name = format_ident!("{}source", RESERVED_PREFIX);
+        lib_rs.push_generated(quote! {
+            #[unsafe(no_mangle)]
+            pub static #name: &str = #source;
+        });
     }
-    fn from_token(token: impl Into<TokenTree>) -> Self {
-        let mut this = Self::default();
-        this.push_token(token.into());
-        this
+
+    lib_rs.push_generated(quote!(
+        extern crate proc_macro;
+    ));
+
+    if metadata
+        .exports
+        .all_macros()
+        .any(|(_, mac, _)| mac.macro_output)
+    {
+        lib_rs.push_generated(macro_output_module());
     }
-    fn push(&mut self, item: impl Display, span: Span) {
-        // eprintln!("tokens: {}", self.tokens);
-        let old_len = self.tokens.len();
-        write!(self.tokens, "{}", item).unwrap();
-        eprintln!("old_len: {old_len}");
-        // let written = &self.tokens[old_len..];
-        let written = self.tokens.as_bytes()
-            .get(old_len..)
-            .expect(&format!("failed to get token after {}", self.tokens.as_bytes()[old_len]));
-        // self.byte_spans.extend(written.bytes().map(|_| span));
-        // self.char_spans.extend(written.chars().map(|_| span));
-        self.byte_spans.extend(written.iter().map(|_| span));
-        self.char_spans.extend(String::from_utf8_lossy(written).chars().map(|_| span));
+
+    let syn = syn_crate(metadata)?;
+    let format_crates = format_crates(metadata)?;
+    if format_crates.iter().any(Option::is_some) {
+        lib_rs.push_generated(deserialize_function());
     }
-    fn extend_prev(&mut self, item: impl Display) {
-        self.push(item, *self.byte_spans.last().unwrap());
+    if let Some(proc_macro2) = proc_macro2_crate(metadata)? {
+        lib_rs.push_generated(tokens_module(metadata, &proc_macro2));
     }
 
-    fn push_tokens(&mut self, tokens: impl ToTokens + std::fmt::Debug) {
-        eprintln!("tokens: {:?}", tokens);
-        for token in tokens.into_token_stream() {
-            self.push_token(token);
+    for (name, mac) in &metadata.exports.bang_macros {
+        let Some(function) = &mac.function else {
+            continue;
+        };
+        let function = &function.0;
+        let name = format_ident!("{}bang_{}", RESERVED_PREFIX, name.0);
+        if mac.proc_macro2 {
+            lib_rs.push_generated(string_shim(
+                mac,
+                &name,
+                "bang",
+                function,
+                syn.as_ref(),
+                &format_crates,
+            ));
+            continue;
         }
+        let (extra_params, extra_args) = extra_params(mac);
+        let parse = parse_statement(mac, syn.as_ref(), "input")
+            .or_else(|| deserialize_statement(mac, &format_crates));
+        let body = shim_body(mac, quote!(#function(input #extra_args)));
+        lib_rs.push_generated(quote! {
+            #[unsafe(no_mangle)]
+            pub fn #name(input: ::proc_macro::TokenStream #extra_params) -> ::proc_macro::TokenStream {
+                #parse
+                #body
+            }
+        });
     }
-    fn push_token(&mut self, token: TokenTree) {
-        if !self.joint && !self.tokens.is_empty() {
-            self.extend_prev(" ");
+
+    for (name, mac) in &metadata.exports.derives {
+        let Some(function) = &mac.function else {
+            continue;
+        };
+        let function = &function.0;
+        let name = format_ident!("{}derive_{}", RESERVED_PREFIX, name.0);
+        if mac.proc_macro2 {
+            lib_rs.push_generated(string_shim(
+                mac,
+                &name,
+                "derive",
+                function,
+                syn.as_ref(),
+                &format_crates,
+            ));
+            continue;
         }
+        let (extra_params, extra_args) = extra_params(mac);
+        let parse = parse_statement(mac, syn.as_ref(), "item");
+        let body = shim_body(mac, quote!(#function(item #extra_args)));
+        lib_rs.push_generated(quote! {
+            #[unsafe(no_mangle)]
+            pub fn #name(item: ::proc_macro::TokenStream #extra_params) -> ::proc_macro::TokenStream {
+                #parse
+                #body
+            }
+        });
+    }
 
-        match &token {
-            TokenTree::Group(group) => {
-                let (open, close) = match group.delimiter() {
-                    Delimiter::Parenthesis => ("(", ")"),
-                    Delimiter::Brace => ("{", "}"),
-                    Delimiter::Bracket => ("[", "]"),
-                    Delimiter::None => ("", ""),
+    for (name, mac) in &metadata.exports.attributes {
+        let Some(function) = &mac.function else {
+            continue;
+        };
+        let function = &function.0;
+        let name = format_ident!("{}attribute_{}", RESERVED_PREFIX, name.0);
+        if mac.proc_macro2 {
+            lib_rs.push_generated(string_shim(
+                mac,
+                &name,
+                "attribute",
+                function,
+                syn.as_ref(),
+                &format_crates,
+            ));
+            continue;
+        }
+        let (extra_params, extra_args) = extra_params(mac);
+        let parse = parse_statement(mac, syn.as_ref(), "item");
+        let body = shim_body(mac, quote!(#function(attr, item #extra_args)));
+        lib_rs.push_generated(quote! {
+            #[unsafe(no_mangle)]
+            pub fn #name(attr: ::proc_macro::TokenStream, item: ::proc_macro::TokenStream #extra_params) -> ::proc_macro::TokenStream {
+                #parse
+                #body
+            }
+        });
+    }
+
+    Ok(lib_rs)
+}
+
+/// The name of the `syn` dependency, which is needed by macros with `parse`. Returns `None` if no
+/// macro uses `parse`.
+fn syn_crate(metadata: &Metadata) -> Result<Option<Ident>> {
+    dependency_crate(metadata, "syn", "parse", |mac| mac.parse.is_some())
+}
+
+/// The name of the dependency on `package`, which is needed by macros that `uses` it through the
+/// given option. Returns `None` if no macro uses it.
+fn dependency_crate(
+    metadata: &Metadata,
+    package: &str,
+    option: &str,
+    uses: impl Fn(&Macro) -> bool,
+) -> Result<Option<Ident>> {
+    let Some((name, _, macro_type)) = metadata.exports.all_macros().find(|(_, mac, _)| uses(mac))
+    else {
+        return Ok(None);
+    };
+    let dependency = metadata
+        .dependencies
+        .iter()
+        .find(|(dep_name, dep)| dep.package().unwrap_or(dep_name) == package)
+        .map(|(dep_name, _)| format_ident!("{}", dep_name.replace('-', "_")));
+    match dependency {
+        Some(dependency) => Ok(Some(dependency)),
+        None => Err(Error::Metadata(format!(
+            "Macro `{}` needs `{}` as a dependency to use `{}`",
+            name, package, option
+        ))
+        .at(metadata.spans.get_macro(name, macro_type, option))),
+    }
+}
+
+/// The names of the `ron` and `serde_json` dependencies, which are needed by macros with
+/// `deserialize` in that format.
+fn format_crates(metadata: &Metadata) -> Result<[Option<Ident>; 2]> {
+    let format_crate = |format: InputFormat| {
+        dependency_crate(metadata, format.package(), "deserialize", |mac| {
+            mac.deserialize
+                .as_ref()
+                .is_some_and(|input| input.format == format)
+        })
+    };
+    Ok([
+        format_crate(InputFormat::Ron)?,
+        format_crate(InputFormat::Json)?,
+    ])
+}
+
+/// The statement that deserializes the input of a macro with `deserialize` into its type, returning
+/// the error as a `compile_error!` if it fails.
+fn deserialize_statement(mac: &Macro, format_crates: &[Option<Ident>; 2]) -> Option<TokenStream> {
+    let deserialize = deserialize_closure(mac, format_crates)?;
+    let name = format_ident!("{}deserialize", RESERVED_PREFIX);
+    let error = if mac.macro_output {
+        quote!(crate::inline_proc::MacroOutput::<::proc_macro::TokenStream>::new(error).__encode())
+    } else {
+        quote!(error)
+    };
+    Some(quote! {
+        let input = match #name(input, #deserialize) {
+            ::std::result::Result::Ok(input) => input,
+            ::std::result::Result::Err(error) => return #error,
+        };
+    })
+}
+
+/// The closure that deserializes the source of the input of a macro with `deserialize` into its
+/// type, returning the error message with its line and column if it fails.
+fn deserialize_closure(mac: &Macro, format_crates: &[Option<Ident>; 2]) -> Option<TokenStream> {
+    let DeserializeInput { format, ty } = mac.deserialize.as_deref()?;
+    let ty = &ty.0;
+    Some(match format {
+        InputFormat::Ron => {
+            let ron = &format_crates[0];
+            quote!(|source| ::#ron::from_str::<#ty>(source).map_err(|error| {
+                (error.code.to_string(), error.position.line, error.position.col)
+            }))
+        }
+        InputFormat::Json => {
+            let serde_json = &format_crates[1];
+            quote!(|source| ::#serde_json::from_str::<#ty>(source).map_err(|error| {
+                // The position is in the formatted input, so it's reported with the span instead.
+                let message = error.to_string();
+                let message = match message.rsplit_once(" at line ") {
+                    ::std::option::Option::Some((message, _)) => message.to_owned(),
+                    ::std::option::Option::None => message,
                 };
+                (message, error.line(), error.column())
+            }))
+        }
+    })
+}
 
-                self.push(open, group.span_open());
-                self.push_tokens(group.stream());
-                self.push(close, group.span_close());
+/// The function used by macros with `deserialize` to deserialize their input.
+///
+/// The input is written as a string, keeping the span of each char, with the tokens separated by
+/// the same whitespace as in the code they come from, so that `-1` stays a number and the lines
+/// and columns of errors match the input. The error is reported at the token at its line and
+/// column.
+fn deserialize_function() -> TokenStream {
+    let name = format_ident!("{}deserialize", RESERVED_PREFIX);
+    quote! {
+        #[doc(hidden)]
+        pub fn #name<T>(
+            input: ::proc_macro::TokenStream,
+            deserialize: impl ::std::ops::FnOnce(&str) -> ::std::result::Result<T, (::std::string::String, usize, usize)>,
+        ) -> ::std::result::Result<T, ::proc_macro::TokenStream> {
+            use ::proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+            /// The source written so far, the span of each of its chars and where the last token
+            /// ended, as its line and column.
+            struct Source {
+                text: ::std::string::String,
+                spans: ::std::vec::Vec<Span>,
+                end: ::std::option::Option<(usize, usize)>,
             }
-            TokenTree::Literal(lit) => {
-                let old_len = self.tokens.len();
-                write!(self.tokens, "{}", lit).unwrap();
-                let written = &self.tokens[old_len..];
-                let fallback_span = lit.span();
-                self.byte_spans.extend(
-                    (0..written.len()).map(|i| lit.subspan(i..=i).unwrap_or(fallback_span)),
-                );
-                self.char_spans.extend(
-                    written
-                        .char_indices()
-                        .map(|(i, c)| lit.subspan(i..i + c.len_utf8()).unwrap_or(fallback_span)),
-                );
+
+            impl Source {
+                /// Push the text of a token, separated from the previous token like in the code.
+                fn push(&mut self, s: &str, span: Span, joint: bool) {
+                    let start = span.start();
+                    let (line, column) = (start.line(), start.column());
+                    let separator = match self.end {
+                        ::std::option::Option::Some((end_line, _)) if line > end_line => {
+                            "\n".repeat(line - end_line) + &" ".repeat(column.saturating_sub(1))
+                        }
+                        ::std::option::Option::Some((end_line, end_column)) if line == end_line && column >= end_column => {
+                            " ".repeat(column - end_column)
+                        }
+                        ::std::option::Option::Some(_) if !joint => " ".to_owned(),
+                        _ => ::std::string::String::new(),
+                    };
+                    let separator_span = self.spans.last().copied().unwrap_or(span);
+                    self.spans.extend(separator.chars().map(|_| separator_span));
+                    self.text.push_str(&separator);
+                    self.text.push_str(s);
+                    self.spans.extend(s.chars().map(|_| span));
+                    let end = span.end();
+                    self.end = ::std::option::Option::Some((end.line(), end.column()));
+                }
+
+                fn push_tokens(&mut self, tokens: TokenStream) {
+                    let mut joint = false;
+                    for token in tokens {
+                        match &token {
+                            TokenTree::Group(group) => {
+                                let (open, close) = match group.delimiter() {
+                                    Delimiter::Parenthesis => ("(", ")"),
+                                    Delimiter::Brace => ("{", "}"),
+                                    Delimiter::Bracket => ("[", "]"),
+                                    Delimiter::None => ("", ""),
+                                };
+                                self.push(open, group.span_open(), joint);
+                                self.push_tokens(group.stream());
+                                self.push(close, group.span_close(), false);
+                            }
+                            _ => self.push(&token.to_string(), token.span(), joint),
+                        }
+                        joint = matches!(&token, TokenTree::Punct(punct) if punct.spacing() == Spacing::Joint);
+                    }
+                }
             }
-            _ => {
-                self.push(&token, token.span());
+
+            let mut source = Source {
+                text: ::std::string::String::new(),
+                spans: ::std::vec::Vec::new(),
+                end: ::std::option::Option::None,
+            };
+            source.push_tokens(input);
+            let Source { text: source, spans, .. } = source;
+
+            deserialize(&source).map_err(|(message, line, column)| {
+                let index = source
+                    .split('\n')
+                    .take(line.saturating_sub(1))
+                    .map(|line| line.chars().count() + 1)
+                    .sum::<usize>()
+                    + column.saturating_sub(1);
+                let span = spans
+                    .get(index)
+                    .or(spans.last())
+                    .copied()
+                    .unwrap_or_else(Span::call_site);
+                let tokens: [TokenTree; 8] = [
+                    Punct::new(':', Spacing::Joint).into(),
+                    Punct::new(':', Spacing::Alone).into(),
+                    Ident::new("core", span).into(),
+                    Punct::new(':', Spacing::Joint).into(),
+                    Punct::new(':', Spacing::Alone).into(),
+                    Ident::new("compile_error", span).into(),
+                    Punct::new('!', Spacing::Alone).into(),
+                    Group::new(Delimiter::Parenthesis, TokenTree::from(Literal::string(&message)).into()).into(),
+                ];
+                tokens
+                    .into_iter()
+                    .map(|mut token| {
+                        token.set_span(span);
+                        token
+                    })
+                    .collect()
+            })
+        }
+    }
+}
+
+/// The statement that parses the input of a macro with `parse` into its `syn` type, returning the
+/// parse error as a `compile_error!` if it fails.
+fn parse_statement(mac: &Macro, syn: Option<&Ident>, input: &str) -> Option<TokenStream> {
+    let ty = &mac.parse.as_ref()?.0;
+    let input = format_ident!("{}", input);
+    let error = if mac.macro_output {
+        quote!(
+            crate::inline_proc::MacroOutput::<::proc_macro::TokenStream>::new(
+                error.to_compile_error().into()
+            )
+            .__encode()
+        )
+    } else {
+        quote!(error.to_compile_error().into())
+    };
+    Some(quote! {
+        let #input = match ::#syn::parse::<#ty>(#input) {
+            ::std::result::Result::Ok(parsed) => parsed,
+            ::std::result::Result::Err(error) => return #error,
+        };
+    })
+}
+
+/// The body of the exported function for a macro, converting the result of `call` into a
+/// `proc_macro::TokenStream`.
+fn shim_body(mac: &Macro, call: TokenStream) -> TokenStream {
+    if mac.macro_output {
+        quote!(#call.__encode())
+    } else {
+        call
+    }
+}
+
+/// The `inline_proc` module added to the inner crate for macros with `macro_output`.
+///
+/// `MacroOutput` is encoded as a token stream to pass it to `invoke_inline_macro!`: the output in
+/// braces, followed by the level, message and an identifier with the span of each diagnostic.
+/// Macros with `proc_macro2` use `proc_macro2` spans, and are encoded by `tokens_module` instead.
+fn macro_output_module() -> TokenStream {
+    quote! {
+        /// Types for macros with `macro_output`, which report diagnostics along with their output.
+        pub mod inline_proc {
+            use ::proc_macro::{Delimiter, Group, Ident, Literal, Span, TokenStream, TokenTree};
+
+            /// The output of a macro, along with diagnostics to report.
+            pub struct MacroOutput<T = TokenStream, S = Span> {
+                pub tokens: T,
+                pub diagnostics: ::std::vec::Vec<Diagnostic<S>>,
+            }
+
+            impl<T, S> MacroOutput<T, S> {
+                /// Output `tokens` without any diagnostics.
+                pub fn new(tokens: T) -> Self {
+                    Self { tokens, diagnostics: ::std::vec::Vec::new() }
+                }
+
+                /// Add a warning. Like all proc macro warnings, it's only shown on nightly.
+                pub fn warning(mut self, span: S, message: impl ::std::string::ToString) -> Self {
+                    self.diagnostics.push(Diagnostic { level: Level::Warning, span, message: message.to_string() });
+                    self
+                }
+
+                /// Add an error. The output is still used.
+                pub fn error(mut self, span: S, message: impl ::std::string::ToString) -> Self {
+                    self.diagnostics.push(Diagnostic { level: Level::Error, span, message: message.to_string() });
+                    self
+                }
+            }
+
+            impl<T: ::std::convert::Into<TokenStream>> MacroOutput<T> {
+                #[doc(hidden)]
+                pub fn __encode(self) -> TokenStream {
+                    let mut encoded = ::std::vec![TokenTree::from(Group::new(Delimiter::Brace, self.tokens.into()))];
+                    for diagnostic in self.diagnostics {
+                        let level = match diagnostic.level {
+                            Level::Error => "error",
+                            Level::Warning => "warning",
+                        };
+                        encoded.push(Ident::new(level, Span::call_site()).into());
+                        encoded.push(Literal::string(&diagnostic.message).into());
+                        encoded.push(Ident::new("span", diagnostic.span).into());
+                    }
+                    encoded.into_iter().collect()
+                }
+            }
+
+            /// A diagnostic reported by a macro.
+            pub struct Diagnostic<S = Span> {
+                pub level: Level,
+                pub span: S,
+                pub message: ::std::string::String,
+            }
+
+            /// The level of a diagnostic.
+            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+            pub enum Level {
+                Error,
+                Warning,
+            }
+        }
+    }
+}
+
+/// The exported function for a macro with `proc_macro2`.
+///
+/// The `proc_macro` API can only be used inside a procedural macro, which the inner crate isn't, so
+/// these macros only use `proc_macro2` in its fallback mode. Their input is passed as source code
+/// split into parts (the input, or the attribute and item, followed by the host crate), and their
+/// output is returned encoded by `tokens_module`, with spans as byte ranges of the source.
+fn string_shim(
+    mac: &Macro,
+    name: &Ident,
+    macro_type: &str,
+    function: &RustPath,
+    syn: Option<&Ident>,
+    format_crates: &[Option<Ident>; 2],
+) -> TokenStream {
+    let module = format_ident!("{}tokens", RESERVED_PREFIX);
+    let encode = |output: TokenStream| quote!(crate::#module::encode(&file, #output));
+
+    let mut params = TokenStream::new();
+    let mut args = TokenStream::new();
+    let mut statements = TokenStream::new();
+    if macro_type == "attribute" {
+        statements.extend(quote!(let attr = parts.next().unwrap_or_default();));
+        args.extend(quote!(attr,));
+    }
+    let input = if macro_type == "bang" {
+        quote!(input)
+    } else {
+        quote!(item)
+    };
+    statements.extend(quote!(let #input = parts.next().unwrap_or_default();));
+    if let (Some(syn), Some(ty)) = (syn, &mac.parse) {
+        let ty = &ty.0;
+        let error = encode(quote!(error.to_compile_error()));
+        statements.extend(quote! {
+            let #input = match ::#syn::parse2::<#ty>(#input) {
+                ::std::result::Result::Ok(parsed) => parsed,
+                ::std::result::Result::Err(error) => return #error,
+            };
+        });
+    } else if let Some(deserialize) = deserialize_closure(mac, format_crates) {
+        statements.extend(quote! {
+            let #input = match crate::#module::deserialize(source, ranges[0].clone(), #deserialize) {
+                ::std::result::Result::Ok(input) => input,
+                ::std::result::Result::Err(error) => return error,
+            };
+        });
+    }
+    args.extend(input);
+    if mac.host_crate {
+        statements.extend(quote!(let host_crate = parts.next().unwrap_or_default();));
+        args.extend(quote!(, host_crate));
+    }
+    if mac.location {
+        params.extend(quote!(, file_name: &str, line: usize, column: usize));
+        args.extend(quote!(, file_name, line, column));
+    }
+
+    let output = if mac.macro_output {
+        quote!(#function(#args).__encode_string(&file))
+    } else {
+        encode(quote!(#function(#args)))
+    };
+    quote! {
+        #[unsafe(no_mangle)]
+        pub fn #name(source: &str, ranges: &[::std::ops::Range<usize>] #params) -> ::std::string::String {
+            crate::#module::catch_panic(|| {
+                let (file, parts) = crate::#module::parts(source, ranges);
+                let mut parts = parts.into_iter();
+                #statements
+                #output
+            })
+        }
+    }
+}
+
+/// The name of the `proc-macro2` dependency, which is needed by macros with `proc_macro2`. Returns
+/// `None` if no macro uses it.
+fn proc_macro2_crate(metadata: &Metadata) -> Result<Option<Ident>> {
+    dependency_crate(metadata, "proc-macro2", "proc_macro2", |mac| {
+        mac.proc_macro2 && mac.function.is_some()
+    })
+}
+
+/// The module added to the inner crate for macros with `proc_macro2`, which parses their input and
+/// encodes their output for `invoke_inline_macro!`.
+///
+/// Each token of the output is encoded as its kind, the byte range of its span in the source and
+/// its text, separated by spaces: `G(`, `G[`, `G{` or `G_` followed by the tokens of the group and
+/// `E`; `I` for identifiers; `P` for punctuation, with `J` or `A` for its spacing; and `L` for
+/// literals, whose text is preceded by its length. Spans that aren't in the source, such as
+/// `Span::call_site()`, are encoded as `0 0`. Errors and warnings follow as `X` and `W` with the
+/// range of their span and their message.
+fn tokens_module(metadata: &Metadata, proc_macro2: &Ident) -> TokenStream {
+    let module = format_ident!("{}tokens", RESERVED_PREFIX);
+    let macro_output = metadata
+        .exports
+        .all_macros()
+        .any(|(_, mac, _)| mac.proc_macro2 && mac.macro_output)
+        .then(|| {
+            quote! {
+                impl<T: ::std::convert::Into<TokenStream>> crate::inline_proc::MacroOutput<T, Span> {
+                    #[doc(hidden)]
+                    pub fn __encode_string(self, file: &Option<String>) -> String {
+                        let mut output = encode(file, self.tokens.into());
+                        for diagnostic in self.diagnostics {
+                            let error = diagnostic.level == crate::inline_proc::Level::Error;
+                            push_diagnostic(&mut output, file, error, diagnostic.span, &diagnostic.message);
+                        }
+                        output
+                    }
+                }
+            }
+        });
+    quote! {
+        #[doc(hidden)]
+        pub mod #module {
+            use ::#proc_macro2::{Delimiter, Spacing, Span, TokenStream, TokenTree};
+            use ::std::fmt::Write as _;
+            use ::std::ops::Range;
+            use ::std::option::Option;
+            use ::std::result::Result;
+            use ::std::string::{String, ToString};
+            use ::std::vec::Vec;
+
+            /// Parse the source passed by `invoke_inline_macro!` and split it into its parts. The
+            /// name `proc_macro2` gave the parsed source is returned too, to tell its spans apart
+            /// from others.
+            pub fn parts(source: &str, ranges: &[Range<usize>]) -> (Option<String>, Vec<TokenStream>) {
+                let tokens: TokenStream = source.parse().expect("invalid macro input");
+                let mut file = Option::None;
+                let mut parts: Vec<TokenStream> = ranges.iter().map(|_| TokenStream::new()).collect();
+                for token in tokens {
+                    let span = token.span();
+                    file.get_or_insert_with(|| span.file());
+                    let start = span.byte_range().start;
+                    if let Option::Some(i) = ranges.iter().position(|range| range.contains(&start)) {
+                        parts[i].extend([token]);
+                    }
+                }
+                (file, parts)
             }
-        }
-
-        self.joint = matches!(token, TokenTree::Punct(p) if p.spacing() == Spacing::Joint);
-    }
-}
 
-fn generate_cargo_toml(metadata: &Metadata) -> String {
-    #[derive(serde::Serialize)]
-    struct DependenciesWrapper<'a> {
-        dependencies: &'a cargo_toml::DepsSet,
-    }
+            /// Encode the output of a macro.
+            pub fn encode(file: &Option<String>, tokens: TokenStream) -> String {
+                let mut output = String::new();
+                push_tokens(&mut output, file, tokens);
+                output
+            }
 
-    let dependencies = toml::to_string(&DependenciesWrapper {
-        dependencies: &metadata.dependencies,
-    })
-    .unwrap();
+            fn push_tokens(output: &mut String, file: &Option<String>, tokens: TokenStream) {
+                for token in tokens {
+                    match token {
+                        TokenTree::Group(group) => {
+                            output.push_str(match group.delimiter() {
+                                Delimiter::Parenthesis => "G( ",
+                                Delimiter::Bracket => "G[ ",
+                                Delimiter::Brace => "G{ ",
+                                Delimiter::None => "G_ ",
+                            });
+                            push_span(output, file, group.span());
+                            push_tokens(output, file, group.stream());
+                            output.push_str("E ");
+                        }
+                        TokenTree::Ident(ident) => {
+                            output.push_str("I ");
+                            push_span(output, file, ident.span());
+                            write!(output, "{} ", ident).unwrap();
+                        }
+                        TokenTree::Punct(punct) => {
+                            output.push_str("P ");
+                            push_span(output, file, punct.span());
+                            let spacing = match punct.spacing() {
+                                Spacing::Joint => 'J',
+                                Spacing::Alone => 'A',
+                            };
+                            write!(output, "{}{} ", punct.as_char(), spacing).unwrap();
+                        }
+                        TokenTree::Literal(literal) => {
+                            output.push_str("L ");
+                            push_span(output, file, literal.span());
+                            push_text(output, &literal.to_string());
+                        }
+                    }
+                }
+            }
 
-    format!(
-        "\
-package={{name='inline-proc-macro',version='0.0.0',edition='{edition}'}}
-lib={{crate-type=['dylib'],path='lib.rs'}}
-{dependencies}\
-        ",
-        edition = metadata.edition,
-        dependencies = dependencies,
-    )
-}
+            fn push_span(output: &mut String, file: &Option<String>, span: Span) {
+                let range = if file.as_ref() == Option::Some(&span.file()) {
+                    span.byte_range()
+                } else {
+                    0..0
+                };
+                write!(output, "{} {} ", range.start, range.end).unwrap();
+            }
 
-fn generate_lib_rs(metadata: &Metadata, mut code: TokenStream) -> TokenStream {
-    code.extend(quote!(
-        extern crate proc_macro;
-    ));
+            fn push_text(output: &mut String, text: &str) {
+                write!(output, "{} {} ", text.len(), text).unwrap();
+            }
 
-    for (name, mac) in &metadata.exports.bang_macros {
-        let function = &mac.function.0;
-        let name = format_ident!("__exported_macro_bang_{}", name.0);
-        code.extend(quote! {
-            #[unsafe(no_mangle)]
-            pub fn #name(input: ::proc_macro::TokenStream) -> ::proc_macro::TokenStream {
-                #function(input)
+            /// Call a macro, encoding a panic in it as an error. The panic can't unwind into the
+            /// compiler, which has its own copy of the standard library, so it must be caught here.
+            /// The compiler's panic hook, which would report it as a bug in the compiler, is
+            /// replaced while the macro runs.
+            pub fn catch_panic(call: impl ::std::ops::FnOnce() -> String) -> String {
+                let hook = ::std::panic::take_hook();
+                ::std::panic::set_hook(::std::boxed::Box::new(|_| {}));
+                let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(call));
+                ::std::panic::set_hook(hook);
+                let payload = match result {
+                    Result::Ok(output) => return output,
+                    Result::Err(payload) => payload,
+                };
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "Box<dyn Any>".to_string());
+                let mut output = String::new();
+                output.push_str("X 0 0 ");
+                push_text(&mut output, &::std::format!("The macro panicked: {}", message));
+                output
             }
-        });
-    }
 
-    for (name, mac) in &metadata.exports.derives {
-        let function = &mac.function.0;
-        let name = format_ident!("__exported_macro_derive_{}", name.0);
-        code.extend(quote! {
-            #[unsafe(no_mangle)]
-            pub fn #name(item: ::proc_macro::TokenStream) -> ::proc_macro::TokenStream {
-                #function(item)
+            /// Encode an error or warning reported at the span.
+            pub fn push_diagnostic(output: &mut String, file: &Option<String>, error: bool, span: Span, message: &str) {
+                output.push_str(if error { "X " } else { "W " });
+                push_span(output, file, span);
+                push_text(output, message);
             }
-        });
-    }
 
-    for (name, mac) in &metadata.exports.attributes {
-        let function = &mac.function.0;
-        let name = format_ident!("__exported_macro_attribute_{}", name.0);
-        code.extend(quote! {
-            #[unsafe(no_mangle)]
-            pub fn #name(attr: ::proc_macro::TokenStream, item: ::proc_macro::TokenStream) -> ::proc_macro::TokenStream {
-                #function(attr, item)
+            /// Deserialize the part of the source in the range, encoding the error at its line and
+            /// column if it fails.
+            pub fn deserialize<T>(
+                source: &str,
+                range: Range<usize>,
+                deserialize: impl ::std::ops::FnOnce(&str) -> Result<T, (String, usize, usize)>,
+            ) -> Result<T, String> {
+                let input = &source[range.clone()];
+                deserialize(input).map_err(|(message, line, column)| {
+                    let line_start: usize = input
+                        .split('\n')
+                        .take(line.saturating_sub(1))
+                        .map(|line| line.len() + 1)
+                        .sum();
+                    let line = input.get(line_start..).unwrap_or_default();
+                    let line = line.split('\n').next().unwrap_or_default();
+                    let column = line
+                        .char_indices()
+                        .nth(column.saturating_sub(1))
+                        .map_or(line.len(), |(i, _)| i);
+                    let start = (range.start + line_start + column).min(range.end.saturating_sub(1));
+                    let mut output = String::new();
+                    write!(output, "X {} {} ", start, (start + 1).min(range.end)).unwrap();
+                    push_text(&mut output, &message.to_string());
+                    output
+                })
             }
-        });
+
+            #macro_output
+        }
     }
+}
 
-    code
+/// The extra parameters of an exported function for a macro with `host_crate` or `location`, and
+/// the arguments that pass them on to the macro function.
+fn extra_params(mac: &Macro) -> (TokenStream, TokenStream) {
+    let mut params = TokenStream::new();
+    let mut args = TokenStream::new();
+    if mac.host_crate {
+        params.extend(quote!(, host_crate: ::proc_macro::TokenStream));
+        args.extend(quote!(, host_crate));
+    }
+    if mac.location {
+        params.extend(quote!(, file: &str, line: usize, column: usize));
+        args.extend(quote!(, file, line, column));
+    }
+    (params, args)
 }
 
-fn cargo_diagnostic_to_diagnostic(cargo: CargoDiagnostic, source: &TokenStream) -> Diagnostic {
+/// The editions of Rust, from oldest to newest, to find the edition that a diagnostic needs.
+const EDITIONS: &[&str] = &["2015", "2018", "2021", "2024"];
+
+fn cargo_diagnostic_to_diagnostic(
+    cargo: CargoDiagnostic,
+    source: &TokenString,
+    style: DiagnosticsStyle,
+    edition: &str,
+    pretty_printer: Option<&[String]>,
+) -> Diagnostic {
+    let generated_code = pretty_printer.and_then(|pretty_printer| {
+        let span = cargo
+            .spans
+            .iter()
+            .find(|span| span.is_primary)
+            .or_else(|| cargo.spans.first())?;
+        let code = source.generated_code(span.byte_start as usize)?;
+        Some(pretty_print(code, pretty_printer))
+    });
     let mut diagnostic = Diagnostic::spanned(
         cargo_spans_to_span(&cargo.spans, source),
         match cargo.level {
             CargoLevel::Ice | CargoLevel::Error => Level::Error,
             _ => Level::Warning,
         },
-        cargo.message,
+        strip_control_codes(&cargo.message),
     );
 
+    if style == DiagnosticsStyle::Short {
+        return diagnostic;
+    }
+
+    let required_edition = required_edition(&cargo, edition);
     for child in cargo.children {
+        // Rustc suggests editing `Cargo.toml`, which is generated from the metadata.
+        if required_edition.is_some() && is_edition_suggestion(&child.message) {
+            continue;
+        }
         diagnostic = match child.level {
             CargoLevel::Help => Diagnostic::span_help,
             CargoLevel::Note => Diagnostic::span_note,
@@ -524,56 +4203,895 @@ fn cargo_diagnostic_to_diagnostic(cargo: CargoDiagnostic, source: &TokenStream)
         }(
             diagnostic,
             cargo_spans_to_span(&child.spans, source),
-            child.message,
+            strip_control_codes(&child.message),
         );
     }
+    if let Some(required_edition) = required_edition {
+        diagnostic = diagnostic.help(format!(
+            "the inner crate is built with edition {}; set `edition: \"{}\"` in the metadata of \
+             the module to use this",
+            edition, required_edition
+        ));
+    }
+    if let Some(code) = generated_code {
+        diagnostic = diagnostic.note(format!(
+            "this is in code generated by `inline_proc`:\n{}",
+            code
+        ));
+    }
 
     diagnostic
 }
 
-fn cargo_spans_to_span(spans: &[CargoSpan], source: &TokenStream) -> Span {
-    let source = source.to_string().as_bytes().iter().map(||);
+/// Format generated code with the `pretty_printer` command, which reads the code from stdin and
+/// writes it formatted to stdout. The code is left as it is without a command, or if the command
+/// fails.
+fn pretty_print(code: &str, pretty_printer: &[String]) -> String {
+    let Some((program, args)) = pretty_printer.split_first() else {
+        return code.to_owned();
+    };
+    let formatted = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .and_then(|mut child| {
+            io::Write::write_all(&mut child.stdin.take().unwrap(), code.as_bytes())?;
+            child.wait_with_output()
+        });
+    match formatted {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .trim_end()
+            .to_owned(),
+        _ => code.to_owned(),
+    }
+}
+
+/// The edition needed to fix a diagnostic about syntax from a newer edition than `edition`, if it
+/// is one. Rustc reports these as "... in Rust 2015", "... Rust 2021 or later", or with a
+/// suggestion to set the edition in `Cargo.toml`.
+fn required_edition(cargo: &CargoDiagnostic, edition: &str) -> Option<&'static str> {
+    let messages = || iter::once(&cargo.message).chain(cargo.children.iter().map(|c| &c.message));
+    let newer = |required: &str| {
+        EDITIONS
+            .iter()
+            .copied()
+            .find(|&candidate| candidate == required && candidate > edition)
+    };
+
+    messages()
+        .flat_map(|message| {
+            message
+                .match_indices("Rust ")
+                .map(move |(i, _)| &message[i + 5..])
+        })
+        .find_map(|rest| {
+            let year = rest.get(..4)?;
+            if rest[4..].starts_with(" or later") {
+                newer(year)
+            } else if year == edition {
+                // "... is not permitted in Rust 2015": the next edition is the first to allow it.
+                EDITIONS
+                    .iter()
+                    .copied()
+                    .find(|&candidate| candidate > edition)
+            } else {
+                None
+            }
+        })
+        .or_else(|| {
+            messages().find_map(|message| {
+                let rest = message.strip_prefix("set `edition = \"")?;
+                newer(rest.get(..4)?)
+            })
+        })
+}
+
+/// Whether a child of a diagnostic is rustc's suggestion to change the edition outside of the
+/// metadata.
+fn is_edition_suggestion(message: &str) -> bool {
+    message.starts_with("set `edition = ") || message.starts_with("pass `--edition ")
+}
+
+/// Remove terminal control codes, such as colors and the redrawing of progress bars, from captured
+/// output so that it can be shown as plain text in diagnostics.
+///
+/// Text before a carriage return on the same line is removed too, since progress bars use it to
+/// redraw the line.
+fn strip_control_codes(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut line_start = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // Control Sequence Introducer: parameters and intermediates up to a final byte.
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // Operating System Command: up to BEL or String Terminator.
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || c == '\x1b' && chars.next_if_eq(&'\\').is_some() {
+                            break;
+                        }
+                    }
+                }
+                // Other escape sequences are one character long.
+                _ => {}
+            },
+            '\r' if chars.peek() != Some(&'\n') => stripped.truncate(line_start),
+            '\n' => {
+                stripped.push('\n');
+                line_start = stripped.len();
+            }
+            '\t' => stripped.push('\t'),
+            c if c.is_control() => {}
+            c => stripped.push(c),
+        }
+    }
+    stripped
+}
+
+fn cargo_spans_to_span(spans: &[CargoSpan], source: &TokenString) -> Span {
     spans
         .iter()
         .find(|span| span.is_primary)
         .or_else(|| spans.first())
-        .map(|span| {
-            let start = source[span.byte_start as usize];
-            start
-                .join(source[span.byte_end as usize])
-                .unwrap_or(start)
+        .and_then(|span| {
+            let start = *source.byte_spans.get(span.byte_start as usize)?;
+            let end = source
+                .byte_spans
+                .get((span.byte_end as usize).saturating_sub(1))
+                .copied()
+                .unwrap_or(start);
+            Some(start.join(end).unwrap_or(start))
         })
         .unwrap_or_else(Span::call_site)
 }
 
-fn generate_user_macros(metadata: &Metadata, dylib_path: &str) -> TokenStream {
+/// Generate macros that stand in for the user-facing macros when the inner crate fails to build,
+/// so that every use of them doesn't cause another error. Attribute macros return their item
+/// unchanged, and bang and derive macros expand to nothing.
+fn generate_placeholder_macros(metadata: &Metadata) -> TokenStream {
     metadata
         .exports
         .all_macros()
         .map(|(name, mac, macro_type)| {
-            let macro_type = Ident::new(macro_type, Span::call_site());
-
-            if mac.export {
-                let name_inner = format_ident!("{}_inner", name);
-                quote! {
-                    #[macro_export]
-                    #[doc(hidden)]
-                    macro_rules! #name_inner {
-                        ($inline_proc:path, $($tokens:tt)*) => {
-                            $inline_proc!(#dylib_path #name #macro_type $($tokens)*);
-                        }
-                    }
-                }
+            let (input, output) = if mac.passthrough || macro_type == "attribute" {
+                passthrough_rule(mac, macro_type)
             } else {
-                let inline_proc_path = &metadata.inline_proc_path.0;
-                quote! {
-                    macro_rules! #name {
-                        ($($tokens:tt)*) => {
-                            #inline_proc_path::invoke_inline_macro!(#dylib_path #name #macro_type $($tokens)*);
-                        }
-                    }
-                }
+                (quote!($($tokens:tt)*), quote!())
+            };
+            generate_macro_rules(metadata, name, mac, macro_type, input, |_, _| {
+                output.clone()
+            })
+        })
+        .collect()
+}
+
+/// The input pattern and expansion of a passthrough macro: bang macros expand to their input, and
+/// attribute macros to their item.
+fn passthrough_rule(mac: &Macro, macro_type: &str) -> (TokenStream, TokenStream) {
+    if let Some(input) = fragment_input(mac) {
+        input
+    } else if macro_type == "attribute" {
+        (quote!(($($attr:tt)*) $($item:tt)*), quote!($($item)*))
+    } else {
+        (quote!($($tokens:tt)*), quote!($($tokens)*))
+    }
+}
+
+/// The input pattern of a bang macro with `input_fragment`, which matches a single fragment of that
+/// kind, and the input it passes on.
+fn fragment_input(mac: &Macro) -> Option<(TokenStream, TokenStream)> {
+    let fragment = Ident::new(mac.input_fragment.as_ref()?, Span::call_site());
+    Some((quote!($input:#fragment), quote!($input)))
+}
+
+/// Generate the `macro_rules!` macro of an export, along with its wrapper or re-export. `output`
+/// makes the expansion from the path of `invoke_inline_macro!`, which exported macros take before
+/// `input`, and from the depth of the expansion if the input was marked with it.
+///
+/// Bang macros that call a function also accept input marked with `__inline_proc_depth(n)`, which
+/// `invoke_inline_macro!` adds to the invocations of a macro in its own output, so that it can
+/// limit how deep the macro expands to itself.
+fn generate_macro_rules(
+    metadata: &Metadata,
+    name: &Ident,
+    mac: &Macro,
+    macro_type: &str,
+    input: TokenStream,
+    output: impl Fn(TokenStream, Option<TokenStream>) -> TokenStream,
+) -> TokenStream {
+    let feature_gate = feature_gate_attribute(mac);
+    let doc = mac.doc.as_ref().map(|doc| quote!(#[doc = #doc]));
+    let deprecated = deprecated_attribute(mac);
+
+    // The token whose span is given to the output comes before the input, in every rule so that
+    // the macro's syntax doesn't depend on whether it is a passthrough or placeholder.
+    let input = if mac.spans == SpanMode::Argument {
+        quote!($span:tt, #input)
+    } else {
+        input
+    };
+    // The marked rule comes first, since the input of the other rule may match any tokens.
+    let inputs = [
+        (macro_type == "bang" && !mac.passthrough).then(|| {
+            (
+                quote!(__inline_proc_depth($depth:literal) #input),
+                Some(quote!(depth($depth))),
+            )
+        }),
+        Some((input, None)),
+    ];
+    let inputs = inputs.into_iter().flatten();
+
+    if mac.export {
+        let name_inner = format_ident!("{}_inner", name);
+        let wrapper = generate_wrapper(metadata, name, mac);
+        // The path is matched by its segments, since a `path` fragment can't be the path of a
+        // macro invoked in expression position.
+        let rules = inputs.map(|(input, depth)| {
+            let rooted_output = output(quote!(:: $($inline_proc)::+), depth.clone());
+            let output = output(quote!($($inline_proc)::+), depth);
+            quote! {
+                (:: $($inline_proc:ident)::+, #input) => { #rooted_output };
+                ($($inline_proc:ident)::+, #input) => { #output };
+            }
+        });
+        quote! {
+            #feature_gate
+            #[macro_export]
+            #[doc(hidden)]
+            macro_rules! #name_inner {
+                #(#rules)*
+            }
+            #wrapper
+        }
+    } else {
+        // With the span of its entry in the metadata, the macro counts as written by the user, so
+        // the compiler's `unused_macros` lint reports it if it is never invoked.
+        let mut name = name.clone();
+        if let Some(span) = use_warn_unused(metadata)
+            .then(|| metadata.spans.get_macro(&name, macro_type, ""))
+            .flatten()
+        {
+            name.set_span(span);
+        }
+        let reexport = generate_reexport(&name, mac);
+        let inline_proc_path = &metadata.inline_proc_path.0;
+        let rules = inputs.map(|(input, depth)| {
+            let output = output(quote!(#inline_proc_path::invoke_inline_macro), depth);
+            quote!((#input) => { #output };)
+        });
+        quote! {
+            #feature_gate
+            #doc
+            #deprecated
+            macro_rules! #name {
+                #(#rules)*
+            }
+            #reexport
+        }
+    }
+}
+
+/// Generate the public wrapper of an exported macro that has a `doc` or is `deprecated`, carrying
+/// its documentation and deprecation, or of every exported macro with `reexport_invoker`.
+///
+/// Like a handwritten wrapper, this requires `invoke_inline_macro` to be re-exported at the crate
+/// root, unless `reexport_invoker` re-exports it.
+fn generate_wrapper(metadata: &Metadata, name: &Ident, mac: &Macro) -> Option<TokenStream> {
+    let invoker = match invoker_reexport_path(metadata) {
+        Some(path) => path,
+        None if mac.doc.is_some() || mac.deprecated.is_some() => {
+            quote!($crate::invoke_inline_macro)
+        }
+        None => return None,
+    };
+    let doc = mac.doc.as_ref().map(|doc| quote!(#[doc = #doc]));
+    let name_inner = format_ident!("{}_inner", name);
+    let deprecated = deprecated_attribute(mac);
+    let feature_gate = feature_gate_attribute(mac);
+    Some(quote! {
+        #feature_gate
+        #doc
+        #deprecated
+        #[macro_export]
+        macro_rules! #name {
+            ($($tokens:tt)*) => {
+                $crate::#name_inner! { #invoker, $($tokens)* }
+            };
+        }
+    })
+}
+
+/// The name of the module's re-export of `invoke_inline_macro`, which is unique among the modules
+/// in the same parent.
+fn invoker_reexport_name(metadata: &Metadata) -> Ident {
+    format_ident!("__inline_proc_invoke_{}", metadata.module_name)
+}
+
+/// Generate the hidden re-export of `invoke_inline_macro` next to the macros, if the module has
+/// `reexport_invoker`.
+fn generate_invoker_reexport(metadata: &Metadata) -> Option<TokenStream> {
+    metadata.reexport_invoker.as_ref()?;
+    let inline_proc_path = &metadata.inline_proc_path.0;
+    let name = invoker_reexport_name(metadata);
+    Some(quote! {
+        #[doc(hidden)]
+        pub use #inline_proc_path::invoke_inline_macro as #name;
+    })
+}
+
+/// The path exported macros use to reach the re-export of `invoke_inline_macro`: the
+/// `reexport_invoker` path with `crate` replaced by `$crate`.
+fn invoker_reexport_path(metadata: &Metadata) -> Option<TokenStream> {
+    let parent = &metadata.reexport_invoker.as_ref()?.0;
+    let modules = parent.segments.iter().skip(1).map(|segment| &segment.ident);
+    let name = invoker_reexport_name(metadata);
+    Some(quote!($crate #(::#modules)* ::#name))
+}
+
+/// Generate a `use` of a non-exported macro with its `visibility`, which allows it to be used by
+/// path like any other item. It has the same `feature_gate` as the macro.
+fn generate_reexport(name: &Ident, mac: &Macro) -> Option<TokenStream> {
+    mac.visibility.as_ref().map(|visibility| {
+        let visibility = &visibility.0;
+        let feature_gate = feature_gate_attribute(mac);
+        // Only uses of the re-export should warn, not the re-export itself.
+        let allow = mac
+            .deprecated
+            .is_some()
+            .then(|| quote!(#[allow(deprecated)]));
+        quote!(#feature_gate #allow #visibility use #name;)
+    })
+}
+
+/// Generate the `#[cfg]` attribute of a macro with a `feature_gate`.
+fn feature_gate_attribute(mac: &Macro) -> Option<TokenStream> {
+    mac.feature_gate
+        .as_ref()
+        .map(|feature| quote!(#[cfg(feature = #feature)]))
+}
+
+/// Generate the `#[deprecated]` attribute of a macro with a `deprecated` note.
+fn deprecated_attribute(mac: &Macro) -> Option<TokenStream> {
+    mac.deprecated
+        .as_ref()
+        .map(|note| quote!(#[deprecated(note = #note)]))
+}
+
+fn generate_user_macros(
+    metadata: &Metadata,
+    dylib_path: &str,
+    dist: Option<(&Path, &str)>,
+) -> TokenStream {
+    let dist = dist.map(|(dir, lib_name)| {
+        let dir = dir.to_string_lossy();
+        quote!(dist(#dir, #lib_name))
+    });
+    let lazy_binding = metadata.lazy_binding.then(|| quote!(lazy_binding));
+    let post_process = (metadata.wrap_output.is_some() || !metadata.output_attributes.is_empty())
+        .then(|| {
+            let attributes = metadata.output_attributes.iter().map(|meta| &meta.0);
+            let wrap = metadata.wrap_output.as_ref().map(|name| {
+                let name = &name.0;
+                quote!(mod #name)
+            });
+            quote!(post(#(#[#attributes])* #wrap))
+        });
+
+    metadata
+        .exports
+        .all_macros()
+        .map(|(name, mac, macro_type)| {
+            if mac.passthrough {
+                let (input, output) = passthrough_rule(mac, macro_type);
+                return generate_macro_rules(metadata, name, mac, macro_type, input, |_, _| {
+                    output.clone()
+                });
             }
+
+            let macro_type_ident = Ident::new(macro_type, Span::call_site());
+            let spans = mac.spans.keyword().map(|keyword| {
+                let keyword = Ident::new(keyword, Span::call_site());
+                if mac.spans == SpanMode::Argument {
+                    quote!(#keyword($span))
+                } else {
+                    keyword.into_token_stream()
+                }
+            });
+            let host_crate = mac.host_crate.then(|| quote!(host_crate($crate)));
+            let location = mac.location.then(|| quote!(location));
+            let proc_macro2 = mac.proc_macro2.then(|| quote!(proc_macro2));
+            let include = mac.include.then(|| quote!(include));
+            let macro_output = mac.macro_output.then(|| quote!(macro_output));
+            let output_module = mac.output_module.then(|| quote!(output_module));
+            let (input, tokens) = fragment_input(mac)
+                .unwrap_or_else(|| (quote!($($tokens:tt)*), quote!($($tokens)*)));
+            generate_macro_rules(metadata, name, mac, macro_type, input, |invoker, depth| {
+                quote!(#invoker! { #dylib_path #name #dist #lazy_binding #spans #host_crate #location #proc_macro2 #include #macro_output #output_module #depth #post_process #macro_type_ident #tokens })
+            })
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::ExitStatus;
+    use std::sync::Mutex;
+    use syn::LitStr;
+
+    /// A diagnostic from rustc with a primary span covering the bytes of the source.
+    fn cargo_diagnostic(message: &str, bytes: Range<usize>, children: &str) -> CargoDiagnostic {
+        serde_json::from_str(&format!(
+            r#"{{
+                "message": {message:?},
+                "code": null,
+                "level": "error",
+                "spans": [{{
+                    "file_name": "lib.rs",
+                    "byte_start": {},
+                    "byte_end": {},
+                    "line_start": 1,
+                    "line_end": 1,
+                    "column_start": 1,
+                    "column_end": 1,
+                    "is_primary": true,
+                    "text": [],
+                    "label": null,
+                    "suggested_replacement": null,
+                    "suggestion_applicability": null,
+                    "expansion": null
+                }}],
+                "children": [{children}],
+                "rendered": null
+            }}"#,
+            bytes.start, bytes.end,
+        ))
+        .unwrap()
+    }
+
+    /// The source of an inner crate with a function written by the user and generated code after
+    /// it, and the byte ranges of the two.
+    fn source_with_generated_code() -> (TokenString, Range<usize>, Range<usize>) {
+        let mut source = TokenString::from_tokens(quote!(
+            fn user() {}
+        ));
+        let user = 0..source.tokens.len();
+        source.push_generated(quote!(
+            const GENERATED: u8 = 1;
+        ));
+        let generated = user.end..source.tokens.len();
+        (source, user, generated)
+    }
+
+    #[test]
+    fn generated_code_is_found_by_byte() {
+        let (source, user, generated) = source_with_generated_code();
+        assert_eq!(source.generated_code(user.start), None);
+        assert_eq!(
+            source.generated_code(generated.start + 2),
+            Some("const GENERATED : u8 = 1 ;")
+        );
+    }
+
+    #[test]
+    fn diagnostic_in_generated_code_shows_it() {
+        let (source, _, generated) = source_with_generated_code();
+        let cargo = cargo_diagnostic("mismatched types", generated.start + 2..generated.end, "");
+        let diagnostic = cargo_diagnostic_to_diagnostic(
+            cargo,
+            &source,
+            DiagnosticsStyle::Full,
+            "2021",
+            Some(&[]),
+        );
+        let rendered = diagnostic.to_token_stream().to_string();
+        assert!(
+            rendered.contains("this is in code generated by `inline_proc`:"),
+            "{}",
+            rendered
+        );
+        assert!(
+            rendered.contains("const GENERATED : u8 = 1 ;"),
+            "{}",
+            rendered
+        );
+    }
+
+    #[test]
+    fn generated_code_is_only_shown_when_verbose() {
+        let (source, _, generated) = source_with_generated_code();
+        let cargo = cargo_diagnostic("mismatched types", generated, "");
+        let diagnostic =
+            cargo_diagnostic_to_diagnostic(cargo, &source, DiagnosticsStyle::Full, "2021", None);
+        assert!(!diagnostic
+            .to_token_stream()
+            .to_string()
+            .contains("generated by `inline_proc`"));
+    }
+
+    #[test]
+    fn diagnostic_in_user_code_has_no_generated_code() {
+        let (source, user, _) = source_with_generated_code();
+        let cargo = cargo_diagnostic("mismatched types", user, "");
+        let diagnostic = cargo_diagnostic_to_diagnostic(
+            cargo,
+            &source,
+            DiagnosticsStyle::Full,
+            "2021",
+            Some(&[]),
+        );
+        assert!(!diagnostic
+            .to_token_stream()
+            .to_string()
+            .contains("generated by `inline_proc`"));
+    }
+
+    /// A child of a diagnostic, for the `children` of `cargo_diagnostic`.
+    fn child(level: &str, message: &str) -> String {
+        format!(
+            r#"{{ "message": {message:?}, "code": null, "level": {level:?}, "spans": [],
+                "children": [], "rendered": null }}"#
+        )
+    }
+
+    /// Rustc's error for an `async fn` in edition 2015.
+    fn async_fn_error() -> CargoDiagnostic {
+        let children = [
+            child("help", r#"set `edition = "2024"` in `Cargo.toml`"#),
+            child(
+                "note",
+                "for more on editions, read https://doc.rust-lang.org/edition-guide",
+            ),
+        ];
+        cargo_diagnostic(
+            "`async fn` is not permitted in Rust 2015",
+            0..5,
+            &children.join(","),
+        )
+    }
+
+    #[test]
+    fn required_edition_of_syntax_not_permitted() {
+        assert_eq!(required_edition(&async_fn_error(), "2015"), Some("2018"));
+    }
+
+    #[test]
+    fn required_edition_of_syntax_in_later_editions() {
+        let cargo = cargo_diagnostic(
+            "let chains are only allowed in Rust 2024 or later",
+            0..5,
+            "",
+        );
+        assert_eq!(required_edition(&cargo, "2021"), Some("2024"));
+        assert_eq!(required_edition(&cargo, "2024"), None);
+    }
+
+    #[test]
+    fn required_edition_from_suggestion() {
+        let cargo = cargo_diagnostic(
+            "expected item, found keyword `let`",
+            0..5,
+            &child("help", r#"set `edition = "2021"` in `Cargo.toml`"#),
+        );
+        assert_eq!(required_edition(&cargo, "2018"), Some("2021"));
+        assert_eq!(required_edition(&cargo, "2021"), None);
+    }
+
+    #[test]
+    fn unrelated_errors_require_no_edition() {
+        let cargo = cargo_diagnostic("mismatched types", 0..5, "");
+        assert_eq!(required_edition(&cargo, "2015"), None);
+    }
+
+    #[test]
+    fn edition_suggestions() {
+        assert!(is_edition_suggestion(
+            r#"set `edition = "2024"` in `Cargo.toml`"#
+        ));
+        assert!(is_edition_suggestion(
+            "pass `--edition 2024` to use Rust 2024"
+        ));
+        assert!(!is_edition_suggestion(
+            "for more on editions, read https://doc.rust-lang.org/edition-guide"
+        ));
+    }
+
+    #[test]
+    fn edition_help_replaces_cargo_toml_suggestion() {
+        let source = TokenString::from_tokens(quote!(
+            async fn f() {}
+        ));
+        let diagnostic = cargo_diagnostic_to_diagnostic(
+            async_fn_error(),
+            &source,
+            DiagnosticsStyle::Full,
+            "2015",
+            None,
+        );
+        let rendered = diagnostic.to_token_stream().to_string();
+        assert!(
+            rendered.contains(
+                r#"the inner crate is built with edition 2015; set `edition: \"2018\"` in the metadata"#
+            ),
+            "{}",
+            rendered
+        );
+        assert!(!rendered.contains("Cargo.toml"), "{}", rendered);
+        assert!(rendered.contains("for more on editions"), "{}", rendered);
+    }
+
+    #[test]
+    fn colors_are_stripped() {
+        assert_eq!(
+            strip_control_codes("\x1b[0m\x1b[1m\x1b[38;5;9merror\x1b[0m: failed\n"),
+            "error: failed\n"
+        );
+    }
+
+    #[test]
+    fn progress_redraws_are_stripped() {
+        assert_eq!(
+            strip_control_codes("    Building [==>  ] 1/3\r    Building [====>] 3/3\r\nDone\r\n"),
+            "    Building [====>] 3/3\nDone\n"
+        );
+    }
+
+    #[test]
+    fn cursor_codes_are_stripped() {
+        assert_eq!(
+            strip_control_codes("\x1b[2K\x1b[1Aline\x1b[K\x1b7\tend\x1b8"),
+            "line\tend"
+        );
+    }
+
+    #[test]
+    fn hyperlinks_are_stripped() {
+        assert_eq!(
+            strip_control_codes("see \x1b]8;;https://example.com\x07docs\x1b]8;;\x1b\\ here"),
+            "see docs here"
+        );
+    }
+
+    #[test]
+    fn relative_paths() {
+        let relative = |base: &str, path: &str| relative_path(Path::new(base), Path::new(path));
+        assert_eq!(
+            relative("/crate/src", "/crate/defaults.ron"),
+            Some(PathBuf::from("../defaults.ron"))
+        );
+        assert_eq!(
+            relative("/crate", "/crate/config/defaults.ron"),
+            Some(PathBuf::from("config/defaults.ron"))
+        );
+        assert_eq!(relative("/crate/src", "defaults.ron"), None);
+    }
+
+    #[test]
+    fn shared_workspace_manifest_is_replaced_whole() {
+        let workspace_root = env::temp_dir().join("inline-proc-unit-shared-workspace");
+        let _ = fs::remove_dir_all(&workspace_root);
+        fs::create_dir_all(&workspace_root).unwrap();
+        let manifest_path = workspace_root.join("Cargo.toml");
+        update_shared_workspace(&workspace_root).unwrap();
+
+        let updates: Vec<_> = (0..8)
+            .map(|member| {
+                let workspace_root = workspace_root.clone();
+                std::thread::spawn(move || {
+                    let member = workspace_root.join(format!("member-{}", member));
+                    fs::create_dir_all(&member).unwrap();
+                    fs::write(member.join("Cargo.toml"), "").unwrap();
+                    update_shared_workspace(&workspace_root).unwrap();
+                })
+            })
+            .collect();
+        while !updates.iter().all(|update| update.is_finished()) {
+            let manifest = fs::read_to_string(&manifest_path).unwrap();
+            assert!(manifest.ends_with("resolver='2'}\n"), "{:?}", manifest);
+        }
+        for update in updates {
+            update.join().unwrap();
+        }
+
+        let manifest = fs::read_to_string(&manifest_path).unwrap();
+        assert!(manifest.contains("'member-0','member-1'"));
+        assert!(manifest.contains("'member-7'"));
+        assert!(!workspace_root.join("Cargo.toml.tmp").exists());
+        fs::remove_dir_all(&workspace_root).unwrap();
+    }
+
+    /// Metadata with the options, which must include the dependencies, besides an edition and no
+    /// exports, in JSON.
+    fn metadata(options: &str) -> Metadata {
+        let metadata = format!(r#"{{"edition": "2021", "exports": {{}} {}}}"#, options);
+        serde_json::from_str(&metadata).unwrap()
+    }
+
+    #[test]
+    fn invalid_cargo_config_is_a_metadata_error() {
+        let metadata = metadata(r#", "dependencies": {}, "cargo_config": { "not a key": "1" }"#);
+        assert!(matches!(
+            cargo_config_args(&metadata),
+            Err(Error::Metadata(message)) if message == "Invalid Cargo config key `not a key`"
+        ));
+    }
+
+    #[test]
+    fn cargo_config_values_are_toml() {
+        let metadata = metadata(
+            r#", "dependencies": {}, "cargo_config": {
+                "env.NAME": "a \"quoted\" value",
+                "build.jobs": 2,
+                "net.git-fetch-with-cli": true,
+                "build.rustflags": ["--cfg", "inline"]
+            }"#,
+        );
+        let args = cargo_config_args(&metadata).unwrap();
+        assert_eq!(
+            args[2..],
+            [
+                "--config",
+                "build.jobs=2",
+                "--config",
+                r#"build.rustflags=["--cfg", "inline"]"#,
+                "--config",
+                r#"env.NAME='a "quoted" value'"#,
+                "--config",
+                "net.git-fetch-with-cli=true",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_rename_is_a_dependency_error() {
+        let metadata = metadata(
+            r#", "dependencies": { "serde": { "version": "1", "package": "not a crate" } }"#,
+        );
+        assert!(matches!(
+            validate_dependencies(&metadata.dependencies, &metadata.spans),
+            Err(Error::Dependency(message)) if message.contains("`not a crate`")
+        ));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn failing_pre_build_is_a_pre_build_error() {
+        let metadata = metadata(r#", "dependencies": {}, "pre_build": ["false"]"#);
+        assert!(matches!(
+            run_pre_build(&metadata, &env::temp_dir()),
+            Err(Error::PreBuild(message)) if message.starts_with("Pre-build command `false` failed")
+        ));
+    }
+
+    #[test]
+    fn missing_pre_build_program_is_an_io_error() {
+        let metadata = metadata(r#", "dependencies": {}, "pre_build": ["/nonexistent/program"]"#);
+        assert!(matches!(
+            run_pre_build(&metadata, &env::temp_dir()),
+            Err(Error::Io { action, error })
+                if action == "run pre-build command `/nonexistent/program`"
+                    && error.kind() == io::ErrorKind::NotFound
+        ));
+    }
+
+    #[test]
+    fn missing_dylib_is_a_dylib_error() {
+        let metadata = metadata(r#", "dependencies": {}, "cargo_subcommand": ["zigbuild"]"#);
+        let output = Output {
+            status: ExitStatus::default(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        };
+        assert!(matches!(
+            check_custom_build(&metadata, &output, Path::new("/nonexistent/lib.so")),
+            Err(Error::Dylib(message))
+                if message == "`zigbuild` didn't produce the dylib at /nonexistent/lib.so"
+        ));
+    }
+
+    #[test]
+    fn errors_at_a_known_option_are_spanned() {
+        let span = Span::call_site();
+        assert!(matches!(
+            Error::Metadata("invalid".to_owned()).at(Some(span)),
+            Error::Spanned { error, .. } if matches!(*error, Error::Metadata(_))
+        ));
+        assert!(matches!(
+            Error::Metadata("invalid".to_owned()).at(None),
+            Error::Metadata(_)
+        ));
+    }
+
+    #[test]
+    fn shared_cache_key_is_stable() {
+        let (lib_rs, cargo_toml) = ("pub fn f() {}".to_owned(), "[package]".to_owned());
+        let key = |parts: [Option<&String>; 2]| shared_cache_key(parts.into_iter());
+        // Pinned, as described on `stable_hash`.
+        assert_eq!(
+            key([Some(&lib_rs), Some(&cargo_toml)]),
+            0x96e3_136a_6cbb_b00f
+        );
+        assert_ne!(
+            key([Some(&lib_rs), Some(&cargo_toml)]),
+            key([Some(&cargo_toml), Some(&lib_rs)])
+        );
+        assert_ne!(key([Some(&lib_rs), None]), key([None, Some(&lib_rs)]));
+    }
+
+    /// Held by the tests that depend on `INLINE_PROC_DYLIB_EXTENSION`, since one of them sets it.
+    static DYLIB_EXTENSION: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn dylib_file_names_use_the_platform_of_the_target() {
+        // Assumes that `INLINE_PROC_DYLIB_EXTENSION` isn't set when the tests are run.
+        let _lock = DYLIB_EXTENSION.lock().unwrap();
+        let name = |target| dylib_file_name(target, "inline_proc_macro");
+        assert_eq!(name("x86_64-pc-windows-msvc"), "inline_proc_macro.dll");
+        assert_eq!(name("x86_64-pc-windows-gnu"), "inline_proc_macro.dll");
+        assert_eq!(name("aarch64-apple-darwin"), "libinline_proc_macro.dylib");
+        assert_eq!(name("x86_64-unknown-linux-gnu"), "libinline_proc_macro.so");
+        assert_eq!(
+            name(HOST_TARGET),
+            libloading::library_filename("inline_proc_macro")
+                .to_str()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn dylib_extension_can_be_overridden() {
+        let _lock = DYLIB_EXTENSION.lock().unwrap();
+        let previous = env::var_os("INLINE_PROC_DYLIB_EXTENSION");
+        env::set_var("INLINE_PROC_DYLIB_EXTENSION", ".ext");
+
+        let target_dir = env::temp_dir().join("inline-proc-unit-dylib-extension");
+        let dylib = dylib_path(&target_dir, "inline_proc_macro");
+        // The host's dylib in `dist`, named like the module names it, must be the one the invoker
+        // selects.
+        let dist = target_dir.join(DIST_DIR);
+        let host_dylib = dist
+            .join(HOST_TARGET)
+            .join(dylib_file_name(HOST_TARGET, "inline_proc_macro"));
+        fs::create_dir_all(host_dylib.parent().unwrap()).unwrap();
+        fs::write(&host_dylib, "").unwrap();
+        let selected = crate::invoke::select_dylib(
+            dylib.to_str().unwrap().to_owned(),
+            Some(&(
+                LitStr::new(dist.to_str().unwrap(), Span::call_site()),
+                LitStr::new("inline_proc_macro", Span::call_site()),
+            )),
+        );
+
+        match previous {
+            Some(previous) => env::set_var("INLINE_PROC_DYLIB_EXTENSION", previous),
+            None => env::remove_var("INLINE_PROC_DYLIB_EXTENSION"),
+        }
+        let expected = format!("{}inline_proc_macro.ext", env::consts::DLL_PREFIX);
+        assert_eq!(dylib.file_name().unwrap().to_str(), Some(&*expected));
+        assert_eq!(host_dylib.file_name().unwrap().to_str(), Some(&*expected));
+        assert_eq!(selected, host_dylib.to_str().unwrap());
+    }
+
+    #[test]
+    fn short_names_are_stable() {
+        assert_eq!(short_name("inline_macros"), "inline_macros");
+        let longest = "x".repeat(MAX_NAME_LEN);
+        assert_eq!(short_name(&longest), longest);
+        // Pinned, as described on `stable_hash`.
+        assert_eq!(
+            short_name("a_rather_long_name_for_a_module"),
+            "a_rather_long_n_1267502d"
+        );
+    }
+}
@@ -1,60 +1,642 @@
 //! The `invoke_inline_macro!` macro.
 
 use proc_macro::TokenStream as TokenStream1;
-use proc_macro2::TokenStream;
+use proc_macro2::{Delimiter, Group, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+use std::collections::HashMap;
+use std::env;
+use std::fmt::{Display, Write};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use libloading::{Library, Symbol};
-use proc_macro_error2::abort;
+use proc_macro_error2::{abort, abort_call_site, Diagnostic, Level};
+use quote::{format_ident, quote, quote_spanned, ToTokens};
+use serde::Deserialize;
 use syn::parse::{Parse, ParseStream};
-use syn::{Ident, LitStr};
+use syn::{Attribute, Ident, Item, LitInt, LitStr, Token, UseTree, Visibility};
+
+use crate::inline_proc::HOST_TARGET;
+use crate::{dylib_file_name, env_flag, span_location};
 
 pub(super) fn invoke_inline_macro(input: TokenStream1) -> TokenStream1 {
     let input: InvokerInput = syn::parse_macro_input!(input);
 
-    let library = unsafe { Library::new(input.dylib_path.value()) }
+    let dylib_path = select_dylib(input.dylib_path.value(), input.dist.as_ref());
+    let library = unsafe { open_library(&dylib_path, input.lazy_binding) }
         .unwrap_or_else(|e| abort!(input.dylib_path, "Failed to open library: {}", e));
 
-    match input.macro_type {
-        MacroType::Bang(tokens) => {
-            let macro_function: Symbol<fn(TokenStream1) -> TokenStream1> =
-                unsafe { library_macro(&library, "bang", input.name) };
-            macro_function(tokens.into())
+    let name = input.name;
+    let spans = input.spans;
+    let post_process = input.post_process;
+    let location = input
+        .location
+        .then(|| Location::of(input.macro_type.input_span()));
+    let extra = ExtraArgs {
+        host_crate: input.host_crate.map(Into::into),
+        location: location.as_ref(),
+    };
+    let extra = &extra;
+    let proc_macro2 = input.proc_macro2;
+    let macro_output = input.macro_output;
+    let output_module = input.output_module;
+    let input_span = input.macro_type.input_span();
+    let call_site = caller_call_site(input_span);
+    let call = |macro_type: &str, parts: Vec<TokenStream>| {
+        if proc_macro2 {
+            return unsafe {
+                call_string_macro(&library, macro_type, &name, extra, parts, call_site)
+            };
         }
-        MacroType::Derive(item) => {
-            let macro_function: Symbol<fn(TokenStream1) -> TokenStream1> =
-                unsafe { library_macro(&library, "derive", input.name) };
-            macro_function(item.into())
+        let mut parts = parts.into_iter().map(TokenStream1::from);
+        let mut part = || parts.next().unwrap_or_default();
+        let output = unsafe {
+            if macro_type == "attribute" {
+                call_attribute_macro(&library, &name, extra, part(), part())
+            } else {
+                call_macro(&library, macro_type, &name, extra, part())
+            }
+        };
+        if macro_output {
+            emit_macro_output(output)
+        } else {
+            output
         }
-        MacroType::Attribute(attr, item) => {
-            let macro_function: Symbol<fn(TokenStream1, TokenStream1) -> TokenStream1> =
-                unsafe { library_macro(&library, "attribute", input.name) };
-            macro_function(attr.into(), item.into())
+    };
+    let output = match input.macro_type {
+        MacroType::Bang(tokens) => match syn::parse2::<IncludeInput>(tokens.clone()) {
+            Ok(include) if input.include => {
+                include.invoke(|input| call("bang", vec![input.into()]))
+            }
+            _ => {
+                let output = call("bang", vec![tokens.clone()]);
+                check_recursion(&name, &tokens, output.into(), input.depth).into()
+            }
+        },
+        MacroType::Derive(item) => call("derive", vec![item]),
+        MacroType::Attribute(attr, item) => call("attribute", vec![attr, item]),
+    };
+    // `proc_macro2` keeps the source it parsed in a thread local, whose destructor would crash
+    // the compiler if it ran after the library is unloaded, so such libraries are never unloaded.
+    if proc_macro2 {
+        std::mem::forget(library);
+    }
+
+    let mut output = spans.apply(output.into(), input.span_argument, call_site);
+    if output_module {
+        output = wrap_in_output_module(output);
+    }
+    let output = post_process.apply(output).into();
+
+    if env_flag("INLINE_PROC_DUMP_EXPANSION") {
+        dump_expansion(&name, input_span, &output);
+    }
+
+    output
+}
+
+/// Emit the diagnostics of a macro with `macro_output` and return its output. The macro returns
+/// its output in braces, followed by the level, message and an identifier with the span of each
+/// diagnostic.
+fn emit_macro_output(output: TokenStream1) -> TokenStream1 {
+    let mut tokens = TokenStream::from(output).into_iter();
+    let output = match tokens.next() {
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => group.stream(),
+        _ => abort_call_site!("Invalid output from a macro with `macro_output`"),
+    };
+    while let Some(level) = tokens.next() {
+        let (Some(TokenTree::Literal(message)), Some(TokenTree::Ident(span))) =
+            (tokens.next(), tokens.next())
+        else {
+            abort_call_site!("Invalid diagnostic from a macro with `macro_output`");
+        };
+        let message = syn::parse2::<LitStr>(message.to_token_stream())
+            .map_or_else(|_| message.to_string(), |message| message.value());
+        let level = match level.to_string().as_str() {
+            "warning" => Level::Warning,
+            _ => Level::Error,
+        };
+        Diagnostic::spanned(span.span(), level, message).emit();
+    }
+    output.into()
+}
+
+/// The number of output modules generated by this compiler process.
+static OUTPUT_MODULES: AtomicUsize = AtomicUsize::new(0);
+
+/// Put the output of a macro with `output_module` in a module of its own, so that its private items
+/// can't clash with the code around it.
+fn wrap_in_output_module(output: TokenStream) -> TokenStream {
+    let file = syn::parse2::<syn::File>(output.clone()).unwrap_or_else(|e| {
+        abort_call_site!(
+            "The output of a macro with `output_module` must be items: {}",
+            e
+        )
+    });
+    wrap_in_module("__inline_proc_output", &file, output)
+}
+
+/// Put output items in a module and re-export them with their own visibility. The module is named
+/// after the prefix, where the macro is expanded and the number of modules before it, so that
+/// several invocations in one scope get different modules even if they have the same output.
+fn wrap_in_module(prefix: &str, file: &syn::File, output: TokenStream) -> TokenStream {
+    let start = Span::call_site().unwrap().start();
+    let module = format_ident!(
+        "{}_{}_{}_{}",
+        prefix,
+        start.line(),
+        start.column(),
+        OUTPUT_MODULES.fetch_add(1, Ordering::Relaxed)
+    );
+    let reexports = file.items.iter().filter_map(|item| {
+        let (attrs, vis, names) = item_exports(item)?;
+        let vis = match vis {
+            Visibility::Public(_) => vis.to_token_stream(),
+            Visibility::Restricted(restricted) if restricted.path.segments[0].ident == "crate" => {
+                vis.to_token_stream()
+            }
+            // `pub(super)` in the module means visible to the module the macro is invoked in.
+            Visibility::Restricted(restricted) if restricted.path.is_ident("super") => {
+                TokenStream::new()
+            }
+            _ => return None,
+        };
+        let cfgs = attrs.iter().filter(|attr| attr.path().is_ident("cfg"));
+        Some(quote! {
+            #(#cfgs)*
+            #[allow(unused_imports)]
+            #vis use #module::{#(#names),*};
+        })
+    });
+    quote! {
+        #[doc(hidden)]
+        mod #module {
+            #[allow(unused_imports)]
+            use super::*;
+            #output
         }
+        #(#reexports)*
     }
 }
 
+/// The attributes, visibility and names of an item that can be re-exported from an output module.
+fn item_exports(item: &Item) -> Option<(&[Attribute], &Visibility, Vec<TokenStream>)> {
+    let (attrs, vis, ident) = match item {
+        Item::Const(item) => (&item.attrs, &item.vis, &item.ident),
+        Item::Enum(item) => (&item.attrs, &item.vis, &item.ident),
+        Item::ExternCrate(item) => (
+            &item.attrs,
+            &item.vis,
+            item.rename
+                .as_ref()
+                .map_or(&item.ident, |(_, rename)| rename),
+        ),
+        Item::Fn(item) => (&item.attrs, &item.vis, &item.sig.ident),
+        Item::Mod(item) => (&item.attrs, &item.vis, &item.ident),
+        Item::Static(item) => (&item.attrs, &item.vis, &item.ident),
+        Item::Struct(item) => (&item.attrs, &item.vis, &item.ident),
+        Item::Trait(item) => (&item.attrs, &item.vis, &item.ident),
+        Item::TraitAlias(item) => (&item.attrs, &item.vis, &item.ident),
+        Item::Type(item) => (&item.attrs, &item.vis, &item.ident),
+        Item::Union(item) => (&item.attrs, &item.vis, &item.ident),
+        Item::Use(item) => {
+            let mut names = Vec::new();
+            use_tree_names(&item.tree, None, &mut names);
+            return Some((&item.attrs, &item.vis, names));
+        }
+        _ => return None,
+    };
+    (ident != "_" && ident != "self").then(|| (&attrs[..], vis, vec![ident.to_token_stream()]))
+}
+
+/// The names a `use` item brings into scope, or `*` for glob imports.
+fn use_tree_names(tree: &UseTree, parent: Option<&Ident>, names: &mut Vec<TokenStream>) {
+    match tree {
+        UseTree::Path(path) => use_tree_names(&path.tree, Some(&path.ident), names),
+        UseTree::Name(name) if name.ident == "self" => {
+            names.extend(parent.map(ToTokens::to_token_stream));
+        }
+        UseTree::Name(name) => names.push(name.ident.to_token_stream()),
+        UseTree::Rename(rename) if rename.rename == "_" => {}
+        UseTree::Rename(rename) => names.push(rename.rename.to_token_stream()),
+        UseTree::Glob(_) => names.push(quote!(*)),
+        UseTree::Group(group) => {
+            for tree in &group.items {
+                use_tree_names(tree, parent, names);
+            }
+        }
+    }
+}
+
+/// How many times a bang macro may expand to an invocation of itself, nested in each other.
+const RECURSION_LIMIT: u32 = 32;
+
+/// Abort if the output of a bang macro invokes the same macro with the same input, since that would
+/// never terminate, or if the macro got here by expanding to itself `RECURSION_LIMIT` times.
+/// Otherwise, the invocations of the macro in its output are marked with their depth.
+fn check_recursion(
+    name: &Ident,
+    input: &TokenStream,
+    output: TokenStream,
+    depth: u32,
+) -> TokenStream {
+    if invokes_itself(name, &input.to_string(), output.clone()) {
+        abort!(
+            name,
+            "Recursive inline macro invocation detected: `{}!` expands to itself",
+            name
+        );
+    }
+    if depth >= RECURSION_LIMIT {
+        abort!(
+            name,
+            "Recursive inline macro invocation detected: `{}!` expanded to itself {} times",
+            name,
+            RECURSION_LIMIT
+        );
+    }
+    mark_depth(name, output, depth + 1)
+}
+
+/// Mark the invocations of the macro in its output with `__inline_proc_depth(depth)`, which its
+/// `macro_rules!` macro passes on to `invoke_inline_macro!`.
+fn mark_depth(name: &Ident, output: TokenStream, depth: u32) -> TokenStream {
+    let tokens: Vec<TokenTree> = output.into_iter().collect();
+    tokens
+        .iter()
+        .enumerate()
+        .map(|(i, token)| match token {
+            TokenTree::Group(group) => {
+                let mut stream = mark_depth(name, group.stream(), depth);
+                let invoked = i >= 2
+                    && matches!(&tokens[i - 2], TokenTree::Ident(ident) if ident == name)
+                    && matches!(&tokens[i - 1], TokenTree::Punct(punct) if punct.as_char() == '!');
+                if invoked {
+                    let depth = Literal::u32_unsuffixed(depth);
+                    stream = quote_spanned!(group.span()=> __inline_proc_depth(#depth) #stream);
+                }
+                let mut marked = Group::new(group.delimiter(), stream);
+                marked.set_span(group.span());
+                TokenTree::Group(marked)
+            }
+            token => token.clone(),
+        })
+        .collect()
+}
+
+fn invokes_itself(name: &Ident, input: &str, output: TokenStream) -> bool {
+    let tokens: Vec<TokenTree> = output.into_iter().collect();
+    tokens.iter().enumerate().any(|(i, token)| match token {
+        TokenTree::Ident(ident) => {
+            let bang = matches!(
+                tokens.get(i + 1),
+                Some(TokenTree::Punct(punct)) if punct.as_char() == '!'
+            );
+            let same_input = matches!(
+                tokens.get(i + 2),
+                Some(TokenTree::Group(group)) if group.stream().to_string() == input
+            );
+            ident == name && bang && same_input
+        }
+        TokenTree::Group(group) => invokes_itself(name, input, group.stream()),
+        _ => false,
+    })
+}
+
+/// Print the output of a macro invoked at `span` to stderr, for debugging.
+fn dump_expansion(name: &Ident, span: Span, output: &TokenStream1) {
+    let output = match syn::parse::<syn::File>(output.clone()) {
+        Ok(file) => file
+            .items
+            .iter()
+            .map(|item| item.to_token_stream().to_string())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Err(_) => output.to_string(),
+    };
+    eprintln!(
+        "inline macro `{}!` at {} expanded to:\n{}\n",
+        name,
+        span_location(span.unwrap()),
+        output,
+    );
+}
+
 struct InvokerInput {
     dylib_path: LitStr,
     name: Ident,
+    /// The directory of the dylibs built for the module's `targets` and the name of their library,
+    /// written as `dist("path", "lib_name")`.
+    dist: Option<(LitStr, LitStr)>,
+    /// Whether to bind the symbols of the dylib when they are first used, written as `lazy_binding`.
+    lazy_binding: bool,
+    spans: SpanMode,
+    /// The span of the token passed to a macro with `spans: "argument"`, written as
+    /// `argument(token)`.
+    span_argument: Option<Span>,
+    /// The path to the crate that defines the macro, for macro functions with `host_crate`, written
+    /// as `host_crate($crate)`.
+    host_crate: Option<TokenStream>,
+    /// Whether the macro function takes the location it was invoked at, written as `location`.
+    location: bool,
+    /// Whether the macro function uses `proc_macro2`, and so takes and returns strings, written as
+    /// `proc_macro2`.
+    proc_macro2: bool,
+    /// Whether the macro can be invoked with `include "path"`, written as `include`.
+    include: bool,
+    /// Whether the macro function returns a `MacroOutput`, written as `macro_output`.
+    macro_output: bool,
+    /// Whether to put the output in a module of its own, written as `output_module`.
+    output_module: bool,
+    /// How many times the macro has expanded to itself to get to this invocation, written as
+    /// `depth(n)`.
+    depth: u32,
+    post_process: PostProcess,
     macro_type: MacroType,
 }
 
 impl Parse for InvokerInput {
     fn parse(input: ParseStream) -> syn::Result<Self> {
+        let dylib_path = input.parse::<LitStr>()?;
+        let name = input.parse::<Ident>()?;
+        let dist = if input
+            .fork()
+            .parse::<Ident>()
+            .is_ok_and(|ident| ident == "dist")
+        {
+            input.parse::<Ident>()?;
+            let content;
+            syn::parenthesized!(content in input);
+            let dir = content.parse::<LitStr>()?;
+            content.parse::<Token![,]>()?;
+            Some((dir, content.parse::<LitStr>()?))
+        } else {
+            None
+        };
+        let lazy_binding = parse_flag(input, "lazy_binding")?;
+        let spans = input.parse()?;
+        let span_argument = if spans == SpanMode::Argument {
+            let content;
+            syn::parenthesized!(content in input);
+            Some(content.parse::<TokenTree>()?.span())
+        } else {
+            None
+        };
         Ok(Self {
-            dylib_path: input.parse::<LitStr>()?,
-            name: input.parse::<Ident>()?,
+            dylib_path,
+            name,
+            dist,
+            lazy_binding,
+            spans,
+            span_argument,
+            host_crate: parse_host_crate(input)?,
+            location: parse_flag(input, "location")?,
+            proc_macro2: parse_flag(input, "proc_macro2")?,
+            include: parse_flag(input, "include")?,
+            macro_output: parse_flag(input, "macro_output")?,
+            output_module: parse_flag(input, "output_module")?,
+            depth: parse_depth(input)?,
+            post_process: input.parse()?,
             macro_type: input.parse()?,
         })
     }
 }
 
+/// Parse an optional flag written as the identifier `name`.
+fn parse_flag(input: ParseStream, name: &str) -> syn::Result<bool> {
+    let present = input
+        .fork()
+        .parse::<Ident>()
+        .is_ok_and(|ident| ident == name);
+    if present {
+        input.parse::<Ident>()?;
+    }
+    Ok(present)
+}
+
+/// Parse the optional depth of the expansion, written as `depth(n)`.
+fn parse_depth(input: ParseStream) -> syn::Result<u32> {
+    if !input
+        .fork()
+        .parse::<Ident>()
+        .is_ok_and(|ident| ident == "depth")
+    {
+        return Ok(0);
+    }
+    input.parse::<Ident>()?;
+    let content;
+    syn::parenthesized!(content in input);
+    content.parse::<LitInt>()?.base10_parse()
+}
+
+/// Parse the optional path to the crate that defines the macro, written as `host_crate(path)`.
+fn parse_host_crate(input: ParseStream) -> syn::Result<Option<TokenStream>> {
+    if !input
+        .fork()
+        .parse::<Ident>()
+        .is_ok_and(|ident| ident == "host_crate")
+    {
+        return Ok(None);
+    }
+    input.parse::<Ident>()?;
+    let content;
+    syn::parenthesized!(content in input);
+    Ok(Some(content.parse()?))
+}
+
+/// The location a macro was invoked at, passed to macro functions with `location`.
+struct Location {
+    file: String,
+    line: usize,
+    column: usize,
+}
+
+impl Location {
+    fn of(span: Span) -> Self {
+        let span = span.unwrap();
+        Self {
+            file: span
+                .local_file()
+                .map_or_else(|| span.file(), |file| file.display().to_string()),
+            line: span.line(),
+            column: span.column(),
+        }
+    }
+}
+
+/// How the spans of the tokens returned by a macro are set.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub(crate) enum SpanMode {
+    /// Keep the spans the macro gave the tokens, like a native proc macro.
+    #[default]
+    Preserve,
+    /// Give every token the call site span, so all identifiers can be referred to by the caller.
+    /// See `caller_call_site`.
+    CallSite,
+    /// Give every token the mixed site span, so local variables and labels are hygienic.
+    MixedSite,
+    /// Give every token the span of a token passed before the input of a bang macro, as in
+    /// `my_macro!(token, input)`.
+    Argument,
+}
+
+impl SpanMode {
+    /// The keyword passed to `invoke_inline_macro!` for this mode, if it isn't the default.
+    pub(crate) fn keyword(self) -> Option<&'static str> {
+        match self {
+            Self::Preserve => None,
+            Self::CallSite => Some("call_site"),
+            Self::MixedSite => Some("mixed_site"),
+            Self::Argument => Some("argument"),
+        }
+    }
+
+    /// Respan the tokens, where `argument` is the span of the token passed to a macro with
+    /// `Argument` and `call_site` is the one from `caller_call_site`.
+    fn apply(self, tokens: TokenStream, argument: Option<Span>, call_site: Span) -> TokenStream {
+        let span = match self {
+            Self::Preserve => return tokens,
+            Self::CallSite => call_site,
+            Self::MixedSite => Span::mixed_site(),
+            Self::Argument => argument.unwrap_or(call_site),
+        };
+        respan(tokens, span)
+    }
+}
+
+/// The span that stands in for the call site of a native proc macro: located at the call site, but
+/// resolving names like the start of the input, `input_span`.
+///
+/// The call site of the invocation is inside the generated `macro_rules!` macro, so local variables
+/// with its span would be hidden from the caller. Bang macros with no input only have that span.
+fn caller_call_site(input_span: Span) -> Span {
+    Span::call_site().resolved_at(input_span)
+}
+
+/// Parse from a string rather than an enum, since RON doesn't write enums as strings.
+impl TryFrom<String> for SpanMode {
+    type Error = String;
+    fn try_from(mode: String) -> Result<Self, Self::Error> {
+        match &*mode {
+            "preserve" => Ok(Self::Preserve),
+            "call_site" => Ok(Self::CallSite),
+            "mixed_site" => Ok(Self::MixedSite),
+            "argument" => Ok(Self::Argument),
+            _ => Err(format!(
+                "unknown span mode `{}`, expected `preserve`, `call_site`, `mixed_site` or \
+                 `argument`",
+                mode
+            )),
+        }
+    }
+}
+
+impl Parse for SpanMode {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mode = match input.fork().parse::<Ident>() {
+            Ok(ident) if ident == "call_site" => Self::CallSite,
+            Ok(ident) if ident == "mixed_site" => Self::MixedSite,
+            Ok(ident) if ident == "argument" => Self::Argument,
+            _ => return Ok(Self::Preserve),
+        };
+        input.parse::<Ident>()?;
+        Ok(mode)
+    }
+}
+
+/// Transformations applied to the output of every macro of a module, from its `wrap_output` and
+/// `output_attributes` options. This is written as `post(#[attributes] mod prefix)`, where both
+/// parts are optional.
+#[derive(Default)]
+struct PostProcess {
+    attributes: Vec<Attribute>,
+    wrap: Option<Ident>,
+}
+
+impl PostProcess {
+    fn apply(self, output: TokenStream) -> TokenStream {
+        // Only items can be put in a module, so other output such as an expression isn't wrapped.
+        let output = match (self.wrap, syn::parse2::<syn::File>(output.clone())) {
+            (Some(prefix), Ok(file)) => wrap_in_module(&prefix.to_string(), &file, output),
+            _ => output,
+        };
+
+        // Attributes can only be added to items, so other output is left as it is.
+        if self.attributes.is_empty() {
+            return output;
+        }
+        match syn::parse2::<syn::File>(output.clone()) {
+            Ok(file) => {
+                let attributes = &self.attributes;
+                let inner_attributes = &file.attrs;
+                let items = file.items.iter().map(|item| quote!(#(#attributes)* #item));
+                quote!(#(#inner_attributes)* #(#items)*)
+            }
+            Err(_) => output,
+        }
+    }
+}
+
+impl Parse for PostProcess {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if !input
+            .fork()
+            .parse::<Ident>()
+            .is_ok_and(|ident| ident == "post")
+        {
+            return Ok(Self::default());
+        }
+        input.parse::<Ident>()?;
+
+        let content;
+        syn::parenthesized!(content in input);
+        Ok(Self {
+            attributes: content.call(Attribute::parse_outer)?,
+            wrap: match content.parse::<Option<Token![mod]>>()? {
+                Some(_) => Some(content.parse()?),
+                None => None,
+            },
+        })
+    }
+}
+
+fn respan(tokens: TokenStream, span: Span) -> TokenStream {
+    tokens
+        .into_iter()
+        .map(|mut token| {
+            if let TokenTree::Group(group) = &token {
+                let mut new_group = Group::new(group.delimiter(), respan(group.stream(), span));
+                new_group.set_span(span);
+                return TokenTree::Group(new_group);
+            }
+            token.set_span(span);
+            token
+        })
+        .collect()
+}
+
 enum MacroType {
     Bang(TokenStream),
     Derive(TokenStream),
     Attribute(TokenStream, TokenStream),
 }
 
+impl MacroType {
+    /// The span of the start of the macro's input, which is the closest to the invocation that is
+    /// available: the invocation itself is inside the generated `macro_rules!` macro, so its span
+    /// points at the `#[inline_proc]` module. That is used for bang macros with no input.
+    fn input_span(&self) -> Span {
+        let (Self::Bang(tokens) | Self::Derive(tokens) | Self::Attribute(_, tokens)) = self;
+        tokens
+            .clone()
+            .into_iter()
+            .next()
+            .map_or_else(Span::call_site, |token| token.span())
+    }
+}
+
 impl Parse for MacroType {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let ty = input.parse::<Ident>()?;
@@ -77,6 +659,497 @@ impl Parse for MacroType {
     }
 }
 
+/// The input to a bang macro of the form `include "path"`, which calls the macro with the contents
+/// of the file at the path as a string literal instead.
+struct IncludeInput {
+    path: LitStr,
+}
+
+impl Parse for IncludeInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let keyword = input.parse::<Ident>()?;
+        if keyword != "include" {
+            return Err(syn::Error::new_spanned(keyword, "Expected `include`"));
+        }
+        Ok(Self {
+            path: input.parse()?,
+        })
+    }
+}
+
+impl IncludeInput {
+    fn invoke(self, macro_function: impl FnOnce(TokenStream1) -> TokenStream1) -> TokenStream1 {
+        // Like `include_str!`, the path is relative to the file the macro is invoked in.
+        let path = self
+            .path
+            .span()
+            .unwrap()
+            .local_file()
+            .and_then(|file| Some(file.parent()?.join(self.path.value())))
+            .or_else(|| {
+                Some(Path::new(&env::var_os("CARGO_MANIFEST_DIR")?).join(self.path.value()))
+            })
+            .unwrap_or_else(|| PathBuf::from(self.path.value()));
+        let contents = fs::read_to_string(&path)
+            .unwrap_or_else(|e| abort!(self.path, "Failed to read {}: {}", path.display(), e));
+
+        let contents = LitStr::new(&contents, self.path.span()).into_token_stream();
+        let mut output: TokenStream = macro_function(contents.into()).into();
+
+        // Make the compiler track the file so the macro is rerun when it changes. The path must be
+        // absolute since `include_str!` resolves relative paths from the file it is expanded in.
+        // `include_str!` is the only way to track a file on stable, so it goes in an unnamed
+        // constant: after items, or in a block around an expression. Other output, such as a type,
+        // has no place for it, so the file isn't tracked.
+        let path = std::path::absolute(&path).unwrap_or(path);
+        let path = path.to_string_lossy();
+        let track = quote!(
+            const _: &str = ::core::include_str!(#path);
+        );
+        if syn::parse2::<syn::File>(output.clone()).is_ok() {
+            output.extend(track);
+        } else if syn::parse2::<syn::Expr>(output.clone()).is_ok() {
+            output = quote!({ #track #output });
+        }
+        output.into()
+    }
+}
+
+/// Select the dylib to load: the one built for the host with `targets`, if there is one, or else
+/// the one the module was built as.
+pub(crate) fn select_dylib(dylib_path: String, dist: Option<&(LitStr, LitStr)>) -> String {
+    let host_dylib = dist.and_then(|(dir, lib_name)| {
+        let path = Path::new(&dir.value())
+            .join(HOST_TARGET)
+            .join(dylib_file_name(HOST_TARGET, &lib_name.value()));
+        path.is_file().then_some(path)
+    });
+    match host_dylib {
+        Some(path) => path.to_string_lossy().into_owned(),
+        None => dylib_path,
+    }
+}
+
+/// Open the dylib. On Unix, symbols are bound eagerly unless the module has `lazy_binding` or
+/// `INLINE_PROC_LAZY_BINDING` is set, so that undefined symbols are reported when the library is
+/// opened instead of when they are used.
+pub(crate) unsafe fn open_library(path: &str, lazy: bool) -> Result<Library, libloading::Error> {
+    #[cfg(unix)]
+    {
+        use libloading::os::unix::{Library as UnixLibrary, RTLD_LAZY, RTLD_LOCAL, RTLD_NOW};
+
+        let binding = if lazy || env_flag("INLINE_PROC_LAZY_BINDING") {
+            RTLD_LAZY
+        } else {
+            RTLD_NOW
+        };
+        unsafe { UnixLibrary::open(Some(path), binding | RTLD_LOCAL) }.map(Library::from)
+    }
+    #[cfg(not(unix))]
+    {
+        // Windows always binds eagerly.
+        let _ = lazy;
+        unsafe { Library::new(path) }
+    }
+}
+
+/// The arguments passed to a macro function after its input, which depend on its options.
+struct ExtraArgs<'a> {
+    host_crate: Option<TokenStream1>,
+    location: Option<&'a Location>,
+}
+
+/// The signature of a bang or derive macro with `location`, which also takes the file, line and
+/// column it was invoked at.
+type LocatedMacro = fn(TokenStream1, &str, usize, usize) -> TokenStream1;
+/// The signature of an attribute macro with `location`.
+type LocatedAttributeMacro = fn(TokenStream1, TokenStream1, &str, usize, usize) -> TokenStream1;
+/// The signature of a bang or derive macro with `host_crate` and `location`.
+type HostLocatedMacro = fn(TokenStream1, TokenStream1, &str, usize, usize) -> TokenStream1;
+/// The signature of an attribute macro with `host_crate` and `location`.
+type HostLocatedAttributeMacro =
+    fn(TokenStream1, TokenStream1, TokenStream1, &str, usize, usize) -> TokenStream1;
+
+/// Call a bang or derive macro, which take a single token stream.
+unsafe fn call_macro(
+    library: &Library,
+    macro_type: &str,
+    name: &Ident,
+    extra: &ExtraArgs,
+    input: TokenStream1,
+) -> TokenStream1 {
+    match (extra.host_crate.clone(), extra.location) {
+        (Some(host_crate), Some(location)) => {
+            let macro_function: Symbol<HostLocatedMacro> =
+                unsafe { library_macro(library, macro_type, name.clone()) };
+            macro_function(
+                input,
+                host_crate,
+                &location.file,
+                location.line,
+                location.column,
+            )
+        }
+        (Some(host_crate), None) => {
+            let macro_function: Symbol<fn(TokenStream1, TokenStream1) -> TokenStream1> =
+                unsafe { library_macro(library, macro_type, name.clone()) };
+            macro_function(input, host_crate)
+        }
+        (None, Some(location)) => {
+            let macro_function: Symbol<LocatedMacro> =
+                unsafe { library_macro(library, macro_type, name.clone()) };
+            macro_function(input, &location.file, location.line, location.column)
+        }
+        (None, None) => {
+            let macro_function: Symbol<fn(TokenStream1) -> TokenStream1> =
+                unsafe { library_macro(library, macro_type, name.clone()) };
+            macro_function(input)
+        }
+    }
+}
+
+unsafe fn call_attribute_macro(
+    library: &Library,
+    name: &Ident,
+    extra: &ExtraArgs,
+    attr: TokenStream1,
+    item: TokenStream1,
+) -> TokenStream1 {
+    match (extra.host_crate.clone(), extra.location) {
+        (Some(host_crate), Some(location)) => {
+            let macro_function: Symbol<HostLocatedAttributeMacro> =
+                unsafe { library_macro(library, "attribute", name.clone()) };
+            macro_function(
+                attr,
+                item,
+                host_crate,
+                &location.file,
+                location.line,
+                location.column,
+            )
+        }
+        (Some(host_crate), None) => {
+            let macro_function: Symbol<
+                fn(TokenStream1, TokenStream1, TokenStream1) -> TokenStream1,
+            > = unsafe { library_macro(library, "attribute", name.clone()) };
+            macro_function(attr, item, host_crate)
+        }
+        (None, Some(location)) => {
+            let macro_function: Symbol<LocatedAttributeMacro> =
+                unsafe { library_macro(library, "attribute", name.clone()) };
+            macro_function(attr, item, &location.file, location.line, location.column)
+        }
+        (None, None) => {
+            let macro_function: Symbol<fn(TokenStream1, TokenStream1) -> TokenStream1> =
+                unsafe { library_macro(library, "attribute", name.clone()) };
+            macro_function(attr, item)
+        }
+    }
+}
+
+/// The signature of a macro with `proc_macro2`, which takes its input as source code and the byte
+/// ranges of its parts, and returns its output encoded as a string.
+type StringMacro = fn(&str, &[Range<usize>]) -> String;
+/// The signature of a macro with `proc_macro2` and `location`.
+type LocatedStringMacro = fn(&str, &[Range<usize>], &str, usize, usize) -> String;
+
+/// Call a macro with `proc_macro2`. Its parts are its input, or the attribute and item of an
+/// attribute macro, followed by the host crate.
+unsafe fn call_string_macro(
+    library: &Library,
+    macro_type: &str,
+    name: &Ident,
+    extra: &ExtraArgs,
+    mut parts: Vec<TokenStream>,
+    call_site: Span,
+) -> TokenStream1 {
+    parts.extend(extra.host_crate.clone().map(TokenStream::from));
+    let source = Source::new(parts, call_site);
+    let output = match extra.location {
+        Some(location) => {
+            let macro_function: Symbol<LocatedStringMacro> =
+                unsafe { library_macro(library, macro_type, name.clone()) };
+            macro_function(
+                &source.text,
+                &source.parts,
+                &location.file,
+                location.line,
+                location.column,
+            )
+        }
+        None => {
+            let macro_function: Symbol<StringMacro> =
+                unsafe { library_macro(library, macro_type, name.clone()) };
+            macro_function(&source.text, &source.parts)
+        }
+    };
+    source.decode(&output).into()
+}
+
+/// The input of a macro with `proc_macro2`, written as source code for it to parse with
+/// `proc_macro2`, which can't use the tokens of the compiler outside of a procedural macro.
+///
+/// Tokens are laid out as in the code they come from where possible, so that the source of macros
+/// with `deserialize` reads like their input. The span of each byte is kept to map the spans of the
+/// output back.
+struct Source {
+    text: String,
+    /// The byte range of each part of the input.
+    parts: Vec<Range<usize>>,
+    /// The span of each byte of the text.
+    spans: Vec<Span>,
+    /// Identifiers that can't be parsed from their text, such as `$crate`, by where they start.
+    dollar_idents: HashMap<usize, Ident>,
+    /// Where the last token ended in the code it comes from, if it is known.
+    end: Option<Position>,
+    /// Whether the last token is punctuation joined to the next token.
+    joint: bool,
+    /// The span of tokens of the output that don't come from the input, from `caller_call_site`.
+    call_site: Span,
+}
+
+/// A position in a source file.
+struct Position {
+    file: String,
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    fn start(span: Span) -> Self {
+        Self::of(span.unwrap().start())
+    }
+
+    fn end(span: Span) -> Self {
+        Self::of(span.unwrap().end())
+    }
+
+    fn of(span: proc_macro::Span) -> Self {
+        Self {
+            file: span.file(),
+            line: span.line(),
+            column: span.column(),
+        }
+    }
+}
+
+impl Source {
+    fn new(parts: Vec<TokenStream>, call_site: Span) -> Self {
+        let mut source = Self {
+            text: String::new(),
+            parts: Vec::new(),
+            spans: Vec::new(),
+            dollar_idents: HashMap::new(),
+            end: None,
+            joint: false,
+            call_site,
+        };
+        for part in parts {
+            // Parts are always separated, so tokens at their edges can't be joined.
+            source.end = None;
+            source.joint = false;
+            let start = source.text.len();
+            source.push_tokens(part);
+            source.parts.push(start..source.text.len());
+        }
+        source
+    }
+
+    fn push_tokens(&mut self, tokens: TokenStream) {
+        for token in tokens {
+            self.push_token(token);
+        }
+    }
+
+    fn push_token(&mut self, token: TokenTree) {
+        match &token {
+            TokenTree::Group(group) if group.delimiter() == Delimiter::None => {
+                self.push_tokens(group.stream());
+                return;
+            }
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Bracket => ("[", "]"),
+                    _ => ("{", "}"),
+                };
+                self.push(open, group.span_open());
+                self.push_tokens(group.stream());
+                self.push(close, group.span_close());
+            }
+            TokenTree::Ident(ident) if ident.to_string().starts_with('$') => {
+                self.dollar_idents.insert(self.text.len(), ident.clone());
+                self.push(ident, ident.span());
+            }
+            _ => self.push(&token, token.span()),
+        }
+        self.joint = matches!(&token, TokenTree::Punct(punct) if punct.spacing() == Spacing::Joint);
+    }
+
+    /// Push the text of a token, separated from the previous token like in the code it comes from.
+    fn push(&mut self, text: impl Display, span: Span) {
+        let start = Position::start(span);
+        let separator = match &self.end {
+            _ if self.joint || self.text.is_empty() => String::new(),
+            Some(end)
+                if end.file == start.file
+                    && (start.line, start.column) >= (end.line, end.column) =>
+            {
+                if start.line > end.line {
+                    "\n".repeat(start.line - end.line) + &" ".repeat(start.column.saturating_sub(1))
+                } else {
+                    " ".repeat(start.column - end.column)
+                }
+            }
+            _ => " ".to_owned(),
+        };
+        let separator_span = self.spans.last().copied().unwrap_or(span);
+        self.spans.extend(separator.bytes().map(|_| separator_span));
+        self.text.push_str(&separator);
+
+        let old_len = self.text.len();
+        write!(self.text, "{}", text).unwrap();
+        self.spans.extend((old_len..self.text.len()).map(|_| span));
+        self.end = Some(Position::end(span));
+        self.joint = false;
+    }
+
+    /// The span of the range of bytes of the text, or the call site if it is empty.
+    fn span(&self, range: Range<usize>) -> Span {
+        match (self.spans.get(range.start), range.end.checked_sub(1)) {
+            (Some(&start), Some(end)) if range.start < range.end => self
+                .spans
+                .get(end)
+                .and_then(|&end| start.join(end))
+                .unwrap_or(start),
+            _ => self.call_site,
+        }
+    }
+
+    /// Decode the output of the macro, emitting its diagnostics. See `tokens_module` in
+    /// `inline_proc.rs` for the encoding.
+    fn decode(&self, output: &str) -> TokenStream {
+        Decoder {
+            source: self,
+            rest: output,
+        }
+        .tokens()
+    }
+}
+
+struct Decoder<'a> {
+    source: &'a Source,
+    rest: &'a str,
+}
+
+impl<'a> Decoder<'a> {
+    fn tokens(&mut self) -> TokenStream {
+        let mut tokens = Vec::<TokenTree>::new();
+        loop {
+            let kind = self.word();
+            let token = match kind {
+                "" | "E" => break,
+                "G(" | "G[" | "G{" | "G_" => {
+                    let span = self.span().1;
+                    let delimiter = match kind {
+                        "G(" => Delimiter::Parenthesis,
+                        "G[" => Delimiter::Bracket,
+                        "G{" => Delimiter::Brace,
+                        _ => Delimiter::None,
+                    };
+                    let mut group = Group::new(delimiter, self.tokens());
+                    group.set_span(span);
+                    group.into()
+                }
+                "I" => {
+                    let span = self.span().1;
+                    let ident = self.word();
+                    match ident.strip_prefix("r#") {
+                        Some(ident) => Ident::new_raw(ident, span),
+                        None => Ident::new(ident, span),
+                    }
+                    .into()
+                }
+                "P" => {
+                    let (start, span) = self.span();
+                    let mut punct = self.word().chars();
+                    let (Some(c), Some(spacing)) = (punct.next(), punct.next()) else {
+                        invalid_output();
+                    };
+                    // `$crate` is parsed as `$` followed by `crate`.
+                    if let Some(ident) = self.source.dollar_idents.get(&start).filter(|_| c == '$')
+                    {
+                        self.word();
+                        self.span();
+                        self.word();
+                        ident.clone().into()
+                    } else {
+                        let spacing = if spacing == 'J' {
+                            Spacing::Joint
+                        } else {
+                            Spacing::Alone
+                        };
+                        let mut punct = Punct::new(c, spacing);
+                        punct.set_span(span);
+                        punct.into()
+                    }
+                }
+                "L" => {
+                    let span = self.span().1;
+                    let mut literal = self
+                        .text()
+                        .parse::<Literal>()
+                        .unwrap_or_else(|_| invalid_output());
+                    literal.set_span(span);
+                    literal.into()
+                }
+                "X" | "W" => {
+                    let span = self.span().1;
+                    let level = if kind == "X" {
+                        Level::Error
+                    } else {
+                        Level::Warning
+                    };
+                    Diagnostic::spanned(span, level, self.text().to_owned()).emit();
+                    continue;
+                }
+                _ => invalid_output(),
+            };
+            tokens.push(token);
+        }
+        tokens.into_iter().collect()
+    }
+
+    fn word(&mut self) -> &'a str {
+        let (word, rest) = self.rest.split_once(' ').unwrap_or((self.rest, ""));
+        self.rest = rest;
+        word
+    }
+
+    fn number(&mut self) -> usize {
+        self.word().parse().unwrap_or_else(|_| invalid_output())
+    }
+
+    /// Decode a byte range of the source, returning its start and its span.
+    fn span(&mut self) -> (usize, Span) {
+        let start = self.number();
+        let end = self.number();
+        (start, self.source.span(start..end))
+    }
+
+    /// Decode text preceded by its length.
+    fn text(&mut self) -> &'a str {
+        let len = self.number();
+        let text = self.rest.get(..len).unwrap_or_else(|| invalid_output());
+        self.rest = self.rest[len..].strip_prefix(' ').unwrap_or_default();
+        text
+    }
+}
+
+fn invalid_output() -> ! {
+    abort_call_site!("Invalid output from a macro with `proc_macro2`")
+}
+
 unsafe fn library_macro<'lib, T>(
     library: &'lib Library,
     macro_type: &str,
@@ -86,11 +1159,20 @@ unsafe fn library_macro<'lib, T>(
 
     let symbol = unsafe { library.get::<T>(symbol_name.as_bytes()) };
     symbol.unwrap_or_else(|e| {
+        let note = unsafe { embedded_source(library) }
+            .map(|source| format!("The library was built from this source:\n{}", source));
         abort!(
             macro_name,
             "Failed to load macro {} from library: {}",
             macro_name,
-            e
+            e;
+            note =? note
         )
     })
 }
+
+/// Get the source of the module the library was built from, if it was built with `embed_source`.
+unsafe fn embedded_source(library: &Library) -> Option<&str> {
+    let source = unsafe { library.get::<*const &str>(b"__exported_macro_source\0") }.ok()?;
+    Some(unsafe { **source })
+}
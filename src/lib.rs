@@ -44,6 +44,37 @@
 //! For example, if you call `inline-proc` on Linux from the module `my_module` in `my-nice-crate`
 //! which has version `0.7.3`, a temporary crate will be created in
 //! `/tmp/inline-proc-crates/my-nice-crate-0.7-my_module`.
+//! Non-ASCII characters in the module name are replaced with `_u` followed by their code point in
+//! hexadecimal and followed by a hash of the name, so `mod café` would use
+//! `/tmp/inline-proc-crates/my-nice-crate-0.7-caf_ue9_cfa40d89` and not clash with `mod caf_ue9`.
+//! Package and module names longer than 24 characters are shortened to their first 15 characters
+//! and a hash of the whole name, since Cargo's paths inside the crate can otherwise grow past the
+//! 260 character limit of Windows with deep dependency trees:
+//!
+//! ```
+//! #[inline_proc::inline_proc]
+//! mod a_module_with_a_very_long_name_that_would_not_fit_on_windows {
+//!     metadata::ron!(
+//!         edition: "2021",
+//!         dependencies: {},
+//!         exports: ( bang_macros: { "identity": "identity" } ),
+//!     );
+//!
+//!     pub fn identity(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+//!         input
+//!     }
+//! }
+//!
+//! // Built in `/tmp/inline-proc-crates/my-nice-crate-0.7-a_module_with_a_{hash}`.
+//! identity!(fn built() {});
+//! # fn main() {
+//! built();
+//! # }
+//! ```
+//!
+//! The crates directory contains a marker file recording the version of its layout. If a version of
+//! `inline-proc` with a different layout finds it, the old crates are deleted and rebuilt, once the
+//! compilers that are still using them have finished.
 //!
 //! It then compiles this crate as a `dylib` with Cargo and translates all the outputted errors into
 //! errors from the proc macro, so it appears identical to writing the code inline. Note that proc
@@ -176,6 +207,78 @@
 //! This level of indirection is necessary as proc macros don't have a way of getting the current
 //! crate like MBEs do (`$crate`), so you have to supply it via the MBE method.
 //!
+//! If the macro has a `doc`, the wrapper is generated for you with that documentation, so it
+//! shows up in `cargo doc` (you still need the re-export of `invoke_inline_macro` at the crate
+//! root). Like doc comments, each line of the documentation should start with a space. A macro
+//! that isn't exported can only have a `doc` if it is re-exported with a `visibility`, since
+//! rustdoc doesn't show it otherwise:
+//!
+//! ```
+//! #![deny(missing_docs)]
+//! #![doc = "A crate with a documented inline macro."]
+//! # #![allow(macro_expanded_macro_exports_accessed_by_absolute_paths)]
+//!
+//! #[doc(hidden)]
+//! pub use inline_proc::invoke_inline_macro;
+//!
+//! #[inline_proc::inline_proc]
+//! mod macro_export {
+//!     metadata::ron!(
+//!         edition: "2021",
+//!         dependencies: {},
+//!         exports: (
+//!             bang_macros: {
+//!                 "my_macro": (
+//!                     function: "my_macro",
+//!                     export: true,
+//!                     doc: " This macro does XYZ.",
+//!                 ),
+//!             },
+//!         ),
+//!     );
+//!     pub fn my_macro(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+//!         input
+//!     }
+//! }
+//!
+//! my_macro!(fn answer() -> u32 { 42 });
+//! # fn main() {
+//! assert_eq!(answer(), 42);
+//! # }
+//! ```
+//!
+//! With `reexport_invoker`, the module re-exports `invoke_inline_macro` itself and generates the
+//! wrapper of every exported macro, so neither needs to be written by hand. The option is the path
+//! of the module the `#[inline_proc]` module is in, starting with `crate`; that module must be
+//! public (or the crate root) for other crates to reach the re-export. The re-export goes through
+//! `inline_proc_path`, so this also works if the crate is renamed:
+//!
+//! ```
+//! # #![allow(macro_expanded_macro_exports_accessed_by_absolute_paths)]
+//! extern crate inline_proc as renamed_inline_proc;
+//!
+//! #[renamed_inline_proc::inline_proc]
+//! mod reexported {
+//!     metadata::ron!(
+//!         edition: "2021",
+//!         dependencies: {},
+//!         inline_proc_path: "::renamed_inline_proc",
+//!         reexport_invoker: "crate",
+//!         exports: (
+//!             bang_macros: { "my_macro": ( function: "my_macro", export: true ) },
+//!         ),
+//!     );
+//!     pub fn my_macro(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+//!         input
+//!     }
+//! }
+//!
+//! my_macro!(fn answer() -> u32 { 42 });
+//! # fn main() {
+//! assert_eq!(answer(), 42);
+//! # }
+//! ```
+//!
 //! # Crate attributes
 //!
 //! Inline procedural macros support inner crate attributes.
@@ -208,12 +311,13 @@
 //! - Exporting macros is a pain.
 //! - The macros can only be defined in one file.
 //! - Errors are a lot less helpful. This is improved a bit by Nightly, but still isn't is good as
-//! native proc macro errors.
+//!   native proc macro errors.
 //! - Derive helper attributes are not supported. The `InlineDerive` macro does reserve the `helper`
-//! helper attribute, so you can for example replace `#[my_helper]` with `#[helper[my_helper]]`.
+//!   helper attribute, so you can for example replace `#[my_helper]` with `#[helper[my_helper]]`.
 
 use proc_macro::TokenStream as TokenStream1;
 use proc_macro2::{Group, TokenStream};
+use std::path::{Path as FsPath, PathBuf};
 
 use proc_macro_error2::{abort, proc_macro_error};
 use quote::quote;
@@ -221,8 +325,67 @@ use syn::parse::{Parse, ParseStream};
 use syn::punctuated::Punctuated;
 use syn::{Item, Path, Token};
 
+mod cache;
+mod error;
 mod inline_proc;
 mod invoke;
+mod timings;
+
+/// Describe where a span is, such as the call site of the current macro, for messages printed to
+/// stderr.
+///
+/// The source file is not available for code that doesn't come from a file on disk, such as code
+/// generated by other macros. In that case this falls back to the package's `CARGO_MANIFEST_DIR`.
+fn span_location(span: proc_macro::Span) -> String {
+    match span.local_file() {
+        Some(file) => format!("{}:{}:{}", file.display(), span.line(), span.column()),
+        None => format!(
+            "an unknown file in {}",
+            std::env::var("CARGO_MANIFEST_DIR")
+                .unwrap_or_else(|_| "the current package".to_owned())
+        ),
+    }
+}
+
+/// The path of the dylib of the inner crate whose library is named `lib_name`, built for the host
+/// into the Cargo target directory `target_dir`.
+fn dylib_path(target_dir: &FsPath, lib_name: &str) -> PathBuf {
+    target_dir
+        .join("debug")
+        .join(dylib_file_name(inline_proc::HOST_TARGET, lib_name))
+}
+
+/// The file name of the dylib of the inner crate whose library is named `lib_name`, built for
+/// `target`.
+///
+/// This is the only place the dylib's file name is decided: the module's own dylib, the ones built
+/// for its `targets` and the one `invoke_inline_macro!` picks out of them are all named by it. The
+/// target's usual prefix and extension are used unless the extension is overridden by
+/// `INLINE_PROC_DYLIB_EXTENSION`.
+fn dylib_file_name(target: &str, lib_name: &str) -> String {
+    let (prefix, extension) = if target.contains("windows") {
+        ("", "dll")
+    } else if target.contains("apple") {
+        ("lib", "dylib")
+    } else {
+        ("lib", "so")
+    };
+    match std::env::var("INLINE_PROC_DYLIB_EXTENSION") {
+        Ok(extension) => format!(
+            "{}{}.{}",
+            prefix,
+            lib_name,
+            extension.trim_start_matches('.')
+        ),
+        Err(_) => format!("{}{}.{}", prefix, lib_name, extension),
+    }
+}
+
+/// Whether the environment variable is set to a truthy value.
+fn env_flag(name: &str) -> bool {
+    std::env::var_os(name)
+        .is_some_and(|value| !value.is_empty() && value != "0" && value != "false")
+}
 
 /// Write an inline procedural macro.
 ///
@@ -239,6 +402,61 @@ mod invoke;
 /// strips out all whitespace. Additionally, the `proc_macro_span` feature is unstable so we can't
 /// even reconstruct the whitespace.
 ///
+/// `metadata` isn't a real path, so it works however this crate is imported. It may also be
+/// written after the path of this crate, such as `inline_proc::metadata::ron!`; everything before
+/// `metadata` is ignored. If this crate is renamed in `Cargo.toml`, set `inline_proc_path` below so that
+/// the generated macros can still find it.
+///
+/// ```
+/// use inline_proc as ip;
+///
+/// #[ip::inline_proc]
+/// mod plain {
+///     metadata::ron!(
+///         edition: "2021",
+///         dependencies: {},
+///         exports: ( bang_macros: { "plain_macro": ( passthrough: true ) } ),
+///     );
+/// }
+///
+/// #[ip::inline_proc]
+/// mod prefixed {
+///     ip::metadata::ron!(
+///         edition: "2021",
+///         dependencies: {},
+///         exports: ( bang_macros: { "prefixed_macro": ( passthrough: true ) } ),
+///     );
+/// }
+///
+/// plain_macro!(const PLAIN: u8 = 1;);
+/// prefixed_macro!(const PREFIXED: u8 = 2;);
+/// assert_eq!(PLAIN + PREFIXED, 3);
+/// ```
+///
+/// ## Defaults File
+///
+/// Options shared by many modules can be put in a file, which a module merges under its metadata
+/// with the `defaults` option, whose path is relative to `Cargo.toml`. Options set by the module
+/// override the ones in the file, except for maps and structs such as `dependencies`, whose
+/// entries are merged so that a module can add dependencies to the default ones. The file has the
+/// same contents as the metadata macro, in the same format, but it can't set `exports`, which
+/// belong to each module. A module never reads a file it doesn't name.
+///
+/// The compiler reruns the macro when the file changes, since the module's output includes it
+/// with `include_bytes!` in an unnamed constant, by a path relative to the module's source file.
+///
+/// ```ignore
+/// // inline-proc-defaults.ron contains `edition: "2021", dependencies: { "quote": "1" },`.
+/// #[inline_proc::inline_proc]
+/// mod defaulted {
+///     metadata::ron!(
+///         defaults: "inline-proc-defaults.ron",
+///         dependencies: { "syn": "2" },
+///         exports: (),
+///     );
+/// }
+/// ```
+///
 /// ## Metadata Options
 ///
 // / ```
@@ -251,27 +469,195 @@ mod invoke;
 // /         // to compile the proc macro (the $CARGO env variable).
 // /         cargo: "cargo",
 // /
+// /         // The Cargo subcommand that builds the macro, and arguments to pass it. A path relative
+// /         // to your package is run in place of Cargo. Default is `["build"]`. See "Build
+// /         // Subcommands" below.
+// /         cargo_subcommand: ["zigbuild"],
+// /
+// /         // A file with default metadata to merge this metadata into, relative to `Cargo.toml`.
+// /         // Default is none. See "Defaults File" above.
+// /         defaults: "inline-proc-defaults.ron",
+// /
+// /         // The path to the rustc used to build the macro. By default it uses the same one as
+// /         // the one compiling this crate; `None` leaves it up to Cargo. See "Compiler Version"
+// /         // below.
+// /         rustc: "rustc",
+// /
+// /         // The wrapper Cargo runs rustc through, such as sccache. Defaults to `$RUSTC_WRAPPER`;
+// /         // an empty path disables it. See "Compiler Wrappers" below.
+// /         rustc_wrapper: "sccache",
+// /
 // /         // Whether to pass `--color=always` to Cargo; otherwise the lines printed by Cargo will
-// /         // not appear in color. Default is true.
+// /         // not appear in color. `NO_COLOR` turns this off. Default is true.
 // /         color: true,
 // /
+// /         // Whether to also build the inner crate as an `rlib`, so that the macro functions can
+// /         // be linked into tests. The macros are still loaded from the `dylib`. Default is false.
+// /         rlib: false,
+// /
+// /         // Whether to embed the source of the module in the compiled macros, so that the
+// /         // source a cached library was built from can be found. It is stored as a
+// /         // `&'static str` in the exported static `__exported_macro_source`. Default is false.
+// /         embed_source: false,
+// /
+// /         // A command to run before building the macro, such as a code generator. See
+// /         // "Pre-build Commands" below. Default is none.
+// /         pre_build: ["protoc", "--rust_out=.", "schema.proto"],
+// /
+// /         // Files created by `pre_build`, relative to the inner crate. Default is none.
+// /         pre_build_outputs: ["schema.rs"],
+// /
+// /         // Where to write a depfile listing the files the macro is built from, relative to the
+// /         // package. See "Depfiles" below. Default is none.
+// /         depfile: "target/my_macros.d",
+// /
+// /         // Native libraries to link the macro against, in the format of rustc's `-l` flag:
+// /         // `[KIND[:MODIFIERS]=]NAME[:RENAME]`. See "Native Libraries" below. Default is none.
+// /         links: ["z", "static=foo"],
+// /
+// /         // Directories to search for native libraries in, in the format of rustc's `-L` flag:
+// /         // `[KIND=]PATH`. Default is none.
+// /         link_search: ["native=/opt/foo/lib"],
+// /
+// /         // Target triples to also build the macro for, with `cargo build --target`, such as
+// /         // "aarch64-apple-darwin". "host-tuple" is the host. See "Building for Other Targets"
+// /         // below. Default is none.
+// /         targets: ["x86_64-pc-windows-msvc", "aarch64-apple-darwin"],
+// /
+// /         // The prefix of a module to wrap the output items of every macro of this module in.
+// /         // See "Post-Processing" below. Default is none.
+// /         wrap_output: "generated",
+// /
+// /         // Attributes to add to every item output by the macros of this module, after
+// /         // wrapping it with `wrap_output`. Default is none.
+// /         output_attributes: ["allow(dead_code)", "rustfmt::skip"],
+// /
+// /         // A binary that runs one of the macros on sample input. See "Harness" below. Default
+// /         // is none.
+// /         harness: ( macro: "my_nice_macro", input: "some tokens" ),
+// /
+// /         // Whether to also document the inner crate with `cargo doc`, and the flags to pass to
+// /         // rustdoc. Default is false and no flags. See "Documenting the Inner Crate" below.
+// /         generate_docs: true,
+// /         rustdoc_flags: ["--document-private-items"],
+// /
+// /         // Cargo configuration overrides, each passed to Cargo as `--config key=value`.
+// /         cargo_config: {
+// /             "net.git-fetch-with-cli": true,
+// /         },
+// /
+// /         // Whether to pass `--quiet` to Cargo, so that only diagnostics are printed and not
+// /         // lines like "Compiling" and "Finished". Default is true.
+// /         quiet: true,
+// /
 // /         // Whether to check the code with Clippy. Default is false.
 // /         clippy: true,
 // /
+// /         // How to display the diagnostics from compiling the macro: "full" shows the notes and
+// /         // help messages attached to each diagnostic, "short" only shows the main message.
+// /         // Default is "full".
+// /         diagnostics: "full",
+// /
+// /         // A command that formats Rust code from stdin to stdout, used to show the code generated
+// /         // around the macro functions in errors. See "Errors in Generated Code" below. Default
+// /         // is none, which shows the code unformatted.
+// /         pretty_printer: ["rustfmt", "--edition", "2021"],
+// /
+// /         // Whether to only report the first error from compiling the macro, since the
+// /         // following errors are often caused by it. Warnings are still reported. Default is
+// /         // false.
+// /         fail_fast: false,
+// /
+// /         // How many times to retry when Cargo fails for a reason other than a compile error,
+// /         // such as a network error while downloading dependencies. See "Retries" below.
+// /         // Default is 0.
+// /         build_retries: 2,
+// /
+// /         // How many jobs Cargo runs in parallel when building the macro, like its `--jobs`
+// /         // flag. This can be lowered to avoid oversubscribing the CPU when many modules are
+// /         // built at once. Default is Cargo's default, the number of CPUs.
+// /         jobs: 2,
+// /
+// /         // Whether to build the macro with incremental compilation, which can speed up
+// /         // rebuilds after small changes but makes the cached crate much larger. Default is
+// /         // false. See "Incremental Compilation" below.
+// /         incremental: false,
+// /
+// /         // Whether to bind the symbols of the compiled macro when they are first used, instead
+// /         // of when it is loaded. Only applies on Unix. Default is false. See "Environment
+// /         // Variables" below.
+// /         lazy_binding: false,
+// /
+// /         // The panic strategy of the macro, `unwind` or `abort`. Default is `unwind`, whatever
+// /         // the host's profile uses. See "Panic Strategy" below.
+// /         panic: "unwind",
+// /
+// /         // Whether to make the build of the macro reproducible, normalizing the modification
+// /         // time of the dylib and remapping the paths of the inner crate. Default is false. See
+// /         // "Reproducible Builds" below.
+// /         reproducible: false,
+// /
+// /         // Whether to build this module's crate as part of a workspace shared by all the
+// /         // inline modules in this package that enable this option, so that their dependencies
+// /         // are only compiled once. Default is false.
+// /         shared_workspace: true,
+// /
+// /         // Whether to find this module's crate by the hash of its contents instead of by the
+// /         // package and module name, so that identical modules in different packages share one
+// /         // build. See "Shared Cache" below. Can't be used with `shared_workspace`. Default is
+// /         // false.
+// /         shared_cache: false,
+// /
 // /         // The edition to use. Default is 2015 edition.
 // /         edition: "2024",
 // /
+// /         // The minimum supported Rust version of the macro, checked by Cargo. Default is none.
+// /         rust_version: "1.85",
+// /
+// /         // The feature resolver version of the inner crate, which affects how the features of
+// /         // dependencies are unified. Can't be used with `shared_workspace`, which always uses
+// /         // resolver 2. Default is the resolver implied by the edition.
+// /         resolver: "2",
+// /
+// /         // Extra fields of the `[package]` section of the inner crate's Cargo.toml, such as
+// /         // `license` or `authors`. `publish` defaults to false. Default is none.
+// /         package_metadata: { "license": "MIT", "authors": ["Me"] },
+// /
 // /         // The dependencies of the proc macro. This is in the same format as Cargo.toml's
-// /         // `[dependencies]` section.
+// /         // `[dependencies]` section. No dependencies are added implicitly, not even
+// /         // `proc-macro2` for macros with `proc_macro2: true`, so a macro that only uses
+// /         // `proc_macro` has nothing to build but itself.
 // /         dependencies: {
 // /             "proc-macro2": "1",
 // /             "syn": ( version: "2", features: ["full"] ),
+// /             // Dependencies can be renamed with `package`, and used as `my_syn` in the code.
+// /             "my_syn": ( version: "1", package: "syn" ),
+// /             // Dependencies can be inherited from `[workspace.dependencies]` of the
+// /             // workspace the macro is defined in. Since the inner crate is not part of that
+// /             // workspace, the definition is copied from the workspace manifest.
+// /             "quote": ( workspace: true ),
 // /         },
 // /
+// /         // The only packages the dependencies may be, if set, and packages they may not be.
+// /         // Only direct dependencies are checked, unless `check_transitive_dependencies` is
+// /         // true. Default is no restrictions. See "Restricting Dependencies" below.
+// /         allowed_dependencies: ["proc-macro2", "syn", "quote", "unicode-ident"],
+// /         denied_dependencies: ["openssl"],
+// /         check_transitive_dependencies: true,
+// /
 // /         // The path to use for the `inline_proc` crate inside non-exported macros. Defaults to
 // /         // `::inline_proc`. Use this if you have renamed the crate.
 // /         inline_proc_path: "::inline_proc",
 // /
+// /         // The path of the module this module is in, from the crate root. If this is set, the
+// /         // module re-exports `invoke_inline_macro` there and generates the wrappers of its
+// /         // exported macros. See "Exporting the macros" in the crate docs. Default is none.
+// /         reexport_invoker: "crate",
+// /
+// /         // Whether to warn about macros that are never invoked in the crate. Default is false.
+// /         // See "Unused Macros" below.
+// /         warn_unused: true,
+// /
 // /         // The macros exported by this module.
 // /         exports: (
 // /             // The bang macros exported by this module.
@@ -281,6 +667,66 @@ mod invoke;
 // /                 // You can use this form to export the macros. See the crate root for an
 // /                 // explanation of how this works.
 // /                 "my_public_macro": ( function: "my_nice_macro", export: true ),
+// /                 // This macro is only generated when the `advanced` feature of the crate the
+// /                 // module is in is enabled.
+// /                 "my_advanced_macro": ( function: "my_nice_macro", feature_gate: "advanced" ),
+// /                 // Only one macro of an exclusive group can be enabled at a time, chosen by
+// /                 // their feature gates. See "Exclusive Groups" below.
+// /                 "my_fast_macro": (
+// /                     function: "my_nice_macro",
+// /                     feature_gate: "fast",
+// /                     group: "backend",
+// /                     exclusive: true,
+// /                 ),
+// /                 // By default the tokens returned by the macro keep the spans it gave them, like
+// /                 // a native proc macro. "call_site" gives every token the call site span, and
+// /                 // "mixed_site" gives every token the mixed site span, making local variables
+// /                 // and labels hygienic. "argument" gives every token the span of a token
+// /                 // passed before the input. See "Span Arguments" below. The call site resolves
+// /                 // names like the start of the input, so the caller can use the local variables
+// /                 // the macro declares, except for bang macros invoked with no input.
+// /                 "my_hygienic_macro": ( function: "my_nice_macro", spans: "mixed_site" ),
+// /                 // This macro can be used by path from elsewhere in the crate. See "Macro
+// /                 // Scoping" below.
+// /                 "my_crate_macro": ( function: "my_nice_macro", visibility: "pub(crate)" ),
+// /                 // The documentation of this macro. For an exported macro, this also generates
+// /                 // its public wrapper; see "Exporting the macros" in the crate docs. Other
+// /                 // macros need a `visibility`, since only re-exported macros are documented.
+// /                 "my_documented_macro": (
+// /                     function: "my_nice_macro",
+// /                     visibility: "pub(crate)",
+// /                     doc: " Does XYZ.",
+// /                 ),
+// /                 // This macro's function also takes the file, line and column it was invoked
+// /                 // at. See "Invocation Location" below.
+// /                 "my_located_macro": ( function: "my_located_macro", location: true ),
+// /                 // This macro's function also takes a path to the crate that defines the
+// /                 // macro, like `$crate`. See "Host Crate" below.
+// /                 "my_crate_relative_macro": ( function: "my_crate_relative_macro", host_crate: true ),
+// /                 // This macro is deprecated, with a note that is shown when it's used. See
+// /                 // "Deprecating Macros" below.
+// /                 "my_old_macro": ( function: "my_nice_macro", deprecated: "use `my_macro!`" ),
+// /                 // This macro's function returns `inline_proc::MacroOutput`, to report
+// /                 // diagnostics along with its output. See "Diagnostics" below.
+// /                 "my_diagnosing_macro": ( function: "my_diagnosing_macro", macro_output: true ),
+// /                 // This macro's function takes its input already parsed into a `syn` type. See
+// /                 // "Parsed Input" below.
+// /                 "my_parsed_macro": ( function: "my_parsed_macro", parse: "syn::Expr" ),
+// /                 // This macro's function takes its input deserialized from RON or JSON into a
+// /                 // type implementing `Deserialize`. See "Typed Input" below.
+// /                 "my_typed_macro": ( function: "my_typed_macro", deserialize: ( format: "ron", type: "MyConfig" ) ),
+// /                 // This macro only accepts an expression, which is checked by its `macro_rules!`
+// /                 // wrapper. See "Input Fragments" below.
+// /                 "my_expr_macro": ( function: "my_nice_macro", input_fragment: "expr" ),
+// /                 // This macro can also be invoked with `include "path"`, to pass it the
+// /                 // contents of a file. See "Output" below.
+// /                 "my_file_macro": ( function: "my_nice_macro", include: true ),
+// /                 // This macro's output is put in a module of its own, whose public items are
+// /                 // re-exported. See "Output Modules" below.
+// /                 "my_prolific_macro": ( function: "my_nice_macro", output_module: true ),
+// /                 // This macro expands to its input without calling a function. See
+// /                 // "Passthrough Macros" below.
+// /                 "my_passthrough_macro": ( passthrough: true ),
 // /             },
 // /             // The derive macros exported by this module.
 // /             derives: {
@@ -289,6 +735,10 @@ mod invoke;
 // /             // The attribute macros exported by this module.
 // /             attributes: {
 // /                 "my_attribute_macro": "my_attribute_macro",
+// /                 // Any macro function can use `proc_macro2::TokenStream` instead of
+// /                 // `proc_macro::TokenStream` if it sets `proc_macro2: true`. This requires
+// /                 // `proc-macro2` to be in the dependencies.
+// /                 "my_proc_macro2_attribute": ( function: "my_proc_macro2_attribute", proc_macro2: true ),
 // /             },
 // /         )
 // /     );
@@ -321,9 +771,1167 @@ mod invoke;
 // /     pub fn my_attribute_macro(_attr: TokenStream, item: TokenStream) -> TokenStream {
 // /         item
 // /     }
+// /
+// /     pub fn my_proc_macro2_attribute(
+// /         _attr: proc_macro2::TokenStream,
+// /         item: proc_macro2::TokenStream,
+// /     ) -> proc_macro2::TokenStream {
+// /         item
+// /     }
 // / }
 // / ```
 ///
+/// ## Metadata Errors
+///
+/// Errors in the metadata point at the option that caused them, such as an invalid
+/// `rust_version`. If the option has a default and wasn't written out, the error points at the closest
+/// enclosing key instead, or at the whole module if there is none.
+///
+/// ## Editions
+///
+/// The module's code is compiled with the metadata's `edition`, which defaults to 2015, and not
+/// the edition of your crate. When the code uses syntax from a newer edition, such as `async fn`
+/// with edition 2015, the error gets a help message with the edition to set in the metadata, in
+/// place of rustc's suggestion to edit `Cargo.toml`:
+///
+/// ```text
+/// error: `async fn` is not permitted in Rust 2015
+///   = help: the inner crate is built with edition 2015; set `edition: "2018"` in the metadata of the module to use this
+/// ```
+///
+/// ## Reserved Names
+///
+/// The inner crate contains `extern crate proc_macro;`, a function named
+/// `__exported_macro_{type}_{name}` for each exported macro and, with `embed_source`, a static
+/// named `__exported_macro_source`. So the module must not contain items with names starting with
+/// `__exported_macro_` nor its own `extern crate proc_macro;`. If any macro uses `macro_output`,
+/// the name `inline_proc` is reserved too.
+///
+/// ## Return Types
+///
+/// Exported macro functions must return a `proc_macro::TokenStream`, or a
+/// `proc_macro2::TokenStream` with `proc_macro2: true`. Functions defined directly in the module
+/// that clearly return something else, such as `()` or a reference, are rejected with an error
+/// pointing at their signature.
+///
+/// ## Macros Using `proc_macro2`
+///
+/// `proc_macro` can only be used by the compiler's own procedural macros, so the macros of the
+/// inner crate can't use it directly. With `proc_macro2: true`, the input is passed to the inner
+/// crate as source code, parsed by `proc-macro2` and the output is passed back as text. The
+/// `span-locations` feature of `proc-macro2` is enabled for this, so the spans of input tokens are
+/// kept, and errors in the output point at the input. Tokens the macro creates itself, with
+/// `Span::call_site()` or `quote!`, get the span of the invocation.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod doubling {
+///     metadata::ron!(
+///         edition: "2021",
+///         dependencies: { "proc-macro2": "1", "quote": "1" },
+///         exports: ( bang_macros: { "double": ( function: "double", proc_macro2: true ) } ),
+///     );
+///     use proc_macro2::TokenStream;
+///
+///     pub fn double(input: TokenStream) -> TokenStream {
+///         quote::quote!((#input) * 2)
+///     }
+/// }
+///
+/// assert_eq!(double!(1 + 2), 6);
+/// ```
+///
+/// ## Macro Scoping
+///
+/// The generated `macro_rules!` macros replace the `#[inline_proc]` module, so like any
+/// `macro_rules!` macro they can only be used textually after it, in the same module or its
+/// children (or everywhere after it with `#[macro_use]` on the parent module).
+///
+/// Setting `visibility` on a macro also re-exports it with that visibility, for example
+/// `pub(crate) use my_macro;`, so it can be used by path like other items, such as
+/// `crate::macros::my_macro!()` or `super::my_macro!()` from a sibling module. The visibility must
+/// be restricted, like `pub(crate)`, `pub(super)` or `pub(in crate::path)`, since a `macro_rules!`
+/// macro can't be re-exported outside its crate, and it can't be combined with `export: true`,
+/// which is the way to do that. For exported macros, note that macros generated by another macro
+/// with `#[macro_export]` can't be referred to by an absolute path like `crate::my_macro!` in the
+/// same crate, which is why `export: true` requires a wrapper macro.
+///
+/// ```
+/// mod macros {
+///     #[inline_proc::inline_proc]
+///     mod my_macros {
+///         metadata::ron!(
+///             edition: "2021",
+///             dependencies: {},
+///             exports: (bang_macros: {
+///                 "my_macro": ( function: "my_macro", visibility: "pub(crate)" ),
+///             }),
+///         );
+///
+///         pub fn my_macro(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+///             input
+///         }
+///     }
+/// }
+///
+/// mod sibling {
+///     pub fn answer() -> i32 {
+///         crate::macros::my_macro!(42)
+///     }
+/// }
+///
+/// fn main() {
+///     assert_eq!(sibling::answer(), 42);
+/// }
+/// ```
+///
+/// ## Deprecating Macros
+///
+/// A macro with a `deprecated` note gets `#[deprecated(note = "...")]`, so using it causes a
+/// warning just like any other deprecated item. For an exported macro, the attribute is put on the
+/// wrapper that is generated for it, as for a `doc`, since the `_inner` macro is only used by the
+/// wrapper. Like with a `doc`, the wrapper needs `invoke_inline_macro` re-exported at the crate
+/// root, or `reexport_invoker`.
+///
+/// ```
+/// # #![allow(macro_expanded_macro_exports_accessed_by_absolute_paths)]
+/// #[doc(hidden)]
+/// pub use inline_proc::invoke_inline_macro;
+///
+/// #[inline_proc::inline_proc]
+/// mod my_macros {
+///     metadata::ron!(
+///         edition: "2021",
+///         dependencies: {},
+///         exports: (bang_macros: {
+///             "old_macro": (
+///                 function: "old_macro",
+///                 export: true,
+///                 deprecated: "use `new_macro!` instead",
+///             ),
+///         }),
+///     );
+///
+///     pub fn old_macro(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+///         input
+///     }
+/// }
+///
+/// # fn main() {
+/// // warning: use of deprecated macro `old_macro`: use `new_macro!` instead
+/// old_macro!(println!("still works"));
+/// # }
+/// ```
+///
+/// ## Exclusive Groups
+///
+/// Macros that are alternatives to each other, like backends to choose from, can be put in the
+/// same `group` with `exclusive: true`. Each of them needs a `feature_gate` of its own, which
+/// selects it: only the macros whose features are enabled are generated, as usual, and enabling
+/// the features of more than one macro of the group is a compile error. Every macro of a group
+/// must be `exclusive` if one of them is.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod backends {
+///     metadata::ron!(
+///         edition: "2021",
+///         dependencies: {},
+///         exports: (bang_macros: {
+///             // In these tests, `default` is enabled and `simd` isn't.
+///             "scalar": (
+///                 function: "identity",
+///                 group: "backend",
+///                 exclusive: true,
+///                 feature_gate: "default",
+///             ),
+///             "simd": (
+///                 function: "identity",
+///                 group: "backend",
+///                 exclusive: true,
+///                 feature_gate: "simd",
+///             ),
+///         }),
+///     );
+///
+///     pub fn identity(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+///         input
+///     }
+/// }
+///
+/// scalar!(fn selected() {});
+/// # fn main() {
+/// selected();
+/// # }
+/// ```
+///
+/// Enabling a second macro of the group fails with "Only one macro of exclusive group `backend`
+/// can be enabled".
+///
+/// ## Unused Macros
+///
+/// Every module pays for building its dylib, even if none of its macros are used. With
+/// `warn_unused: true`, or `INLINE_PROC_WARN_UNUSED=1` for every module, the compiler's
+/// `unused_macros` lint warns about each macro that is never invoked in the crate, pointing at its
+/// entry in `exports`. Such entries can be removed, and the whole module if none of its macros are
+/// used. This is best-effort: exported macros are never reported, since other crates may use them,
+/// and neither are macros used only through their `visibility` re-export.
+///
+/// ## Invocation Location
+///
+/// A native proc macro can find out where it was invoked from the location of
+/// `Span::call_site()`, but the call site of an inline macro is inside the `macro_rules!` macro
+/// generated from the `#[inline_proc]` module. Instead, with `location: true` the macro function
+/// takes three more parameters after its input: the file, line and column it was invoked at.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod located {
+///     metadata::ron!(
+///         edition: "2021",
+///         dependencies: { "proc-macro2": "1", "quote": "1" },
+///         exports: (
+///             bang_macros: { "here": ( function: "here", location: true, proc_macro2: true ) },
+///         ),
+///     );
+///     use proc_macro2::TokenStream;
+///
+///     pub fn here(_input: TokenStream, file: &str, line: usize, column: usize) -> TokenStream {
+///         let location = format!("{}:{}:{}", file, line, column);
+///         quote::quote!(#location)
+///     }
+/// }
+///
+/// let location: &str = here!(input);
+/// // The location of `input`.
+/// assert!(location.ends_with(&format!(":{}:28", line!() - 2)));
+/// ```
+///
+/// The location is that of the start of the macro's input, since it's the closest to the
+/// invocation that a proc macro can see on stable Rust (the APIs to get the original invocation,
+/// `Span::parent` and `Span::source`, are nightly-only). So in `here!(a b)` it is the location of
+/// `a`, and for attribute and derive macros it is the start of the item, including its other
+/// attributes. A bang macro with no input gets the location of the `#[inline_proc]` module
+/// instead.
+///
+/// As with `file!()`, `line!()` and `column!()`, the line and column start at 1 and the file is
+/// relative to the directory the compiler was run in, which for Cargo is the workspace root. For
+/// code that doesn't come from a file on disk, such as code generated by another macro, the file
+/// may not be a real path. This requires Rust 1.88 or later, where these span APIs were
+/// stabilized.
+///
+/// ## Host Crate
+///
+/// Proc macros can't refer to the crate they are used from, which is why exported macros need a
+/// `macro_rules!` wrapper (see "Exporting the macros" in the crate docs). With `host_crate: true`,
+/// the generated `macro_rules!` passes its `$crate` to `invoke_inline_macro!`, which passes it to
+/// the macro function as another `TokenStream` parameter after its input, before the `location`
+/// parameters if there are any. Paths in the output that start with it refer to items of the crate
+/// that defines the macro, from wherever the macro is used, including other crates when the macro
+/// is exported with `export: true`.
+///
+/// ```
+/// # #![allow(macro_expanded_macro_exports_accessed_by_absolute_paths)]
+/// pub fn greeting() -> &'static str {
+///     "Hello"
+/// }
+///
+/// #[inline_proc::inline_proc]
+/// mod greet {
+///     metadata::ron!(
+///         edition: "2021",
+///         dependencies: { "proc-macro2": "1", "quote": "1" },
+///         reexport_invoker: "crate",
+///         exports: (
+///             bang_macros: {
+///                 "greet": (
+///                     function: "greet",
+///                     host_crate: true,
+///                     proc_macro2: true,
+///                     export: true,
+///                 ),
+///             },
+///         ),
+///     );
+///     use proc_macro2::TokenStream;
+///
+///     pub fn greet(_input: TokenStream, host_crate: TokenStream) -> TokenStream {
+///         quote::quote!(#host_crate::greeting())
+///     }
+/// }
+///
+/// # fn main() {
+/// assert_eq!(greet!(), "Hello");
+/// # }
+/// ```
+///
+/// With `proc_macro2: true`, as here, `host_crate` is a `proc_macro2::TokenStream` too. The harness
+/// passes `crate`.
+///
+/// ## Span Arguments
+///
+/// With `spans: "argument"`, a bang macro takes a token before its input, separated by a comma,
+/// and every token of its output is given that token's span. Errors in the output, such as those
+/// from `compile_error!`, then point at the token the user chose, which is useful for macros that
+/// report errors about something other than their own invocation. The macro function only gets
+/// the rest of the input, so `report!(field, "must not be empty")` calls the function with
+/// `"must not be empty"` and attributes the output to `field`. The token can be any single token
+/// tree, including a group in brackets.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod spanned {
+///     metadata::ron!(
+///         edition: "2021",
+///         dependencies: {},
+///         exports: (
+///             bang_macros: { "spanned": ( function: "spanned", spans: "argument" ) },
+///         ),
+///     );
+///
+///     pub fn spanned(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+///         input
+///     }
+/// }
+///
+/// let local = 1;
+/// macro_rules! in_macro_scope {
+///     ($($input:tt)*) => {
+///         let local = 2;
+///         // The output has the span of this `local`, so it sees the macro's own variable.
+///         spanned!(local, $($input)*);
+///     };
+/// }
+/// in_macro_scope!(assert_eq!(local, 2););
+/// # let _ = local;
+/// ```
+///
+/// Only bang macros can take a span argument; `spans: "argument"` on a derive or attribute macro
+/// is an error.
+///
+/// ## Color
+///
+/// With `color: true`, the default, Cargo is run with `--color=always` so that its output keeps
+/// its colors when it's shown by the outer Cargo. Following the [`NO_COLOR`](https://no-color.org)
+/// convention, setting `NO_COLOR` to any non-empty value runs it with `--color=never` instead,
+/// whatever `color` is set to, unless `FORCE_COLOR` is also set. `FORCE_COLOR` only undoes
+/// `NO_COLOR`, and doesn't turn on color when `color` is false.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod colored {
+///     metadata::ron!(
+///         edition: "2021",
+///         color: true,
+///         dependencies: {},
+///         exports: (),
+///     );
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ## Jobs
+///
+/// `jobs` is passed to Cargo as `--jobs` when checking and building the macro. It only limits the
+/// parallelism within the build of one module; modules that are expanded in parallel still each
+/// run their own Cargo.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod limited {
+///     metadata::ron!(
+///         edition: "2021",
+///         jobs: 1,
+///         dependencies: {},
+///         exports: (),
+///     );
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ## Incremental Compilation
+///
+/// The inner crate is built with `CARGO_INCREMENTAL=0` unless `incremental` is true, in which case
+/// it's built with `CARGO_INCREMENTAL=1`. Incremental compilation mostly helps when a module is
+/// edited and rebuilt many times, and costs disk space in the cache and occasionally stale
+/// artifacts. The environment variable takes precedence over `build.incremental` in `cargo_config`.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod incremental {
+///     metadata::ron!(
+///         edition: "2021",
+///         incremental: true,
+///         dependencies: {},
+///         exports: (),
+///     );
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ## Panic Strategy
+///
+/// The macro is loaded into the compiler, so a panic in it must be caught for the compiler to
+/// report it as an error instead of aborting. A panic in a macro with `proc_macro2: true` is caught
+/// in the inner crate, and reported as "The macro panicked" with its message. This needs the panic
+/// to unwind, so the inner crate is built with `panic = "unwind"`, even if the host's Cargo config
+/// or `CARGO_PROFILE_DEV_PANIC` set `panic = "abort"`, which the inner crate would otherwise
+/// inherit. `panic: "abort"` builds it with `panic = "abort"` anyway, and `profile.dev.panic` in
+/// `cargo_config` overrides both.
+///
+/// ## Reproducible Builds
+///
+/// With `reproducible: true`, the macro is built so that building the same module again gives the
+/// same dylib:
+///
+/// - The dylib is loaded from a copy in the inner crate's directory whose modification time is
+///   `SOURCE_DATE_EPOCH`, or the Unix epoch if it isn't set. Cargo's own output, and the generated
+///   sources, keep their modification times, since Cargo uses them to decide what to rebuild.
+/// - The directory of the inner crate is remapped to `/inline-proc` in the paths rustc embeds in
+///   the dylib, with `--remap-path-prefix` added to `build.rustflags`. Like any `build.rustflags`,
+///   it is ignored if `RUSTFLAGS` is set in the environment.
+/// - `SOURCE_DATE_EPOCH` is passed on to Cargo, for build scripts that embed timestamps.
+///
+/// Setting `SOURCE_DATE_EPOCH` alone doesn't turn this on.
+///
+/// This doesn't pin the versions of the dependencies, which are resolved into the inner crate's own
+/// `Cargo.lock` on its first build, so use exact versions such as `"=1.0.0"` where it matters. The
+/// paths of dependencies from the Cargo home and of the toolchain aren't remapped, and the path of
+/// the dylib in the expanded macros depends on the temporary directory.
+///
+/// Proc macros can't see the arguments of the Cargo command that runs them, so `--locked`,
+/// `--frozen` and `--offline` don't apply to the inner crate: it can still update its own
+/// `Cargo.lock` and download dependencies. The inner Cargo inherits the environment, so set
+/// `CARGO_NET_OFFLINE=true` to keep it offline.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod reproducible {
+///     metadata::ron!(
+///         edition: "2021",
+///         reproducible: true,
+///         dependencies: {},
+///         exports: (),
+///     );
+/// }
+/// ```
+///
+/// ## Compiler Version
+///
+/// The macros are loaded into the compiler that compiles your crate as a dynamic library, which
+/// only works if they were built by exactly the same compiler: Rust has no stable ABI, and a
+/// mismatch makes loading the library fail or, worse, crash. So by default the inner crate is
+/// built with the `rustc` compiling your crate, even if a different one would be found on the
+/// `PATH` or selected by rustup. `RUSTC` is used instead if it's set. When the crate is compiled
+/// by `rustdoc` or Clippy, the `rustc` next to them is used if it comes from the same commit.
+///
+/// Set `rustc` to use another compiler, which should still be the same version, or to `None` to
+/// let Cargo pick one as usual:
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod cargo_rustc {
+///     metadata::ron!(
+///         edition: "2021",
+///         rustc: None,
+///         dependencies: {},
+///         exports: (),
+///     );
+/// }
+/// ```
+///
+/// The host compiler can't be found when the macro is expanded by a tool other than rustc, rustdoc
+/// or Clippy, such as rust-analyzer, or when the `rustc` next to rustdoc or Clippy is a different
+/// build, and then Cargo picks one as usual too.
+///
+/// ## Building for Other Targets
+///
+/// `targets` builds the macro for each of the listed target triples too, after the usual build,
+/// with `cargo build --target`. The dylibs are copied into the `dist` directory of the inner crate,
+/// in a directory for each triple, named with that platform's prefix and extension, as in
+/// `dist/x86_64-pc-windows-msvc/inline_proc_macro.dll`. `"host-tuple"` stands for the host's
+/// triple, as with Cargo's `--target host-tuple`. The targets need to be installed, with for
+/// example `rustup target add`, along with a linker for them.
+///
+/// When the macros are invoked, the dylib built for the host's triple is loaded from `dist` if
+/// the host is one of the `targets`, instead of the one from the usual build. The directories of
+/// targets that are removed from `targets` are deleted from `dist` on the next build. A dylib
+/// built for another target still needs the same compiler version as the one that loads it, as
+/// described above.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod multi_target {
+///     metadata::ron!(
+///         edition: "2021",
+///         targets: ["host-tuple"],
+///         dependencies: {},
+///         exports: ( bang_macros: { "identity": "identity" } ),
+///     );
+///
+///     pub fn identity(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+///         input
+///     }
+/// }
+///
+/// // This is expanded by the dylib built for the host target.
+/// identity!(fn built_for_the_host() {});
+/// built_for_the_host();
+/// ```
+///
+/// ## Build Subcommands
+///
+/// `cargo_subcommand` builds the macro with another Cargo subcommand in place of `cargo build`, such
+/// as `["zigbuild"]` for [cargo-zigbuild](https://github.com/rust-cross/cargo-zigbuild). Any
+/// arguments after the subcommand are passed before Cargo's. Like other external subcommands,
+/// `cargo-zigbuild` is looked up in `PATH`. A subcommand given as a path, relative to your
+/// package, is run in place of Cargo instead, which lets a script wrap the whole build.
+///
+/// The subcommand is given the arguments of `cargo build`, including `--manifest-path`, `--config`
+/// and `--target` for `targets`, and has to accept them. It must leave the dylib where
+/// `cargo build` does, in the target directory of the inner crate, built for the host by the same
+/// compiler version that loads it. The module fails to build if the subcommand fails or the dylib
+/// it produces is missing or can't be loaded. Checking the code still uses `cargo check`, so the
+/// subcommand only affects the final build.
+///
+/// ```ignore
+/// #[inline_proc::inline_proc]
+/// mod zig_built {
+///     metadata::ron!(
+///         edition: "2021",
+///         cargo_subcommand: ["zigbuild"],
+///         dependencies: {},
+///         exports: ( bang_macros: { "identity": "identity" } ),
+///     );
+///
+///     pub fn identity(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+///         input
+///     }
+/// }
+/// ```
+///
+/// ## Compiler Wrappers
+///
+/// Cargo runs rustc through `rustc_wrapper` when it builds the inner crate, the same way as it does
+/// with `RUSTC_WRAPPER`; setting `rustc` doesn't change that. A wrapper like
+/// [sccache](https://github.com/mozilla/sccache) caches the builds of the macro's dependencies
+/// across machines, which can save most of the time of building a module from scratch in CI.
+///
+/// `rustc_wrapper` defaults to `RUSTC_WRAPPER`, so a wrapper set in the environment is used for
+/// both your crate and the inner crates. A wrapper set in your `.cargo/config.toml` isn't visible
+/// to the macro though, and needs to be set in the metadata as well. Relative paths are relative
+/// to your package, while bare names like `sccache` are looked up in `PATH`. Set it to `""` to
+/// build without a wrapper.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod wrapped {
+///     metadata::ron!(
+///         edition: "2021",
+///         rustc_wrapper: "tests/cfg-rustc-wrapper.sh",
+///         dependencies: {},
+///         exports: (),
+///     );
+///
+///     // The test wrapper enables this cfg.
+///     #[cfg(not(inline_proc_wrapped))]
+///     compile_error!("not built with the wrapper");
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ## Diagnostics
+///
+/// A macro with `macro_output: true` returns `inline_proc::MacroOutput` instead of a token stream,
+/// which holds both its output and a list of errors and warnings to report at spans of its
+/// choosing. Unlike panicking, the output is still used even if there are errors, which avoids
+/// follow-up errors about missing items. The `inline_proc` module with this type is added to the
+/// inner crate for you. With `proc_macro2: true`, the output is a
+/// `MacroOutput<proc_macro2::TokenStream, proc_macro2::Span>`.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod diagnosing {
+///     metadata::ron!(
+///         edition: "2021",
+///         dependencies: { "proc-macro2": "1" },
+///         exports: (
+///             attributes: {
+///                 "no_unit": ( function: "no_unit", macro_output: true, proc_macro2: true ),
+///             },
+///         ),
+///     );
+///     use proc_macro2::{Span, TokenStream, TokenTree};
+///     use inline_proc::MacroOutput;
+///
+///     pub fn no_unit(_attr: TokenStream, item: TokenStream) -> MacroOutput<TokenStream, Span> {
+///         let is_unit = matches!(
+///             item.clone().into_iter().last(),
+///             Some(TokenTree::Punct(punct)) if punct.as_char() == ';'
+///         );
+///         let span = item.clone().into_iter().next().unwrap().span();
+///         let output = MacroOutput::new(item);
+///         if is_unit {
+///             output.warning(span, "unit structs aren't supported yet")
+///         } else {
+///             output
+///         }
+///     }
+/// }
+///
+/// // The struct is still output along with the warning.
+/// #[inline_proc::inline_attr[no_unit]]
+/// struct Unit;
+/// # let _ = Unit;
+/// ```
+///
+/// Like all warnings from proc macros, the warnings are only shown on nightly Rust.
+///
+/// ## Errors in Generated Code
+///
+/// Besides the module's own code, the inner crate has code generated by `inline_proc`, such as the
+/// functions that call the macro functions. Errors in it, for example from a macro function with
+/// the wrong signature, can only point at the `#[inline_proc]` attribute. When Cargo's output is
+/// shown, with `#[inline_proc(verbose)]`, `quiet: false` or `INLINE_PROC_VERBOSE=1`, these errors
+/// get a note with the generated code they are in, formatted by the `pretty_printer` command if
+/// there is one.
+///
+/// ## Parsed Input
+///
+/// With `parse`, a macro's function takes its input (or for an attribute macro, its item) parsed
+/// into the given type instead of a token stream, which saves calling `parse_macro_input!` in
+/// every macro. The type can be any type implementing `syn::parse::Parse`, including your own, and
+/// its path is relative to the root of the module. If the input doesn't parse, the macro expands
+/// to the parse error as a `compile_error!` without calling the function. This needs `syn` as a
+/// dependency, with its default `proc-macro` feature.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod parsed {
+///     metadata::ron!(
+///         edition: "2021",
+///         dependencies: { "proc-macro2": "1", "syn": "2", "quote": "1" },
+///         exports: (
+///             derives: {
+///                 "Named": ( function: "named", parse: "syn::DeriveInput", proc_macro2: true ),
+///             },
+///         ),
+///     );
+///
+///     pub fn named(input: syn::DeriveInput) -> proc_macro2::TokenStream {
+///         let name = &input.ident;
+///         quote::quote!(impl #name { pub const NAME: &str = stringify!(#name); })
+///     }
+/// }
+///
+/// #[derive(inline_proc::InlineDerive)]
+/// #[inline_derive(Named)]
+/// struct Item;
+///
+/// assert_eq!(Item::NAME, "Item");
+/// ```
+///
+/// ## Typed Input
+///
+/// With `deserialize`, a function-like macro's function takes its input deserialized into a type
+/// implementing `serde::Deserialize`, so the macro can be configured with structured key-value
+/// input instead of parsing tokens. `deserialize` takes the `format` of the input, `"ron"` or
+/// `"json"`, and the `type` to deserialize into, whose path is relative to the root of the module.
+/// This needs `serde` as a dependency to derive `Deserialize`, along with `ron` (0.8 or later) or
+/// `serde_json` for the format.
+///
+/// If the input doesn't deserialize, the macro expands to the error as a `compile_error!` at the
+/// token the error points to, without calling the function. The tokens of the input are separated
+/// by the same whitespace as they are in your code, so `-1` is a number and the whitespace between
+/// tokens is kept for formats that care about it.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod typed {
+///     metadata::ron!(
+///         edition: "2021",
+///         dependencies: {
+///             "serde": ( version: "1", features: ["derive"] ),
+///             "ron": "0.10",
+///             "proc-macro2": "1",
+///         },
+///         exports: (
+///             bang_macros: {
+///                 "constant": (
+///                     function: "constant",
+///                     deserialize: ( format: "ron", type: "Constant" ),
+///                     proc_macro2: true,
+///                 ),
+///             },
+///         ),
+///     );
+///
+///     #[derive(serde::Deserialize)]
+///     pub struct Constant {
+///         name: String,
+///         value: i64,
+///     }
+///
+///     pub fn constant(constant: Constant) -> proc_macro2::TokenStream {
+///         format!("const {}: i64 = {};", constant.name, constant.value)
+///             .parse()
+///             .unwrap()
+///     }
+/// }
+///
+/// // `constant!((name: "ANSWER", value: "42"))` fails with "Expected integer" at `"42"`.
+/// constant!((name: "ANSWER", value: 42));
+/// assert_eq!(ANSWER, 42);
+/// ```
+///
+/// `deserialize` can't be combined with `parse`, and isn't available for derive and attribute
+/// macros.
+///
+/// ## Input Fragments
+///
+/// The `macro_rules!` macro generated for a function-like macro accepts any tokens. With
+/// `input_fragment`, it instead matches a single fragment of that kind, such as `"expr"`, `"ty"`
+/// or `"path"`, so that input of the wrong kind is rejected by the compiler with its usual
+/// `macro_rules!` error before the macro runs. Any fragment specifier that `macro_rules!` accepts
+/// can be used.
+///
+/// The fragment is passed on as a single group with invisible delimiters, which `syn` parses
+/// transparently. Like any fragment captured by `macro_rules!`, it can't be taken apart again by
+/// other `macro_rules!` macros in the output.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod fragments {
+///     metadata::ron!(
+///         edition: "2021",
+///         dependencies: {},
+///         exports: ( bang_macros: { "checked": ( passthrough: true, input_fragment: "expr" ) } ),
+///     );
+/// }
+///
+/// assert_eq!(checked!(1 + 2) * 2, 6);
+/// ```
+///
+/// ## Registering Macros
+///
+/// Instead of writing a function and listing it in `exports`, a macro can be registered with
+/// `export_bang!`, `export_derive!` or `export_attribute!` anywhere in the module. Each takes the
+/// name of the macro, as an identifier or a string, and its function, usually a closure:
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod registered {
+///     metadata::ron!(
+///         edition: "2021",
+///         dependencies: { "proc-macro2": "1", "quote": "1" },
+///         exports: (),
+///     );
+///
+///     inline_proc::export_bang!(double, |input: proc_macro2::TokenStream| {
+///         quote::quote!((#input) * 2)
+///     });
+///     inline_proc::export_derive!(Nothing, |_item: proc_macro2::TokenStream| {
+///         proc_macro2::TokenStream::new()
+///     });
+///     inline_proc::export_attribute!("unchanged", |_attr: proc_macro2::TokenStream, item| item);
+/// }
+///
+/// #[derive(inline_proc::InlineDerive)]
+/// #[inline_derive(Nothing)]
+/// struct Derived;
+///
+/// #[inline_proc::inline_attr[unchanged]]
+/// fn two() -> u8 {
+///     2
+/// }
+///
+/// assert_eq!(double!(1 + 2), 6);
+/// let _ = Derived;
+/// assert_eq!(two(), 2);
+/// ```
+///
+/// Each registration is replaced with a public function of the same name, and the macro is
+/// exported with the default options, as if it was written as `"double": "double"` in `exports`.
+/// The function takes `proc_macro::TokenStream`s, unless the first parameter of the closure is
+/// annotated with a type written as a path starting with `proc_macro2`, like above; then it takes
+/// `proc_macro2::TokenStream`s and the macro is exported with `proc_macro2: true`. The closure
+/// can't capture anything, and the path before `export_*` is ignored like the one before
+/// `metadata`, so no import is needed. To set other options, write the function and the export out
+/// instead; a macro can't be both registered and in `exports`.
+///
+/// ## Proc Macro Crate Compatibility
+///
+/// Functions marked with `#[proc_macro]`, `#[proc_macro_derive(Name)]` or `#[proc_macro_attribute]`
+/// are exported with the default options, under the names a proc macro crate would give them: the
+/// name of the function for bang and attribute macros, and the name in the attribute for derives.
+/// The attributes are removed, since they only work in proc macro crates. This means that apart
+/// from the metadata, the module can be the `lib.rs` of a proc macro crate as is, which makes it
+/// easy to move a macro out of the module into its own crate once it has grown, or to prototype
+/// an existing proc macro inline:
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod native {
+///     metadata::ron!(
+///         edition: "2021",
+///         dependencies: {},
+///         exports: (),
+///     );
+///
+///     // Everything below is also a valid proc macro crate.
+///     use proc_macro::TokenStream;
+///
+///     #[proc_macro]
+///     pub fn identity(input: TokenStream) -> TokenStream {
+///         input
+///     }
+///
+///     #[proc_macro_derive(Nothing, attributes(helper))]
+///     pub fn nothing(_item: TokenStream) -> TokenStream {
+///         TokenStream::new()
+///     }
+///
+///     #[proc_macro_attribute]
+///     pub fn unchanged(_attr: TokenStream, item: TokenStream) -> TokenStream {
+///         item
+///     }
+///     # // The functions keep the signatures of native proc macros.
+///     # const _: fn(TokenStream) -> TokenStream = identity;
+///     # const _: fn(TokenStream) -> TokenStream = nothing;
+///     # const _: fn(TokenStream, TokenStream) -> TokenStream = unchanged;
+/// }
+///
+/// identity!(fn invoked_by_name() {});
+/// # fn main() {
+/// invoked_by_name();
+/// # }
+/// ```
+///
+/// Only the way the macros are invoked differs from a proc macro crate, as described in "Output"
+/// below: derives are used through [`InlineDerive`], which only supports the `helper` helper
+/// attribute, and attribute macros through [`inline_attr`](macro@inline_attr). As with
+/// registrations, a macro defined this way can't also be in `exports`.
+///
+/// ## Passthrough Macros
+///
+/// A macro with `passthrough: true` and no `function` expands to its input (or for an attribute
+/// macro, to its item) without loading the dylib. This is useful as a stand-in while a macro is
+/// being written, or to turn a macro off. If every macro of a module is a passthrough macro, the
+/// module isn't built at all, which saves the time of building it from scratch and of running
+/// Cargo on every expansion; but its code isn't checked either. Derive macros can't be
+/// passthrough macros, since passing their input through would define the item twice.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod passthrough {
+///     metadata::ron!(
+///         edition: "2021",
+///         dependencies: {},
+///         exports: (
+///             bang_macros: { "same": ( passthrough: true ) },
+///             attributes: { "unchanged": ( passthrough: true ) },
+///         ),
+///     );
+/// }
+///
+/// same!(fn one() -> u8 { 1 });
+///
+/// #[inline_proc::inline_attr[unchanged(ignored params)]]
+/// struct Item;
+///
+/// assert_eq!(one(), 1);
+/// let _ = Item;
+/// ```
+///
+/// Options of a macro that affect its function or output, such as `spans`, `location`,
+/// `host_crate`, `macro_output`, `proc_macro2` and `output_module`, are errors on passthrough
+/// macros, while the module's `wrap_output` and `output_attributes` don't apply to them.
+///
+/// ## Harness
+///
+/// Setting `harness` adds a binary named `harness` to the inner crate, which calls a macro with
+/// sample input and prints its output. This makes it possible to try out changes to a macro, or
+/// to debug it, without invoking it from real code. Once the module is built, run it with
+/// `cargo run --manifest-path {inner crate}/Cargo.toml --bin harness`, where the inner crate is in
+/// the directory described in [How It Works](crate#how-it-works).
+///
+/// `harness` takes the name of an exported macro, its `input` and, for attribute macros, its
+/// `attr` parameters. Since `proc_macro` can only be used inside a procedural macro, the macro
+/// must use `proc_macro2: true`.
+///
+/// ```
+/// let cargo_toml = inline_proc::generated_cargo_toml!(metadata::ron!(
+///     edition: "2021",
+///     harness: ( macro: "my_macro", input: "some tokens" ),
+///     dependencies: { "proc-macro2": "1" },
+///     exports: ( bang_macros: { "my_macro": ( function: "my_macro", proc_macro2: true ) } ),
+/// ));
+/// assert!(cargo_toml.contains("bin=[{name='harness',path='harness.rs'}]"));
+/// ```
+///
+/// ## Documenting the Inner Crate
+///
+/// With `generate_docs: true`, the inner crate is also documented with `cargo doc` after every
+/// successful build, for those who maintain the macros as a crate of their own and want to browse
+/// their documentation. Only the inner crate itself is documented, not its dependencies. The docs
+/// are in `target/doc/inline_proc_macro` in the inner crate (named after the module with
+/// `shared_workspace`), and their path is printed when the module is built. `rustdoc_flags` are
+/// passed to rustdoc like `RUSTDOCFLAGS`, which is ignored. If documenting fails, so does the
+/// module.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod documented {
+///     metadata::ron!(
+///         edition: "2021",
+///         generate_docs: true,
+///         rustdoc_flags: ["--cfg", "docsrs"],
+///         dependencies: {},
+///         exports: (),
+///     );
+///
+///     /// Only documented with `--cfg docsrs`.
+///     #[cfg(docsrs)]
+///     pub fn documented_with_flags() {}
+/// }
+/// ```
+///
+/// ## Shared Cache
+///
+/// With `shared_cache: true`, the inner crate is put in
+/// `{temporary directory}/inline-proc-crates/shared-{hash}`, where the hash covers the module's
+/// code, the generated manifest and build script, and `cargo_config`. Modules with identical
+/// metadata and code are then only built once, even if they are in different packages, which
+/// helps in large workspaces where several crates define the same macro.
+///
+/// The trade-off is that the build is no longer tied to the module that caused it. Diagnostics
+/// still point at the module being expanded, but paths seen by the macro's code (such as those
+/// from `file!()` or `pre_build`) are those of the shared crate, relative `path` dependencies are
+/// resolved from the shared crate, and old shared crates are never deleted automatically since no
+/// module owns them.
+///
+/// ## Dependencies Between Modules
+///
+/// A module can use the code of another module's inner crate with a `path` dependency on it, such
+/// as `"inline-proc-macro-other": ( path: "../other" )` in a shared workspace, or
+/// `"other": ( path: "../my-package-1-other", package: "inline-proc-macro" )` otherwise. The other
+/// module must be expanded first, since its crate doesn't exist until then. If the path dependencies of
+/// inline modules lead back to the module being expanded, Cargo would fail with an obscure error
+/// or wait forever, so the expansion fails with the cycle instead, such as
+/// ``Module `second` depends on itself through the path dependencies of inline modules: `second` ->
+/// `first` -> `second` ``.
+///
+/// Path dependencies are also followed through their own `Cargo.toml`s to check that they don't
+/// lead back to the package the module is in. The macro is built before the package it's used in,
+/// so building the package as a dependency of the macro would expand the module again, and Cargo
+/// would fail with a cyclic dependency or never finish. This usually comes from a `workspace: true`
+/// dependency on a crate of the same workspace, and the expansion fails with the path it took,
+/// such as ``Module `host_loop` depends on the package it is in, `inline-proc`, through its path
+/// dependencies: `helper` -> `inline-proc` ``. Code that both the macro and the package need
+/// belongs in a crate of its own.
+///
+/// ## Restricting Dependencies
+///
+/// The dependencies of inline modules don't show up in your `Cargo.lock`, so tools that audit it
+/// can't see them. `allowed_dependencies` and `denied_dependencies` restrict which packages a
+/// module may depend on instead, by name: with `allowed_dependencies`, only the packages it lists
+/// are allowed, and the packages in `denied_dependencies` never are. If any dependency isn't
+/// allowed, the module fails to build with the list of them, before anything is built. Set the
+/// lists in a defaults file to apply them to every module that names it. The inner crates of
+/// other modules are always allowed.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod allowed {
+///     metadata::ron!(
+///         edition: "2021",
+///         allowed_dependencies: ["proc-macro2", "unicode-ident"],
+///         check_transitive_dependencies: true,
+///         dependencies: { "proc-macro2": "1" },
+///         exports: (),
+///     );
+/// }
+/// # fn main() {}
+/// ```
+///
+/// Only the direct dependencies are checked, unless `check_transitive_dependencies` is set. Then
+/// all the packages they depend on are resolved with `cargo metadata` and checked too, which takes
+/// a little longer, and a denied package is reported with the dependency that requires it.
+///
+/// ## Pre-build Commands
+///
+/// `pre_build` is run before the inner crate is built, with the inner crate's directory as its
+/// working directory. That directory is also in the `INLINE_PROC_CRATE_DIR` environment variable,
+/// and the rest of the environment is the compiler's, including `CARGO_MANIFEST_DIR`. If the
+/// command fails, its output is reported as an error. Since the module's code is in the root of
+/// the inner crate, its outputs can be used with for example `include!("schema.rs")`.
+///
+/// The command runs every time the module is expanded. If `pre_build_outputs` is set, the inner
+/// crate is only rebuilt when the contents of those files change, or when the module itself
+/// changes.
+///
+/// Like a build script, the command runs with the full permissions of the user building the
+/// crate, and it runs whenever the crate is compiled, including by IDEs that expand macros in the
+/// background. Only use commands you trust, and be careful with dependencies that use
+/// `#[inline_proc]`.
+///
+/// ## Depfiles
+///
+/// For build systems other than Cargo, such as Bazel, Buck or make, `depfile` writes a depfile in
+/// the format of rustc's `--emit dep-info` every time the module is built. Its target is the
+/// compiled macro, and its dependencies are the file containing the module, the defaults file and
+/// the outputs of `pre_build`. The file containing the module isn't listed if the module doesn't
+/// come from a file on disk, such as in code generated by other macros.
+///
+/// Setting `INLINE_PROC_DEPFILE_DIR` writes the depfiles of all modules to that directory instead,
+/// named after the package and the module, like `my-crate-0.1-my_macros.d`.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod depfile {
+///     metadata::ron!(
+///         edition: "2021",
+///         depfile: "target/inline-proc-depfile.d",
+///         dependencies: {},
+///         exports: (),
+///     );
+/// }
+/// ```
+///
+/// ## Native Libraries
+///
+/// `links` and `link_search` are passed to the compiler by a generated build script, like
+/// `cargo:rustc-link-lib` and `cargo:rustc-link-search` in a build script of your own:
+///
+/// ```
+/// # #[cfg(unix)]
+/// #[inline_proc::inline_proc]
+/// mod native {
+///     metadata::ron!(
+///         edition: "2021",
+///         links: ["m"],
+///         dependencies: {},
+///         exports: (),
+///     );
+///
+///     unsafe extern "C" {
+///         fn cbrt(x: f64) -> f64;
+///     }
+///
+///     pub fn cube_root(x: f64) -> f64 {
+///         unsafe { cbrt(x) }
+///     }
+/// }
+/// ```
+///
+/// Library names and the available kinds differ between platforms: for example `framework` only
+/// exists on macOS, and the same library may be called `z` on Unix and `zlib` on Windows. Since
+/// the macros are loaded by the compiler while it runs, a dynamically linked library must also be
+/// found by the system's dynamic loader at that point, for example through `LD_LIBRARY_PATH` on
+/// Linux, and not only in a `link_search` directory.
+///
+/// ## Retries
+///
+/// Cargo can fail for reasons that have nothing to do with your code, like a network error while
+/// downloading dependencies or a file locked by another process on CI. With `build_retries`, the
+/// check and the build are retried up to that many times when Cargo fails with such an error,
+/// waiting half a second before the first retry and twice as long before each one after. Other
+/// failures, and compile errors, are never retried, since they would just fail again. Retries are
+/// reported on stderr when Cargo's output is shown, with `quiet: false` or `INLINE_PROC_VERBOSE=1`.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod retried {
+///     metadata::ron!(
+///         edition: "2021",
+///         build_retries: 2,
+///         dependencies: {},
+///         exports: (),
+///     );
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ## Attribute Arguments
+///
+/// For debugging, some options can be toggled on the attribute itself, as in
+/// `#[inline_proc(keep, verbose)]`, without editing the metadata:
+///
+/// - `keep` copies the sources of the inner crate, such as its generated `Cargo.toml` and `lib.rs`,
+///   and its dylib into `inline-proc-kept` in the temporary directory, and prints where to stderr.
+///   Unlike the cache, the copy isn't deleted when the cache is cleared or its layout changes, so
+///   it can be inspected or built by hand later.
+/// - `verbose` shows Cargo's progress output, like `quiet: false`.
+/// - `clippy` checks the code with Clippy, like `clippy: true`.
+///
+/// These override the metadata, but not the environment variables below.
+///
+/// ```
+/// #[inline_proc::inline_proc(keep)]
+/// mod kept {
+///     metadata::ron!(
+///         edition: "2021",
+///         dependencies: {},
+///         exports: ( bang_macros: { "my_macro": "my_macro" } ),
+///     );
+///
+///     pub fn my_macro(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+///         input
+///     }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// ## Environment Variables
+///
+/// Some options can be overridden from the environment without editing the source:
+///
+/// - `INLINE_PROC_NO_CLIPPY=1` checks the code with `cargo check` even if `clippy: true` is set.
+/// - `INLINE_PROC_FORCE_CLIPPY=1` checks the code with Clippy even if `clippy` is unset or false.
+/// - `INLINE_PROC_VERBOSE=1` shows Cargo's progress output even if `quiet` is true.
+/// - `INLINE_PROC_DIAGNOSTICS=short` or `INLINE_PROC_DIAGNOSTICS=full` overrides `diagnostics`.
+/// - `INLINE_PROC_JOBS=N` overrides `jobs`, for example to limit the parallelism on CI.
+/// - `NO_COLOR` set to any non-empty value passes `--color=never` to Cargo even if `color` is
+///   true, unless `FORCE_COLOR=1` is also set. `FORCE_COLOR` doesn't enable color when `color` is
+///   false.
+/// - `INLINE_PROC_DYLIB_EXTENSION` overrides the file extension of the compiled macros, for
+///   toolchains that don't use the platform's usual one (`so`, `dylib` or `dll`).
+/// - `INLINE_PROC_DEPFILE_DIR` writes the depfile of every module to that directory, overriding
+///   `depfile`.
+/// - `INLINE_PROC_WARN_UNUSED=1` warns about unused macros even if `warn_unused` is unset or false.
+///
+/// Environment variables take precedence over the metadata, and `INLINE_PROC_NO_CLIPPY` takes
+/// precedence over `INLINE_PROC_FORCE_CLIPPY`.
+///
+/// On Unix, the compiled macros are loaded with all their symbols bound immediately, so a missing
+/// symbol causes an error when the library is loaded instead of a crash when it is first used.
+/// `lazy_binding: true` in the metadata, or setting `INLINE_PROC_LAZY_BINDING=1` for every module,
+/// binds symbols on first use instead, which loads slightly faster and was the behavior of earlier
+/// versions.
+///
+/// Setting `INLINE_PROC_DUMP_EXPANSION=1` prints the output of every inline macro invocation to
+/// stderr, along with the macro's name and the location of its input. This is like `cargo expand`,
+/// but scoped to inline macros. It does not affect the generated code. If the compiler doesn't know
+/// which file the input is in (for example when it comes from a macro of another crate built with
+/// `--remap-path-prefix`), only the package directory is printed.
+///
+/// Setting `INLINE_PROC_TIMINGS=1` prints how long each inline module took to build to stderr,
+/// to help find the macros that slow down builds the most. Each line gives the time spent
+/// compiling the module's dependencies, checking it (which includes the dependencies) and building
+/// it, and whether the build was cold (the module had never been built before) or warm.
+/// At the end of the compilation, a table of all the modules follows, slowest first.
+/// `INLINE_PROC_TIMINGS=json` prints one JSON object per line instead, after `inline-proc: ` so
+/// that Cargo doesn't take it for one of its own messages, with the fields `module`, `location`,
+/// `cold`, `dependencies_ms`, `check_ms` and `build_ms`. To measure cold builds, delete the
+/// `inline-proc-crates` directory in the system's temporary directory first.
+///
+/// Setting `INLINE_PROC_STATS=1` prints a note to stderr for every expansion of an inline module,
+/// saying whether it was a cache hit (the module was already built from the same source), had to
+/// be rebuilt, or was a cold build, and how long it took. The note also counts the hits and misses
+/// and the total time of the module so far, which is useful when the same module is expanded many
+/// times by one compiler process, like in an IDE. Each `cargo build` runs a new compiler process,
+/// so there it counts a single expansion.
+///
 /// # Output
 ///
 /// This macro generates a `macro_rules!` macro for each macro listed in `exports`. This macro can
@@ -341,10 +1949,80 @@ mod invoke;
 /// However for derive macros and attribute macros it is recommended to use the
 /// [`InlineDerive`](derive.InlineDerive.html) and [`#[inline_attr]`](attr.inline_attr.html) macros
 /// instead.
+///
+/// Bang macros with `include: true` can also be invoked with `include "path"`, which calls the
+/// macro with the contents of the file at that path as a string literal, instead of with the tokens
+/// themselves. Like `include_str!`, the path is relative to the file the macro is invoked in. The
+/// file is tracked so that the macro is expanded again when it changes, unless the macro expands
+/// to something other than items or an expression, such as a type.
+///
+/// ```ignore
+/// my_bang_macro!(include "schema.graphql");
+/// ```
+///
+/// If a bang macro expands to an invocation of itself with the same input, the expansion would
+/// never terminate, so it is rejected with an error. A bang macro may otherwise expand to
+/// invocations of itself, such as to count down to a base case, but only 32 levels deep: the
+/// invocations in its output are marked with how deep they are, and the one that would go deeper
+/// is an error.
+///
+/// ## Post-Processing
+///
+/// `wrap_output` puts the items output by every macro of the module in a module of their own, like
+/// `output_module` below, named with `wrap_output` as a prefix, and `output_attributes` adds
+/// attributes to every item of the output. With `wrap_output: "generated"` and
+/// `output_attributes: ["allow(dead_code)"]`, a macro that returns `pub struct Foo;` expands to:
+///
+/// ```ignore
+/// #[allow(dead_code)]
+/// #[doc(hidden)]
+/// mod generated_12_1_0 {
+///     #[allow(unused_imports)]
+///     use super::*;
+///     pub struct Foo;
+/// }
+/// #[allow(dead_code)]
+/// #[allow(unused_imports)]
+/// pub use generated_12_1_0::Foo;
+/// ```
+///
+/// Since only items can be put in a module or given attributes, output that isn't a list of items,
+/// such as an expression, is left as it is.
+///
+/// ## Output Modules
+///
+/// A macro with `output_module: true` that generates many items can keep its helpers from clashing
+/// with the code around it. Its output is put in a module of its own, numbered so that several
+/// invocations in one scope don't clash either, and the items of that module are re-exported with
+/// their own visibility. Private items are not re-exported, so a macro
+/// that returns `fn helper() {} pub fn api() { helper() }` expands to:
+///
+/// ```ignore
+/// #[doc(hidden)]
+/// mod __inline_proc_output_12_1_0 {
+///     #[allow(unused_imports)]
+///     use super::*;
+///     fn helper() {}
+///     pub fn api() { helper() }
+/// }
+/// pub use __inline_proc_output_12_1_0::api;
+/// ```
+///
+/// Items that are visible to the module they are invoked in, with `pub(super)`, are re-exported
+/// privately. The output must be items, and the module is added before `wrap_output` and
+/// `output_attributes` are applied.
+///
+/// # Errors
+///
+/// If the module fails to build, its errors are reported without stopping the compiler, so the
+/// errors of every failing module are shown in a single build, like Cargo's `--keep-going`. The
+/// module's macros are still defined, but attribute macros return their item unchanged and bang
+/// and derive macros expand to nothing, so that their uses don't cause more errors.
 #[proc_macro_error]
 #[proc_macro_attribute]
-pub fn inline_proc(_: TokenStream1, input: TokenStream1) -> TokenStream1 {
-    inline_proc::inline_proc(input)
+pub fn inline_proc(args: TokenStream1, input: TokenStream1) -> TokenStream1 {
+    let args = syn::parse_macro_input!(args as inline_proc::AttributeArgs);
+    inline_proc::inline_proc(args, input)
 }
 
 #[proc_macro_error]
@@ -354,6 +2032,151 @@ pub fn invoke_inline_macro(input: TokenStream1) -> TokenStream1 {
     invoke::invoke_inline_macro(input)
 }
 
+/// Render the `Cargo.toml` of the inner crate for some metadata, as a string literal, without
+/// building anything. This is useful for checking how dependencies are rendered.
+///
+/// ```
+/// let cargo_toml = inline_proc::generated_cargo_toml!(metadata::ron!(
+///     edition: "2021",
+///     dependencies: {
+///         "quote": "1",
+///         "git_dep": ( git: "https://github.com/dtolnay/syn", branch: "master" ),
+///         "path_dep": ( path: "../path_dep" ),
+///         "my_syn": ( version: "2", package: "syn", features: ["full", "extra-traits"] ),
+///     },
+///     exports: (),
+/// ));
+///
+/// assert!(cargo_toml.contains("edition='2021'"));
+/// assert!(cargo_toml.contains("quote = \"1\"\n"));
+/// assert!(cargo_toml.contains(
+///     "[dependencies.git_dep]\n\
+///      git = \"https://github.com/dtolnay/syn\"\n\
+///      branch = \"master\"\n"
+/// ));
+/// assert!(cargo_toml.contains("[dependencies.path_dep]\npath = \"../path_dep\"\n"));
+/// assert!(cargo_toml.contains(
+///     "[dependencies.my_syn]\n\
+///      version = \"2\"\n\
+///      package = \"syn\"\n\
+///      features = [\"full\", \"extra-traits\"]\n"
+/// ));
+/// ```
+///
+/// Only the dependencies in the metadata are rendered. Options that generate code in the inner
+/// crate, such as `macro_output` and `location`, only use `proc_macro`:
+///
+/// ```
+/// let cargo_toml = inline_proc::generated_cargo_toml!(metadata::ron!(
+///     edition: "2021",
+///     dependencies: {},
+///     exports: (
+///         bang_macros: { "minimal": ( function: "minimal", macro_output: true, location: true ) },
+///     ),
+/// ));
+///
+/// assert_eq!(
+///     cargo_toml,
+///     "package={name='inline-proc-macro',version='0.0.0',edition='2021',publish=false}\n\
+///      lib={crate-type=['dylib'],path='lib.rs'}\n\
+///      [dependencies]\n",
+/// );
+/// ```
+///
+/// The resolver is only rendered if it's set, so that by default Cargo uses the one implied by
+/// the edition:
+///
+/// ```
+/// let cargo_toml = inline_proc::generated_cargo_toml!(metadata::ron!(
+///     edition: "2018",
+///     resolver: "2",
+///     dependencies: {},
+///     exports: (),
+/// ));
+/// assert!(cargo_toml.starts_with(
+///     "package={name='inline-proc-macro',version='0.0.0',edition='2018',publish=false,resolver='2'}"
+/// ));
+///
+/// let cargo_toml = inline_proc::generated_cargo_toml!(metadata::ron!(
+///     edition: "2018",
+///     dependencies: {},
+///     exports: (),
+/// ));
+/// assert!(!cargo_toml.contains("resolver"));
+/// ```
+///
+/// The inner crate is marked with `publish = false` unless `package_metadata` says otherwise, and
+/// `package_metadata` adds other fields to the `[package]` section:
+///
+/// ```
+/// let cargo_toml = inline_proc::generated_cargo_toml!(metadata::ron!(
+///     edition: "2021",
+///     package_metadata: {
+///         "license": "MIT OR Apache-2.0",
+///         "authors": ["Jane Doe <jane@example.com>"],
+///         "publish": true,
+///     },
+///     dependencies: {},
+///     exports: (),
+/// ));
+///
+/// let manifest: toml::Table = cargo_toml.parse().unwrap();
+/// let package = &manifest["package"];
+/// assert_eq!(package["license"].as_str(), Some("MIT OR Apache-2.0"));
+/// assert_eq!(package["authors"][0].as_str(), Some("Jane Doe <jane@example.com>"));
+/// assert_eq!(package["publish"].as_bool(), Some(true));
+/// ```
+///
+/// A dependency with `workspace: true` is an error unless the workspace defines it.
+#[proc_macro_error]
+#[proc_macro]
+#[doc(hidden)]
+pub fn generated_cargo_toml(input: TokenStream1) -> TokenStream1 {
+    inline_proc::generated_cargo_toml(input)
+}
+
+/// Generate functions to list and remove the cached inner crates, for tools that manage the
+/// cache.
+///
+/// A proc macro crate can only export macros, so this macro generates the API in the module it's
+/// invoked in instead:
+///
+/// - `CacheEntry`, with the `package`, `version` and `module` of a cached inner crate (or the
+///   `hash` of one built with `shared_cache`), its `path` and its `size` in bytes.
+/// - `cache_entries() -> io::Result<Vec<CacheEntry>>`, which lists the cached inner crates of
+///   every package, sorted by path.
+/// - `invalidate(entry: &CacheEntry) -> io::Result<()>`, which removes one, so that its module is
+///   built from scratch the next time it is expanded.
+///
+/// The cache is looked up in the temporary directory of the process calling the functions, so it
+/// must be run with the same `TMPDIR` as the builds. Removing an entry while its module is being
+/// built makes that build fail.
+///
+/// ```
+/// #[inline_proc::inline_proc]
+/// mod listed {
+///     metadata::ron!(
+///         edition: "2021",
+///         dependencies: {},
+///         exports: (),
+///     );
+/// }
+///
+/// mod cache {
+///     inline_proc::cache_api!();
+/// }
+///
+/// for entry in cache::cache_entries().unwrap() {
+///     if entry.module.as_deref() == Some("listed") {
+///         cache::invalidate(&entry).unwrap();
+///     }
+/// }
+/// ```
+#[proc_macro]
+pub fn cache_api(input: TokenStream1) -> TokenStream1 {
+    cache::cache_api(input)
+}
+
 /// Use an inline procedural macro attribute.
 ///
 /// Simply replace where you would usually write `#[my_attr]` or `#[my_attr(params)]` with
@@ -370,6 +2193,174 @@ pub fn invoke_inline_macro(input: TokenStream1) -> TokenStream1 {
 /// ```ignore
 /// attr_name!((params) struct Item);
 /// ```
+///
+/// Since the macro is invoked in item position, the attribute macro can return any number of
+/// items, and they are placed in order where the original item was. For example an attribute
+/// macro can return the original item followed by an `impl` block for it:
+///
+/// ```ignore
+/// pub fn add_new(_attr: TokenStream, item: TokenStream) -> TokenStream {
+///     let item: syn::ItemStruct = syn::parse(item).unwrap();
+///     let name = &item.ident;
+///     quote::quote!(
+///         #item
+///         impl #name {
+///             pub fn new() -> Self { Self }
+///         }
+///     )
+///     .into()
+/// }
+/// ```
+///
+/// The expansion doesn't refer to `inline_attr` itself, so it can be imported under another name
+/// if it collides with one of your own attributes:
+///
+/// ```
+/// use inline_proc::inline_attr as my_inline_attr;
+///
+/// // Any macro taking `(params) item` works here, including `macro_rules!` macros.
+/// macro_rules! add_unit {
+///     (($name:ident) $item:item) => {
+///         $item
+///         struct $name;
+///     };
+/// }
+///
+/// #[my_inline_attr[add_unit(Unit)]]
+/// struct Item;
+/// # let _ = (Item, Unit);
+/// ```
+///
+/// The parameters are passed on verbatim, so the same syntax as native attribute parameters can
+/// be used, such as `key = value` pairs, flags, and nested lists. Like native attributes, the
+/// parameters can also be delimited by brackets or braces, and the macro receives them in
+/// parentheses either way:
+///
+/// ```
+/// macro_rules! exact_params {
+///     (
+///         (key = "value", flag, list(a, [b], { c }), nested(depth = (1, 2)), path::to::Item)
+///         $item:item
+///     ) => {
+///         $item
+///     };
+/// }
+///
+/// #[inline_proc::inline_attr(exact_params(
+///     key = "value",
+///     flag,
+///     list(a, [b], { c }),
+///     nested(depth = (1, 2)),
+///     path::to::Item
+/// ))]
+/// struct Parens;
+///
+/// #[inline_proc::inline_attr[exact_params[
+///     key = "value",
+///     flag,
+///     list(a, [b], { c }),
+///     nested(depth = (1, 2)),
+///     path::to::Item
+/// ]]]
+/// struct Brackets;
+///
+/// #[inline_proc::inline_attr{exact_params{
+///     key = "value",
+///     flag,
+///     list(a, [b], { c }),
+///     nested(depth = (1, 2)),
+///     path::to::Item
+/// }}]
+/// struct Braces;
+/// # let _ = (Parens, Brackets, Braces);
+/// ```
+///
+/// This matches native attribute macros, which also only receive the tokens inside the delimiters.
+/// A macro that needs to tell the delimiters apart can take them as a group inside the
+/// parameters instead:
+///
+/// ```
+/// macro_rules! body_style {
+///     (({ $($body:tt)* }) struct $name:ident;) => {
+///         struct $name;
+///         impl $name {
+///             const STYLE: &'static str = "braces";
+///         }
+///     };
+///     ((( $($body:tt)* )) struct $name:ident;) => {
+///         struct $name;
+///         impl $name {
+///             const STYLE: &'static str = "parens";
+///         }
+///     };
+/// }
+///
+/// #[inline_proc::inline_attr[body_style({ a b })]]
+/// struct Braced;
+///
+/// #[inline_proc::inline_attr[body_style((a b))]]
+/// struct Parenthesized;
+///
+/// assert_eq!(Braced::STYLE, "braces");
+/// assert_eq!(Parenthesized::STYLE, "parens");
+/// ```
+///
+/// The item is passed on with the attributes that follow `inline_attr`, in the same order. The
+/// compiler evaluates `cfg_attr` before passing the item on, the same as for native attribute
+/// macros: an active `cfg_attr` (such as `#[cfg_attr(feature = "x", ...)]` with `x` enabled) is
+/// replaced by its attributes, and an inactive one is removed, as are fields and variants with an
+/// inactive `cfg`:
+///
+/// ```
+/// macro_rules! list_attrs {
+///     (() $(#[$attr:meta])* struct $name:ident { $($fields:tt)* }) => {
+///         $(#[$attr])*
+///         struct $name { $($fields)* }
+///         impl $name {
+///             const ATTRS: &'static [&'static str] = &[$(stringify!($attr)),*];
+///         }
+///     };
+/// }
+///
+/// #[inline_proc::inline_attr[list_attrs]]
+/// #[cfg_attr(all(), derive(Debug))]
+/// #[cfg_attr(any(), derive(Clone))]
+/// #[doc = "plain"]
+/// #[cfg_attr(all(), doc = "active")]
+/// struct Item {
+///     #[cfg(any())]
+///     removed: u8,
+///     kept: u8,
+/// }
+///
+/// assert_eq!(Item::ATTRS, ["derive(Debug)", "doc = \"plain\"", "doc = \"active\""]);
+/// assert_eq!(format!("{:?}", Item { kept: 1 }), "Item { kept: 1 }");
+/// ```
+///
+/// `inline_attr` can itself be applied with `cfg_attr`, in which case the attribute macro is only
+/// used when the condition holds:
+///
+/// ```
+/// macro_rules! add_marker {
+///     (() $item:item) => {
+///         $item
+///         const MARKED: bool = true;
+///     };
+/// }
+///
+/// #[cfg_attr(all(), inline_proc::inline_attr[add_marker])]
+/// struct Active;
+///
+/// #[cfg_attr(any(), inline_proc::inline_attr[missing_macro])]
+/// struct Inactive;
+///
+/// assert!(MARKED);
+/// # let _ = (Active, Inactive);
+/// ```
+///
+/// The parameters and the item are passed on as they are, keeping their original spans, so an
+/// error the attribute macro reports on part of the item (for example with
+/// `syn::Error::new_spanned`) points at the item and not at the attribute.
 #[proc_macro_attribute]
 pub fn inline_attr(params: TokenStream1, item: TokenStream1) -> TokenStream1 {
     let item: TokenStream = item.into();
@@ -386,6 +2377,7 @@ impl Parse for AttrParams {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         Ok(Self {
             attr_path: input.parse()?,
+            // Like native attributes, the delimiter of the parameters is not passed on.
             tokens: input
                 .parse::<Option<Group>>()?
                 .map_or_else(TokenStream::new, |group| group.stream()),
@@ -412,6 +2404,26 @@ impl Parse for AttrParams {
 /// DeriveName1!(struct Item;);
 /// DeriveName2!(struct Item;);
 /// ```
+///
+/// `InlineDerive` can be imported under another name, but the names of its `inline_derive` and
+/// `helper` helper attributes are fixed:
+///
+/// ```
+/// use inline_proc::InlineDerive as MyInlineDerive;
+///
+/// macro_rules! Describe {
+///     (struct $name:ident;) => {
+///         impl $name {
+///             const NAME: &'static str = stringify!($name);
+///         }
+///     };
+/// }
+///
+/// #[derive(MyInlineDerive)]
+/// #[inline_derive(Describe)]
+/// struct Item;
+/// assert_eq!(Item::NAME, "Item");
+/// ```
 #[proc_macro_error]
 #[proc_macro_derive(InlineDerive, attributes(inline_derive, helper))]
 pub fn inline_derive(item: TokenStream1) -> TokenStream1 {
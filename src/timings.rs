@@ -0,0 +1,218 @@
+//! Reporting how long inner crates take to build, enabled with `INLINE_PROC_TIMINGS`, and how
+//! often their builds are cached, enabled with `INLINE_PROC_STATS`.
+
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::span_location;
+
+/// How to report build times.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TimingsFormat {
+    /// One human readable line per module.
+    Text,
+    /// One JSON object per line per module, after `inline-proc: `.
+    Json,
+}
+
+impl TimingsFormat {
+    /// Read the format from `INLINE_PROC_TIMINGS`, returning `None` if timings are disabled.
+    pub(crate) fn from_env() -> Option<Self> {
+        match env::var("INLINE_PROC_TIMINGS").ok()?.as_str() {
+            "" | "0" | "false" => None,
+            "json" => Some(Self::Json),
+            _ => Some(Self::Text),
+        }
+    }
+}
+
+/// The time taken to build one inline module.
+pub(crate) struct Timings<'a> {
+    pub(crate) module: &'a str,
+    /// Whether the module had never been built before, so nothing was cached.
+    pub(crate) cold: bool,
+    /// The time taken to compile the dependencies of the inner crate, as part of checking it.
+    pub(crate) dependencies: Duration,
+    /// The time taken to check the inner crate, including `dependencies`.
+    pub(crate) check: Duration,
+    /// The time taken to build the dylib after checking.
+    pub(crate) build: Duration,
+}
+
+impl Timings<'_> {
+    /// Print the timings to stderr in the given format. With `Text`, they are also added to the
+    /// summary table printed at the end of the compilation.
+    pub(crate) fn report(&self, format: TimingsFormat) {
+        let location = span_location(proc_macro::Span::call_site());
+        match format {
+            TimingsFormat::Text => {
+                SUMMARY.with(|summary| {
+                    summary.0.borrow_mut().push(SummaryRow {
+                        module: self.module.to_owned(),
+                        location: location.clone(),
+                        cold: self.cold,
+                        dependencies: self.dependencies,
+                        check: self.check,
+                        build: self.build,
+                    })
+                });
+                eprintln!(
+                "inline-proc: built `{}` at {} ({}) in {:.2}s: dependencies {:.2}s, check {:.2}s, build {:.2}s",
+                self.module,
+                location,
+                if self.cold { "cold" } else { "warm" },
+                (self.check + self.build).as_secs_f64(),
+                self.dependencies.as_secs_f64(),
+                self.check.as_secs_f64(),
+                self.build.as_secs_f64(),
+                )
+            }
+            // Cargo swallows lines of the compiler's output that start with `{`, taking them for its
+            // own JSON messages.
+            TimingsFormat::Json => eprintln!(
+                "inline-proc: {{\"module\":{},\"location\":{},\"cold\":{},\"dependencies_ms\":{},\"check_ms\":{},\"build_ms\":{}}}",
+                json_string(self.module),
+                json_string(&location),
+                self.cold,
+                self.dependencies.as_millis(),
+                self.check.as_millis(),
+                self.build.as_millis(),
+            ),
+        }
+    }
+}
+
+thread_local! {
+    static SUMMARY: Summary = const { Summary(RefCell::new(Vec::new())) };
+}
+
+/// The timings of the modules built on this thread. Macros are expanded on the thread of the
+/// compilation, so this is dropped, printing the table, when the compilation is over.
+struct Summary(RefCell<Vec<SummaryRow>>);
+
+struct SummaryRow {
+    module: String,
+    location: String,
+    cold: bool,
+    dependencies: Duration,
+    check: Duration,
+    build: Duration,
+}
+
+impl SummaryRow {
+    fn total(&self) -> Duration {
+        self.check + self.build
+    }
+}
+
+impl Drop for Summary {
+    /// Print a table of the modules, slowest first.
+    fn drop(&mut self) {
+        let rows = self.0.get_mut();
+        if rows.is_empty() {
+            return;
+        }
+        rows.sort_by_key(|row| Reverse(row.total()));
+        let width = |header: &str, column: fn(&SummaryRow) -> &str| {
+            rows.iter()
+                .map(|row| column(row).len())
+                .chain([header.len()])
+                .max()
+                .unwrap()
+        };
+        let module_width = width("module", |row| &row.module);
+        let location_width = width("location", |row| &row.location);
+        let mut table = format!(
+            "inline-proc: build times of {} inline modules\n{:module_width$}  {:location_width$}  cache  total   deps    check   build\n",
+            rows.len(),
+            "module",
+            "location",
+        );
+        for row in rows.iter() {
+            writeln!(
+                table,
+                "{:module_width$}  {:location_width$}  {:5}  {:<6.2}  {:<6.2}  {:<6.2}  {:.2}",
+                row.module,
+                row.location,
+                if row.cold { "cold" } else { "warm" },
+                row.total().as_secs_f64(),
+                row.dependencies.as_secs_f64(),
+                row.check.as_secs_f64(),
+                row.build.as_secs_f64(),
+            )
+            .unwrap();
+        }
+        eprint!("{}", table);
+    }
+}
+
+/// Quote and escape a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Whether a module's build could reuse the previous one.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CacheOutcome {
+    /// The inner crate was already built from the same source, so Cargo had nothing to do.
+    Hit,
+    /// The inner crate had been built before, but had to be rebuilt.
+    Rebuilt,
+    /// The inner crate had never been built before.
+    Cold,
+}
+
+/// The cache statistics of one module, for all of its expansions in this compiler process.
+#[derive(Default)]
+struct ModuleStats {
+    hits: u32,
+    misses: u32,
+    time: Duration,
+}
+
+/// Record the outcome of building a module and print its statistics so far to stderr.
+pub(crate) fn report_cache_stats(module: &str, outcome: CacheOutcome, time: Duration) {
+    // Proc macros may be expanded on several threads, and a module may be expanded more than once,
+    // for example by IDEs.
+    static STATS: Mutex<BTreeMap<String, ModuleStats>> = Mutex::new(BTreeMap::new());
+
+    let mut stats = STATS.lock().unwrap_or_else(PoisonError::into_inner);
+    let module_stats = stats.entry(module.to_owned()).or_default();
+    if outcome == CacheOutcome::Hit {
+        module_stats.hits += 1;
+    } else {
+        module_stats.misses += 1;
+    }
+    module_stats.time += time;
+
+    eprintln!(
+        "note: inline-proc: `{}` at {}: {} in {:.2}s ({} hits and {} misses, {:.2}s in total)",
+        module,
+        span_location(proc_macro::Span::call_site()),
+        match outcome {
+            CacheOutcome::Hit => "cache hit",
+            CacheOutcome::Rebuilt => "rebuilt",
+            CacheOutcome::Cold => "cold build",
+        },
+        time.as_secs_f64(),
+        module_stats.hits,
+        module_stats.misses,
+        module_stats.time.as_secs_f64(),
+    );
+}
@@ -0,0 +1,60 @@
+//! Binding the symbols of the compiled macros, eagerly by default or lazily with `lazy_binding`.
+#![cfg(unix)]
+
+mod support;
+
+use support::{assert_contains, Fixture};
+
+/// A `main.rs` with a module that has the options and whose macro returns its input, calling
+/// `extern_fn` first if it's invoked without input.
+fn main_rs(options: &str, extern_fn: &str) -> String {
+    format!(
+        r#"
+        #[inline_proc::inline_proc]
+        mod binding {{
+            metadata::ron!(
+                edition: "2021",
+                {}
+                dependencies: {{ "proc-macro2": "1" }},
+                exports: ( bang_macros: {{ "id": ( function: "id", proc_macro2: true ) }} ),
+            );
+            extern "C" {{
+                fn {extern_fn}() -> i32;
+            }}
+            pub fn id(input: proc_macro2::TokenStream) -> proc_macro2::TokenStream {{
+                if input.is_empty() {{
+                    std::process::exit(unsafe {{ {extern_fn}() }});
+                }}
+                input
+            }}
+        }}
+        fn main() {{
+            println!("{{}}", id!(42));
+        }}
+        "#,
+        options,
+        extern_fn = extern_fn,
+    )
+}
+
+#[test]
+fn missing_symbol_is_an_error_when_loading() {
+    let main_rs = main_rs("", "inline_proc_test_undefined_symbol");
+    let stderr = Fixture::new("binding_eager", &main_rs).compile_fail();
+    assert_contains(
+        &stderr,
+        &[
+            "Failed to open library",
+            "undefined symbol: inline_proc_test_undefined_symbol",
+        ],
+    );
+}
+
+/// Rust calls functions of other libraries through a table that is filled in when the library is
+/// loaded, so this can't check that undefined symbols are allowed, only that the option is passed
+/// on to the invocations.
+#[test]
+fn lazy_binding() {
+    let fixture = Fixture::new("binding_lazy", &main_rs("lazy_binding: true,", "getpid"));
+    assert_eq!(fixture.run(), "42\n");
+}
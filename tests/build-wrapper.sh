@@ -0,0 +1,6 @@
+#!/bin/sh
+# A build wrapper for `cargo_subcommand`, which records that it was run in the package being built
+# and then builds the inner crate like `cargo build`.
+
+touch "$CARGO_MANIFEST_DIR/build-wrapper-ran"
+exec cargo build "$@"
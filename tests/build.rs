@@ -0,0 +1,37 @@
+//! Building the inner crate after it has been checked.
+
+mod support;
+
+use support::{assert_contains, module_with_options, test_file, Fixture};
+
+fn fixture(name: &str) -> Fixture {
+    let options = format!("cargo: {:?},", test_file("expect-arg-cargo.sh"));
+    Fixture::new(name, &module_with_options(&options))
+}
+
+/// The build is run with the `cargo` option, which fails it here.
+#[test]
+fn failed_build_is_an_error() {
+    let stderr = fixture("failed_build_is_an_error")
+        .expect_cargo_arg("build", "--not-an-argument")
+        .compile_fail();
+    assert_contains(
+        &stderr,
+        &[
+            "Failed to build the macro",
+            "error: expected `--not-an-argument` in: CARGO_INCREMENTAL=0 build",
+        ],
+    );
+}
+
+/// A `cargo_subcommand` given as a path is run in place of `cargo build`.
+#[test]
+fn build_wrapper_is_run() {
+    let options = format!("cargo_subcommand: [{:?}],", test_file("build-wrapper.sh"));
+    let fixture = Fixture::new("build_wrapper_is_run", &module_with_options(&options));
+    // The wrapper leaves this file behind, so one from an earlier run must not count.
+    let marker = fixture.dir().join("build-wrapper-ran");
+    let _ = std::fs::remove_file(&marker);
+    fixture.build();
+    assert!(marker.exists(), "the build wrapper wasn't run");
+}
@@ -0,0 +1,44 @@
+//! `cache_api!`, which lists and removes the cached inner crates.
+
+mod support;
+
+use support::Fixture;
+
+#[test]
+fn entries_are_listed_and_invalidated() {
+    // A crates directory of its own, so that only this package's crates are listed. The fixture
+    // runs with it too.
+    let fixture = Fixture::new(
+        "cache_api",
+        r#"
+        #[inline_proc::inline_proc]
+        mod listed {
+            metadata::ron!(edition: "2021", dependencies: {}, exports: ());
+        }
+        mod cache {
+            inline_proc::cache_api!();
+        }
+        fn main() {
+            let entries = cache::cache_entries().unwrap();
+            for entry in &entries {
+                println!(
+                    "{:?} {:?} {:?} {} {}",
+                    entry.package,
+                    entry.version,
+                    entry.module,
+                    entry.path.join("Cargo.toml").is_file(),
+                    entry.size > 0,
+                );
+            }
+            cache::invalidate(&entries[0]).unwrap();
+            println!("{}", entries[0].path.exists());
+            println!("{}", cache::cache_entries().unwrap().len());
+        }
+        "#,
+    )
+    .with_private_tmpdir();
+    assert_eq!(
+        fixture.run(),
+        "Some(\"cache_api\") Some(\"0.0\") Some(\"listed\") true true\nfalse\n0\n"
+    );
+}
@@ -0,0 +1,50 @@
+//! The lock on the crates directory, which keeps its crates from being deleted by a compiler that
+//! finds them in an old layout while another compiler uses them.
+
+mod support;
+
+use std::fs::{self, File, TryLockError};
+use std::thread;
+use std::time::Duration;
+
+use support::{module_with_options, Fixture};
+
+#[test]
+fn builds_hold_the_lock() {
+    let fixture = Fixture::new("layout_lock_held", &module_with_options("")).with_private_tmpdir();
+    let lock_path = fixture.tmpdir().join("inline-proc-crates.lock");
+    let build = thread::spawn(move || fixture.build());
+
+    loop {
+        assert!(!build.is_finished(), "the build never held the lock");
+        if let Ok(lock) = File::open(&lock_path) {
+            match lock.try_lock() {
+                Err(TryLockError::WouldBlock) => break,
+                Err(TryLockError::Error(e)) => panic!("{}", e),
+                Ok(()) => {}
+            }
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    build.join().unwrap();
+}
+
+#[test]
+fn old_crates_are_deleted_once_unused() {
+    let fixture = Fixture::new("layout_migration", &module_with_options("")).with_private_tmpdir();
+    let old_crate = fixture.crates_dir().join("old-0.0-inline");
+    fs::create_dir_all(&old_crate).unwrap();
+    fs::write(fixture.crates_dir().join(".layout-version"), "0").unwrap();
+
+    // Like a compiler that is using the old crates.
+    let lock = File::create(fixture.tmpdir().join("inline-proc-crates.lock")).unwrap();
+    lock.lock_shared().unwrap();
+    let build = thread::spawn(move || fixture.build());
+    thread::sleep(Duration::from_secs(2));
+    assert!(old_crate.exists());
+    assert!(!build.is_finished());
+
+    drop(lock);
+    build.join().unwrap();
+    assert!(!old_crate.exists());
+}
@@ -0,0 +1,19 @@
+//! `INLINE_PROC_STATS` reports whether each module's build was cached.
+
+mod support;
+
+use support::{assert_contains, module_with_options, Fixture};
+
+#[test]
+fn second_expansion_is_a_cache_hit() {
+    let main_rs = module_with_options("");
+    let fixture = Fixture::new("cache_stats", &main_rs).env("INLINE_PROC_STATS", "1");
+    let first = fixture.build();
+    assert_contains(&first, &["note: inline-proc: `inline` at src/main.rs:"]);
+
+    // Changing the crate makes Cargo expand the module again, which is then already built.
+    let fixture = Fixture::new("cache_stats", &format!("{}\n// changed", main_rs))
+        .env("INLINE_PROC_STATS", "1");
+    let second = fixture.build();
+    assert_contains(&second, &["cache hit in", "(1 hits and 0 misses"]);
+}
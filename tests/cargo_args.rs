@@ -0,0 +1,66 @@
+//! The arguments and environment the inner Cargo is run with, checked by
+//! `tests/expect-arg-cargo.sh`.
+
+mod support;
+
+use support::{module_with_options, test_file, Fixture};
+
+/// A crate whose module has the options and checks how Cargo is run.
+fn fixture(name: &str, options: &str) -> Fixture {
+    let options = format!("cargo: {:?}, {}", test_file("expect-arg-cargo.sh"), options);
+    Fixture::new(name, &module_with_options(&options))
+}
+
+#[test]
+fn color_option() {
+    fixture("color_option", "color: true,")
+        .env_remove("NO_COLOR")
+        .expect_cargo_arg("check", "--color always")
+        .run();
+}
+
+#[test]
+fn no_color_disables_color() {
+    fixture("no_color", "color: true,")
+        .env("NO_COLOR", "1")
+        .env_remove("FORCE_COLOR")
+        .expect_cargo_arg("check", "--color never")
+        .run();
+}
+
+#[test]
+fn jobs_option() {
+    fixture("jobs_option", "jobs: 1,")
+        .env_remove("INLINE_PROC_JOBS")
+        .expect_cargo_arg("check build", "--jobs 1")
+        .run();
+}
+
+#[test]
+fn jobs_environment_variable_overrides_option() {
+    fixture("jobs_env", "jobs: 1,")
+        .env("INLINE_PROC_JOBS", "3")
+        .expect_cargo_arg("check build", "--jobs 3")
+        .run();
+}
+
+#[test]
+fn incremental_off_by_default() {
+    fixture("incremental_off", "")
+        .expect_cargo_arg("check build", "CARGO_INCREMENTAL=0")
+        .run();
+}
+
+#[test]
+fn incremental_option() {
+    fixture("incremental_option", "incremental: true,")
+        .expect_cargo_arg("check build", "CARGO_INCREMENTAL=1")
+        .run();
+}
+
+#[test]
+fn panic_unwinds_by_default() {
+    fixture("panic_unwinds_by_default", "")
+        .expect_cargo_arg("check build", r#"profile.dev.panic="unwind""#)
+        .run();
+}
@@ -0,0 +1,30 @@
+//! `cargo_config`, passed to the inner Cargo as `--config` overrides.
+
+mod support;
+
+use support::Fixture;
+
+#[test]
+fn config_is_applied() {
+    let fixture = Fixture::new(
+        "cargo_config",
+        r#"
+        #[inline_proc::inline_proc]
+        mod configured {
+            metadata::ron!(
+                edition: "2021",
+                cargo_config: { "env.INLINE_PROC_TEST_ANSWER": "42" },
+                dependencies: { "proc-macro2": "1" },
+                exports: ( bang_macros: { "answer": ( function: "answer", proc_macro2: true ) } ),
+            );
+            pub fn answer(_: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+                env!("INLINE_PROC_TEST_ANSWER").parse().unwrap()
+            }
+        }
+        fn main() {
+            println!("{}", answer!());
+        }
+        "#,
+    );
+    assert_eq!(fixture.run(), "42\n");
+}
@@ -0,0 +1,6 @@
+#!/bin/sh
+# A rustc wrapper that enables `cfg(inline_proc_wrapped)`, to test the `rustc_wrapper` option.
+
+rustc="$1"
+shift
+exec "$rustc" "$@" --cfg inline_proc_wrapped --check-cfg 'cfg(inline_proc_wrapped)'
@@ -0,0 +1,46 @@
+//! `clippy` and the environment variables that override it.
+
+mod support;
+
+use support::{module_with_options, test_file, Fixture};
+
+fn fixture(name: &str, clippy: bool) -> Fixture {
+    let options = format!(
+        "cargo: {:?}, clippy: {},",
+        test_file("expect-arg-cargo.sh"),
+        clippy
+    );
+    Fixture::new(name, &module_with_options(&options))
+        .env_remove("INLINE_PROC_NO_CLIPPY")
+        .env_remove("INLINE_PROC_FORCE_CLIPPY")
+}
+
+#[test]
+fn clippy_option() {
+    fixture("clippy_option", true)
+        .expect_cargo_arg("check clippy", "clippy")
+        .run();
+}
+
+#[test]
+fn no_clippy_overrides_option() {
+    fixture("no_clippy_overrides_option", true)
+        .env("INLINE_PROC_NO_CLIPPY", "1")
+        .expect_cargo_arg("check clippy", "check")
+        .run();
+}
+
+#[test]
+fn force_clippy_overrides_option() {
+    fixture("force_clippy_overrides_option", false)
+        .env("INLINE_PROC_FORCE_CLIPPY", "1")
+        .expect_cargo_arg("check clippy", "clippy")
+        .run();
+}
+
+#[test]
+fn check_without_clippy() {
+    fixture("check_without_clippy", false)
+        .expect_cargo_arg("check clippy", "check")
+        .run();
+}
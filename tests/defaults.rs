@@ -0,0 +1,74 @@
+//! Defaults files, merged under the metadata of the modules that name them with `defaults`.
+
+mod support;
+
+use std::fs;
+
+use support::{assert_contains, Fixture};
+
+/// A `main.rs` with a module whose metadata has the options, and that prints a constant quoted by
+/// its macro, so that it needs the `quote` dependency of the defaults file.
+fn main_rs(options: &str) -> String {
+    format!(
+        r#"
+        #[inline_proc::inline_proc]
+        mod inline {{
+            metadata::ron!(
+                {}
+                exports: ( bang_macros: {{ "answer": ( function: "answer", proc_macro2: true ) }} ),
+            );
+            pub fn answer(_: proc_macro2::TokenStream) -> proc_macro2::TokenStream {{
+                quote::quote!(42)
+            }}
+        }}
+        fn main() {{
+            println!("{{}}", answer!());
+        }}
+        "#,
+        options
+    )
+}
+
+const DEFAULTS: &str = r#"edition: "2021", dependencies: { "proc-macro2": "1", "quote": "1" },"#;
+
+#[test]
+fn defaults_are_merged() {
+    let fixture = Fixture::new("defaults_merged", &main_rs(r#"defaults: "shared.ron","#));
+    fs::write(fixture.dir().join("shared.ron"), DEFAULTS).unwrap();
+    assert_eq!(fixture.run(), "42\n");
+}
+
+#[test]
+fn unnamed_file_is_ignored() {
+    let fixture = Fixture::new(
+        "defaults_unnamed",
+        &main_rs(r#"edition: "2021", dependencies: { "proc-macro2": "1", "quote": "1" },"#),
+    );
+    // The file that used to be read implicitly, which would fail to build if it was.
+    fs::write(fixture.dir().join("inline-proc.ron"), r#"edition: "2020","#).unwrap();
+    assert_eq!(fixture.run(), "42\n");
+}
+
+#[test]
+fn exports_in_defaults_file_are_an_error() {
+    let fixture = Fixture::new("defaults_exports", &main_rs(r#"defaults: "shared.ron","#));
+    fs::write(
+        fixture.dir().join("shared.ron"),
+        format!("{} exports: (),", DEFAULTS),
+    )
+    .unwrap();
+    assert_contains(
+        &fixture.compile_fail(),
+        &["error: The defaults file", "shared.ron can't set `exports`"],
+    );
+}
+
+#[test]
+fn changes_to_defaults_file_are_tracked() {
+    let fixture = Fixture::new("defaults_tracked", &main_rs(r#"defaults: "shared.ron","#));
+    let defaults = fixture.dir().join("shared.ron");
+    fs::write(&defaults, DEFAULTS).unwrap();
+    fixture.build();
+    fs::write(&defaults, format!("{} rust_version: \"1.x\",", DEFAULTS)).unwrap();
+    assert_contains(&fixture.compile_fail(), &["Invalid rust_version `1.x`"]);
+}
@@ -0,0 +1,97 @@
+//! Path dependencies that lead back to the module being expanded, or to the package it is in.
+
+mod support;
+
+use std::fs;
+
+use support::{assert_contains, Fixture};
+
+#[test]
+fn cycle_between_modules() {
+    // `second` is expanded first, so its `Cargo.toml` already depends on `first` when `first` is
+    // expanded, although it fails to build since `first` doesn't exist yet.
+    let errors = Fixture::new(
+        "dependency_cycle",
+        r#"
+        #[inline_proc::inline_proc]
+        mod second {
+            metadata::ron!(
+                edition: "2021",
+                dependencies: {
+                    "first": ( path: "../dependency_cycle-0.0-first", package: "inline-proc-macro" ),
+                },
+                exports: (),
+            );
+        }
+
+        #[inline_proc::inline_proc]
+        mod first {
+            metadata::ron!(
+                edition: "2021",
+                dependencies: {
+                    "second": ( path: "../dependency_cycle-0.0-second", package: "inline-proc-macro" ),
+                },
+                exports: (),
+            );
+        }
+
+        fn main() {}
+        "#,
+    )
+    .compile_fail();
+    assert_contains(
+        &errors,
+        &[
+            "Module `first` depends on itself through the path dependencies of inline modules: \
+             `first` -> `second` -> `first`",
+        ],
+    );
+}
+
+#[test]
+fn dependency_on_the_host_package() {
+    // `helper`, a crate of the workspace, depends on the package the module is in.
+    let fixture = Fixture::new(
+        "host_loop",
+        r#"
+        #[inline_proc::inline_proc]
+        mod host_loop {
+            metadata::ron!(
+                edition: "2021",
+                dependencies: { "helper": ( workspace: true ) },
+                exports: (),
+            );
+        }
+        fn main() {}
+        "#,
+    );
+    let dir = fixture.dir();
+    let cargo_toml = fs::read_to_string(dir.join("Cargo.toml")).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        format!(
+            "{}[workspace.dependencies]\nhelper = {{ path = \"helper\" }}\n",
+            cargo_toml
+        ),
+    )
+    .unwrap();
+    fs::create_dir_all(dir.join("helper/src")).unwrap();
+    fs::write(
+        dir.join("helper/Cargo.toml"),
+        "[package]\n\
+         name = \"helper\"\n\
+         version = \"0.0.0\"\n\
+         edition = \"2021\"\n\
+         [dependencies]\n\
+         host_loop = { path = \"..\" }\n",
+    )
+    .unwrap();
+    fs::write(dir.join("helper/src/lib.rs"), "").unwrap();
+    assert_contains(
+        &fixture.compile_fail(),
+        &[
+            "Module `host_loop` depends on the package it is in, `host_loop`, through its path \
+           dependencies: `helper` -> `host_loop`",
+        ],
+    );
+}
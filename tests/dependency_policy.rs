@@ -0,0 +1,78 @@
+//! `allowed_dependencies` and `denied_dependencies`.
+
+mod support;
+
+use support::{assert_contains, Fixture};
+
+#[test]
+fn denied_direct_dependency() {
+    let errors = Fixture::new(
+        "denied_direct_dependency",
+        r#"
+        #[inline_proc::inline_proc]
+        mod denied {
+            metadata::ron!(
+                edition: "2021",
+                denied_dependencies: ["syn"],
+                dependencies: { "my_syn": ( version: "2", package: "syn" ) },
+                exports: (),
+            );
+        }
+        fn main() {}
+        "#,
+    )
+    .compile_fail();
+    assert_contains(
+        &errors,
+        &["Module `denied` depends on crates that aren't allowed: `my_syn`"],
+    );
+}
+
+#[test]
+fn denied_transitive_dependency() {
+    let errors = Fixture::new(
+        "denied_transitive_dependency",
+        r#"
+        #[inline_proc::inline_proc]
+        mod denied_transitively {
+            metadata::ron!(
+                edition: "2021",
+                denied_dependencies: ["unicode-ident"],
+                check_transitive_dependencies: true,
+                dependencies: { "proc-macro2": "1" },
+                exports: (),
+            );
+        }
+        fn main() {}
+        "#,
+    )
+    .compile_fail();
+    assert_contains(
+        &errors,
+        &[
+            "Module `denied_transitively` depends on crates that aren't allowed: \
+             `unicode-ident` (required by `proc-macro2`)",
+        ],
+    );
+}
+
+#[test]
+fn allowed_transitive_dependencies() {
+    Fixture::new(
+        "allowed_transitive_dependencies",
+        r#"
+        #[inline_proc::inline_proc]
+        mod allowed {
+            metadata::ron!(
+                edition: "2021",
+                allowed_dependencies: ["proc-macro2", "unicode-ident"],
+                check_transitive_dependencies: true,
+                dependencies: { "proc-macro2": "1" },
+                exports: (),
+            );
+        }
+        fn main() {}
+        "#,
+    )
+    .run();
+}
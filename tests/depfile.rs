@@ -0,0 +1,54 @@
+//! `depfile` and `INLINE_PROC_DEPFILE_DIR`, which list the files a module is built from for other
+//! build systems.
+
+mod support;
+
+use std::env::consts::DLL_SUFFIX;
+use std::fs;
+use std::path::Path;
+
+use support::{module_with_options, Fixture};
+
+#[test]
+fn depfile_lists_the_sources() {
+    let fixture = Fixture::new(
+        "depfile",
+        &module_with_options(r#"defaults: "shared.ron", depfile: "macros.d","#),
+    );
+    fs::write(fixture.dir().join("shared.ron"), "rlib: false,").unwrap();
+    let depfile_path = fixture.dir().join("macros.d");
+    let _ = fs::remove_file(&depfile_path);
+    fixture.build();
+
+    let depfile = fs::read_to_string(depfile_path).unwrap();
+    let mut lines = depfile.lines();
+    let (target, dependencies) = lines.next().unwrap().split_once(": ").unwrap();
+    assert!(target.ends_with(DLL_SUFFIX), "{}", depfile);
+    let dependencies: Vec<_> = dependencies.split(' ').map(Path::new).collect();
+    assert_eq!(
+        dependencies,
+        [
+            fixture.dir().join("src/main.rs"),
+            fixture.dir().join("shared.ron")
+        ],
+        "{}",
+        depfile
+    );
+    // Every dependency has an empty rule, so that deleting it doesn't break the build.
+    assert!(
+        lines.any(|line| line.ends_with("shared.ron:")),
+        "{}",
+        depfile
+    );
+}
+
+#[test]
+fn depfile_dir() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("depfile_dir_output");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    Fixture::new("depfile_dir", &module_with_options(""))
+        .env("INLINE_PROC_DEPFILE_DIR", &dir)
+        .build();
+    assert!(dir.join("depfile_dir-0.0-inline.d").is_file());
+}
@@ -0,0 +1,58 @@
+//! Invoking macros with a `deprecated` note.
+
+mod support;
+
+use support::{assert_contains, position, Fixture};
+
+const MAIN_RS: &str = r#"#![allow(macro_expanded_macro_exports_accessed_by_absolute_paths)]
+#![deny(deprecated)]
+
+#[doc(hidden)]
+pub use inline_proc::invoke_inline_macro;
+
+#[inline_proc::inline_proc]
+mod deprecating {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: {},
+        exports: (
+            bang_macros: {
+                "exported": ( function: "identity", export: true, deprecated: "use `a!`" ),
+                "local": ( function: "identity", visibility: "pub(crate)", deprecated: "use `b!`" ),
+            },
+        ),
+    );
+
+    pub fn identity(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+        input
+    }
+}
+
+fn main() {
+    exported!(println!("exported"));
+    crate::local!(println!("local"));
+}
+"#;
+
+#[test]
+fn invoking_deprecated_macros_is_reported() {
+    let stderr = Fixture::new("deprecated_invoked", MAIN_RS).compile_fail();
+    let (exported_line, _) = position(MAIN_RS, "exported!(");
+    let (local_line, _) = position(MAIN_RS, "crate::local!(");
+    assert_contains(
+        &stderr,
+        &[
+            "error: use of deprecated macro `exported`: use `a!`",
+            &format!("--> src/main.rs:{}:5", exported_line),
+            "error: use of deprecated macro `crate::local`: use `b!`",
+            &format!("--> src/main.rs:{}:5", local_line),
+        ],
+    );
+}
+
+#[test]
+fn deprecated_macros_still_work() {
+    let main_rs = MAIN_RS.replace("#![deny(deprecated)]", "#![allow(deprecated)]");
+    let stdout = Fixture::new("deprecated_allowed", &main_rs).run();
+    assert_eq!(stdout, "exported\nlocal\n");
+}
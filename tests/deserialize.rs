@@ -0,0 +1,88 @@
+//! `deserialize` passes macro functions their input deserialized from RON or JSON.
+
+mod support;
+
+use support::{assert_contains, position, Fixture};
+
+fn main_rs(main: &str) -> String {
+    format!(
+        r#"#[inline_proc::inline_proc]
+mod typed {{
+    metadata::ron!(
+        edition: "2021",
+        dependencies: {{
+            "serde": ( version: "1", features: ["derive"] ),
+            "ron": "0.10",
+            "serde_json": "1",
+            "proc-macro2": "1",
+        }},
+        exports: (
+            bang_macros: {{
+                "constant": (
+                    function: "constant",
+                    deserialize: ( format: "ron", type: "Constant" ),
+                    proc_macro2: true,
+                ),
+                "json_constant": (
+                    function: "constant",
+                    deserialize: ( format: "json", type: "Constant" ),
+                    proc_macro2: true,
+                ),
+            }},
+        ),
+    );
+
+    #[derive(serde::Deserialize)]
+    pub struct Constant {{
+        name: String,
+        value: i64,
+    }}
+
+    pub fn constant(constant: Constant) -> proc_macro2::TokenStream {{
+        format!("const {{}}: i64 = {{}};", constant.name, constant.value)
+            .parse()
+            .unwrap()
+    }}
+}}
+
+fn main() {{
+    {}
+}}
+"#,
+        main
+    )
+}
+
+#[test]
+fn typed_input() {
+    let main_rs = main_rs(
+        r#"
+    constant!((name: "ANSWER", value: -42));
+    json_constant!({ "name": "JSON", "value": 1 });
+    println!("{} {}", ANSWER, JSON);"#,
+    );
+    let stdout = Fixture::new("typed_input", &main_rs).run();
+    assert_eq!(stdout, "-42 1\n");
+}
+
+/// Input that doesn't deserialize is an error at the token the error is at, including on later
+/// lines of the input.
+#[test]
+fn deserialization_error() {
+    let main_rs = main_rs(
+        r#"
+    constant!((
+        name: "ANSWER",
+        value: "42",
+    ));"#,
+    );
+    let stderr = Fixture::new("deserialization_error", &main_rs).compile_fail();
+    let (line, column) = position(&main_rs, r#""42""#);
+    assert_contains(
+        &stderr,
+        &[
+            "error: Expected integer",
+            &format!("--> src/main.rs:{}:{}", line, column),
+        ],
+    );
+}
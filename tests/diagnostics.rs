@@ -0,0 +1,105 @@
+//! `diagnostics`, which chooses between full and short errors from the macro's crate, and errors in
+//! the code generated around the module's code.
+
+mod support;
+
+use support::{assert_contains, Fixture};
+
+/// A `main.rs` with a module that has the options and calls a method that doesn't exist, which the
+/// compiler suggests a similar one for.
+fn main_rs(options: &str) -> String {
+    format!(
+        r#"
+        #[inline_proc::inline_proc]
+        mod misspelled {{
+            metadata::ron!(
+                edition: "2021",
+                {}
+                dependencies: {{}},
+                exports: (),
+            );
+            pub fn length(text: &str) -> usize {{
+                text.lenght()
+            }}
+        }}
+        fn main() {{}}
+        "#,
+        options
+    )
+}
+
+const HELP: &str = "there is a method `len` with a similar name";
+
+#[test]
+fn full_diagnostics_by_default() {
+    let stderr = Fixture::new("diagnostics_full", &main_rs(""))
+        .env_remove("INLINE_PROC_DIAGNOSTICS")
+        .compile_fail();
+    assert_contains(&stderr, &["no method named `lenght` found", HELP]);
+}
+
+#[test]
+fn short_diagnostics() {
+    let stderr = Fixture::new("diagnostics_short", &main_rs(r#"diagnostics: "short","#))
+        .env_remove("INLINE_PROC_DIAGNOSTICS")
+        .compile_fail();
+    assert_contains(&stderr, &["no method named `lenght` found"]);
+    assert!(!stderr.contains(HELP), "{}", stderr);
+}
+
+#[test]
+fn environment_variable_overrides_option() {
+    let stderr = Fixture::new("diagnostics_env", &main_rs(r#"diagnostics: "short","#))
+        .env("INLINE_PROC_DIAGNOSTICS", "full")
+        .compile_fail();
+    assert_contains(&stderr, &[HELP]);
+}
+
+/// A module whose macro function has the wrong signature, with an error in the generated code that
+/// calls it, and the options.
+fn wrong_signature(options: &str) -> String {
+    format!(
+        r#"
+        #[inline_proc::inline_proc]
+        mod wrong_signature {{
+            metadata::ron!(
+                edition: "2021",
+                {}
+                dependencies: {{}},
+                exports: ( bang_macros: {{ "my_macro": "my_macro" }} ),
+            );
+            pub fn my_macro(input: String) -> proc_macro::TokenStream {{
+                input.parse().unwrap()
+            }}
+        }}
+        fn main() {{}}
+        "#,
+        options
+    )
+}
+
+#[test]
+fn generated_code_is_shown_when_verbose() {
+    let main_rs =
+        wrong_signature(r#"quiet: false, pretty_printer: ["rustfmt", "--edition", "2021"],"#);
+    let stderr = Fixture::new("diagnostics_generated", &main_rs).compile_fail();
+    assert_contains(
+        &stderr,
+        &[
+            "error: mismatched types",
+            "note: this is in code generated by `inline_proc`:",
+            // Formatted by the pretty printer, over several lines.
+            "-> ::proc_macro::TokenStream {\n",
+            "my_macro(input)",
+        ],
+    );
+}
+
+#[test]
+fn generated_code_is_hidden_by_default() {
+    let stderr = Fixture::new("diagnostics_generated_quiet", &wrong_signature(""))
+        .env_remove("INLINE_PROC_VERBOSE")
+        .compile_fail();
+    assert_contains(&stderr, &["error: mismatched types"]);
+    assert!(!stderr.contains("code generated by"), "{}", stderr);
+}
@@ -0,0 +1,67 @@
+//! The `doc` option of macros.
+
+mod support;
+
+use std::fs;
+use support::{assert_contains, Fixture};
+
+const MAIN_RS: &str = r#"
+#![allow(macro_expanded_macro_exports_accessed_by_absolute_paths)]
+
+#[doc(hidden)]
+pub use inline_proc::invoke_inline_macro;
+
+#[inline_proc::inline_proc]
+mod documented {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: {},
+        exports: (
+            bang_macros: {
+                "exported": ( function: "identity", export: true, doc: " Exported docs." ),
+                "reexported": (
+                    function: "identity",
+                    visibility: "pub(crate)",
+                    doc: " Re-exported docs.",
+                ),
+            },
+        ),
+    );
+
+    pub fn identity(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+        input
+    }
+}
+
+fn main() {
+    exported!(println!("exported"));
+    reexported!(println!("reexported"));
+}
+"#;
+
+#[test]
+fn documented_macros_can_be_invoked() {
+    let stdout = Fixture::new("doc_invoked", MAIN_RS).run();
+    assert_eq!(stdout, "exported\nreexported\n");
+}
+
+#[test]
+fn doc_is_shown_by_rustdoc() {
+    let fixture =
+        Fixture::new("doc_shown", MAIN_RS).env("RUSTDOCFLAGS", "--document-private-items");
+    let doc_dir = fixture.doc();
+    let exported = fs::read_to_string(doc_dir.join("macro.exported.html")).unwrap();
+    assert_contains(&exported, &["Exported docs."]);
+    let reexported = fs::read_to_string(doc_dir.join("macro.reexported.html")).unwrap();
+    assert_contains(&reexported, &["Re-exported docs."]);
+}
+
+#[test]
+fn doc_of_private_macro_is_an_error() {
+    let main_rs = MAIN_RS.replace("visibility: \"pub(crate)\",", "");
+    let stderr = Fixture::new("doc_private", &main_rs).compile_fail();
+    assert_contains(
+        &stderr,
+        &["Macro `reexported` has a `doc` but is neither exported"],
+    );
+}
@@ -0,0 +1,81 @@
+//! `INLINE_PROC_DUMP_EXPANSION`, which prints the output of every invocation with its location.
+
+mod support;
+
+use std::fs;
+
+use support::{assert_contains, position, Fixture};
+
+const MAIN_RS: &str = r#"#[inline_proc::inline_proc]
+mod dumped {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: { "proc-macro2": "1" },
+        exports: ( bang_macros: { "id": ( function: "id", proc_macro2: true ) } ),
+    );
+    pub fn id(input: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        input
+    }
+}
+fn main() {
+    id!(let answer = 42;);
+    println!("{}", answer);
+}
+"#;
+
+#[test]
+fn expansion_is_printed_with_its_location() {
+    let stderr = Fixture::new("dump_expansion", MAIN_RS)
+        .env("INLINE_PROC_DUMP_EXPANSION", "1")
+        .build();
+    // The location of the input, since the invocation itself is inside the generated macro.
+    let (line, column) = position(MAIN_RS, "let answer");
+    assert_contains(
+        &stderr,
+        &[
+            &format!("inline macro `id!` at src/main.rs:{}:{}", line, column),
+            "expanded to:\nlet answer = 42;",
+        ],
+    );
+}
+
+/// The compiler doesn't know the files of other crates whose paths were remapped, so if the input
+/// comes from one, only the package is printed.
+#[test]
+fn unknown_file_falls_back_to_the_package() {
+    let main_rs = MAIN_RS.replace("id!(let answer = 42;);", "helper::let_answer!(id, answer);");
+    let fixture = Fixture::new("dump_expansion_remapped", &main_rs);
+    let dir = fixture.dir();
+    let cargo_toml = fs::read_to_string(dir.join("Cargo.toml")).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        cargo_toml.replace("[workspace]", "helper = { path = \"helper\" }\n[workspace]"),
+    )
+    .unwrap();
+    fs::create_dir_all(dir.join("helper/src")).unwrap();
+    fs::write(
+        dir.join("helper/Cargo.toml"),
+        "[package]\nname = \"helper\"\nversion = \"0.0.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("helper/src/lib.rs"),
+        "#[macro_export] macro_rules! let_answer { ($m:ident, $name:ident) => { $m!(let $name = 42;) } }\n",
+    )
+    .unwrap();
+    let remap = format!(
+        "--remap-path-prefix={}=/remapped",
+        dir.join("helper").display()
+    );
+    let stderr = fixture
+        .env("INLINE_PROC_DUMP_EXPANSION", "1")
+        .env("RUSTFLAGS", remap)
+        .build();
+    assert_contains(
+        &stderr,
+        &[
+            "inline macro `id!` at an unknown file in ",
+            "dump_expansion_remapped expanded to:",
+        ],
+    );
+}
@@ -0,0 +1,35 @@
+//! The module's code is compiled with the edition of its metadata, which is suggested when the
+//! code needs a newer one.
+
+mod support;
+
+use support::{assert_contains, Fixture};
+
+#[test]
+fn newer_syntax_suggests_an_edition() {
+    let stderr = Fixture::new(
+        "edition_suggestion",
+        r#"
+        #[inline_proc::inline_proc]
+        mod old_edition {
+            metadata::ron!(
+                edition: "2015",
+                dependencies: {},
+                exports: (),
+            );
+            pub async fn helper() {}
+        }
+        fn main() {}
+        "#,
+    )
+    .compile_fail();
+    assert_contains(
+        &stderr,
+        &[
+            "error: `async fn` is not permitted in Rust 2015",
+            "help: the inner crate is built with edition 2015; set `edition: \"2018\"` in the \
+             metadata of the module to use this",
+        ],
+    );
+    assert!(!stderr.contains("Cargo.toml"), "{}", stderr);
+}
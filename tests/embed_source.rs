@@ -0,0 +1,55 @@
+//! `embed_source`, which embeds the source of the module in its dylib.
+
+mod support;
+
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+use std::path::{Path, PathBuf};
+
+use support::Fixture;
+
+/// Build a module with the metadata options and a constant in a crates directory of its own,
+/// returning the path of its dylib.
+fn build_dylib(name: &str, options: &str) -> PathBuf {
+    let main_rs = format!(
+        r#"
+        #[inline_proc::inline_proc]
+        mod inline {{
+            metadata::ron!(edition: "2021", {} dependencies: {{}}, exports: ());
+            pub const ANSWER: u32 = 42;
+        }}
+        fn main() {{}}
+        "#,
+        options
+    );
+    let fixture = Fixture::new(name, &main_rs).with_private_tmpdir();
+    fixture.build();
+    fixture
+        .crates_dir()
+        .join(format!("{}-0.0-inline", name))
+        .join("target/debug")
+        .join(format!("{}inline_proc_macro{}", DLL_PREFIX, DLL_SUFFIX))
+}
+
+/// The source embedded in the dylib, if there is one.
+fn embedded_source(dylib: &Path) -> Option<String> {
+    unsafe {
+        let library = libloading::Library::new(dylib).unwrap();
+        let source = library
+            .get::<*const &str>(b"__exported_macro_source\0")
+            .ok()?;
+        Some((**source).to_owned())
+    }
+}
+
+#[test]
+fn source_is_read_back_from_the_dylib() {
+    let dylib = build_dylib("embed_source", "embed_source: true,");
+    let source = embedded_source(&dylib).expect("no embedded source");
+    assert!(source.contains("pub const ANSWER : u32 = 42"), "{}", source);
+}
+
+#[test]
+fn source_is_not_embedded_by_default() {
+    let dylib = build_dylib("embed_source_default", "");
+    assert_eq!(embedded_source(&dylib), None);
+}
@@ -0,0 +1,19 @@
+#!/bin/sh
+# A Cargo that fails unless its commands listed in `EXPECT_CARGO_COMMANDS`, such as `check build`,
+# contain `EXPECT_CARGO_ARG`, such as `--jobs 1`, to test how Cargo is run. Commands are matched as
+# `CARGO_INCREMENTAL={value} {arguments}`, so that the environment can be expected too.
+
+command="CARGO_INCREMENTAL=${CARGO_INCREMENTAL-unset} $*"
+case " $EXPECT_CARGO_COMMANDS " in
+    *" $1 "*)
+        case " $command " in
+            *" $EXPECT_CARGO_ARG "*) ;;
+            *)
+                echo "error: expected \`$EXPECT_CARGO_ARG\` in: $command" >&2
+                exit 101
+                ;;
+        esac
+        ;;
+esac
+
+exec cargo "$@"
@@ -0,0 +1,46 @@
+//! `fail_fast`, which only reports the first error of the macro's crate.
+
+mod support;
+
+use support::{assert_contains, Fixture};
+
+/// A `main.rs` with a module that has the options, and whose code has three type errors.
+fn main_rs(options: &str) -> String {
+    format!(
+        r#"
+        #[inline_proc::inline_proc]
+        mod failing {{
+            metadata::ron!(
+                edition: "2021",
+                {}
+                dependencies: {{}},
+                exports: (),
+            );
+            pub fn errors() {{
+                let first: u8 = "first error";
+                let second: u8 = "second error";
+                let third: u8 = "third error";
+            }}
+        }}
+        fn main() {{}}
+        "#,
+        options
+    )
+}
+
+#[test]
+fn all_errors_by_default() {
+    let stderr = Fixture::new("fail_fast_off", &main_rs("")).compile_fail();
+    assert_contains(
+        &stderr,
+        &["\"first error\"", "\"second error\"", "\"third error\""],
+    );
+}
+
+#[test]
+fn only_first_error_with_fail_fast() {
+    let stderr = Fixture::new("fail_fast_on", &main_rs("fail_fast: true,")).compile_fail();
+    assert_contains(&stderr, &["\"first error\""]);
+    assert!(!stderr.contains("second error"), "{}", stderr);
+    assert!(!stderr.contains("third error"), "{}", stderr);
+}
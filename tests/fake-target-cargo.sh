@@ -0,0 +1,29 @@
+#!/bin/sh
+# A build wrapper for `cargo_subcommand` that builds `--target fake-target` for the host instead
+# and copies the dylib to where a build for that target puts it, so that tests can list a second
+# target without installing one.
+
+fake=
+manifest=
+previous=
+for arg do
+    shift
+    if [ "$previous" = --target ]; then
+        if [ "$arg" = fake-target ]; then
+            fake=1
+        else
+            set -- "$@" --target "$arg"
+        fi
+    elif [ "$arg" != --target ]; then
+        [ "$previous" = --manifest-path ] && manifest=$arg
+        set -- "$@" "$arg"
+    fi
+    previous=$arg
+done
+
+cargo build "$@" || exit
+if [ -n "$fake" ]; then
+    target_dir="$(dirname "$manifest")/target"
+    mkdir -p "$target_dir/fake-target/debug"
+    cp "$target_dir"/debug/*inline_proc_macro.* "$target_dir/fake-target/debug/"
+fi
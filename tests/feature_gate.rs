@@ -0,0 +1,119 @@
+//! `feature_gate`, which only generates a macro when a feature of the package is enabled, and
+//! exclusive groups of such macros.
+
+mod support;
+
+use std::fs;
+
+use support::{assert_contains, Fixture};
+
+const MAIN_RS: &str = r#"
+#[inline_proc::inline_proc]
+mod gated {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: { "proc-macro2": "1" },
+        exports: (bang_macros: {
+            "basic": ( function: "id", proc_macro2: true ),
+            "advanced": ( function: "id", proc_macro2: true, feature_gate: "advanced" ),
+        }),
+    );
+    pub fn id(input: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        input
+    }
+}
+fn main() {
+    println!("{}", basic!(1));
+    println!("{}", advanced!(2));
+}
+"#;
+
+/// A fixture with an `advanced` feature, enabled by default if `default` is set.
+fn fixture(name: &str, default: bool) -> Fixture {
+    let features = if default {
+        "advanced = []\ndefault = [\"advanced\"]\n"
+    } else {
+        "advanced = []\n"
+    };
+    with_features(Fixture::new(name, MAIN_RS), features)
+}
+
+/// Add the features to the `Cargo.toml` of the fixture.
+fn with_features(fixture: Fixture, features: &str) -> Fixture {
+    let cargo_toml = fixture.dir().join("Cargo.toml");
+    let contents = fs::read_to_string(&cargo_toml).unwrap();
+    fs::write(&cargo_toml, format!("{}[features]\n{}", contents, features)).unwrap();
+    fixture
+}
+
+#[test]
+fn enabled_feature_generates_the_macro() {
+    assert_eq!(fixture("feature_gate_on", true).run(), "1\n2\n");
+}
+
+#[test]
+fn disabled_feature_omits_the_macro() {
+    let stderr = fixture("feature_gate_off", false).compile_fail();
+    assert_contains(&stderr, &["cannot find macro `advanced` in this scope"]);
+    assert!(!stderr.contains("`basic`"), "{}", stderr);
+}
+
+/// A fixture with an exclusive group of two macros gated by the features `scalar` and `simd`, and
+/// the default features.
+fn exclusive_fixture(name: &str, default: &str) -> Fixture {
+    let main_rs = r#"
+    #[inline_proc::inline_proc]
+    mod backends {
+        metadata::ron!(
+            edition: "2021",
+            dependencies: { "proc-macro2": "1" },
+            exports: (bang_macros: {
+                "scalar": (
+                    function: "id",
+                    proc_macro2: true,
+                    group: "backend",
+                    exclusive: true,
+                    feature_gate: "scalar",
+                ),
+                "simd": (
+                    function: "id",
+                    proc_macro2: true,
+                    group: "backend",
+                    exclusive: true,
+                    feature_gate: "simd",
+                ),
+            }),
+        );
+        pub fn id(input: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+            input
+        }
+    }
+    fn main() {
+        println!("{}", scalar!(1));
+    }
+    "#;
+    with_features(
+        Fixture::new(name, main_rs),
+        &format!("scalar = []\nsimd = []\ndefault = {}\n", default),
+    )
+}
+
+#[test]
+fn one_macro_of_an_exclusive_group() {
+    assert_eq!(
+        exclusive_fixture("exclusive_one", r#"["scalar"]"#).run(),
+        "1\n"
+    );
+}
+
+#[test]
+fn two_macros_of_an_exclusive_group() {
+    let stderr = exclusive_fixture("exclusive_two", r#"["scalar", "simd"]"#).compile_fail();
+    assert_contains(
+        &stderr,
+        &[
+            "Only one macro of exclusive group `backend` can be enabled, but `scalar` (feature \
+           `scalar`) and `simd` (feature `simd`) both are",
+        ],
+    );
+}
@@ -0,0 +1,17 @@
+#!/bin/sh
+# A Cargo that fails the first time it runs each of the commands in `FLAKY_CARGO_COMMANDS`, such as
+# `check build`, with the error in `FLAKY_CARGO_ERROR`, to test `build_retries`. Whether a command
+# has failed is kept in a file starting with `FLAKY_CARGO_MARKER`, which is unique to each test.
+
+case " $FLAKY_CARGO_COMMANDS " in
+    *" $1 "*)
+        marker="$FLAKY_CARGO_MARKER.$1"
+        if [ ! -e "$marker" ]; then
+            touch "$marker"
+            echo "$FLAKY_CARGO_ERROR" >&2
+            exit 101
+        fi
+        ;;
+esac
+
+exec cargo "$@"
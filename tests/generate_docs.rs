@@ -0,0 +1,35 @@
+//! `generate_docs`, which documents the inner crate with `rustdoc_flags`.
+
+mod support;
+
+use support::Fixture;
+
+#[test]
+fn docs_are_generated_with_the_flags() {
+    let fixture = Fixture::new(
+        "generate_docs",
+        r#"
+        #[inline_proc::inline_proc]
+        mod documented {
+            metadata::ron!(
+                edition: "2021",
+                generate_docs: true,
+                rustdoc_flags: ["--cfg", "docsrs"],
+                dependencies: {},
+                exports: (),
+            );
+            /// Only documented with `--cfg docsrs`.
+            #[cfg(docsrs)]
+            pub fn documented_with_flags() {}
+        }
+        fn main() {}
+        "#,
+    )
+    .with_private_tmpdir();
+    fixture.build();
+    let docs = fixture
+        .crates_dir()
+        .join("generate_docs-0.0-documented/target/doc/inline_proc_macro");
+    assert!(docs.join("index.html").is_file());
+    assert!(docs.join("fn.documented_with_flags.html").is_file());
+}
@@ -0,0 +1,50 @@
+//! The `harness` binary, which runs a macro of the inner crate on sample input.
+
+mod support;
+
+use std::process::Command;
+
+use support::Fixture;
+
+/// `proc-macro2` is renamed, so that the harness must use the name of the dependency.
+const MAIN_RS: &str = r#"
+#[inline_proc::inline_proc]
+mod harnessed {
+    metadata::ron!(
+        edition: "2021",
+        harness: ( macro: "reverse", input: "a b c" ),
+        dependencies: { "pm2": ( version: "1", package: "proc-macro2" ) },
+        exports: ( bang_macros: { "reverse": ( function: "reverse", proc_macro2: true ) } ),
+    );
+    use pm2::TokenStream;
+
+    pub fn reverse(input: TokenStream) -> TokenStream {
+        let mut tokens: Vec<_> = input.into_iter().collect();
+        tokens.reverse();
+        tokens.into_iter().collect()
+    }
+}
+
+fn main() {}
+"#;
+
+#[test]
+fn harness_runs_the_macro() {
+    // A crates directory of its own, so that the inner crate is the only one in it.
+    let fixture = Fixture::new("harness", MAIN_RS).with_private_tmpdir();
+    fixture.build();
+
+    let crate_root = fixture.crates_dir().join("harness-0.0-harnessed");
+    let output = Command::new(env!("CARGO"))
+        .args(["run", "--quiet", "--bin", "harness", "--manifest-path"])
+        .arg(crate_root.join("Cargo.toml"))
+        .env_remove("CARGO_TARGET_DIR")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "the harness failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "c b a\n");
+}
@@ -0,0 +1,60 @@
+//! Bang macros with `include: true`, invoked with `include "path"`.
+
+#[inline_proc::inline_proc]
+mod including {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: { "proc-macro2": "1", "quote": "1", "syn": "2" },
+        exports: (
+            bang_macros: {
+                "line_count": ( function: "line_count", proc_macro2: true, include: true ),
+                "line_count_const": ( function: "line_count_const", proc_macro2: true, include: true ),
+                "stringify_input": ( function: "stringify_input", proc_macro2: true ),
+            },
+        ),
+    );
+    use proc_macro2::TokenStream;
+
+    fn count(input: TokenStream) -> usize {
+        syn::parse2::<syn::LitStr>(input)
+            .unwrap()
+            .value()
+            .lines()
+            .count()
+    }
+
+    pub fn line_count(input: TokenStream) -> TokenStream {
+        let count = count(input);
+        quote::quote!(#count)
+    }
+
+    pub fn line_count_const(input: TokenStream) -> TokenStream {
+        let count = count(input);
+        quote::quote!(const LINE_COUNT: usize = #count;)
+    }
+
+    pub fn stringify_input(input: TokenStream) -> TokenStream {
+        let input = input.to_string();
+        quote::quote!(#input)
+    }
+}
+
+line_count_const!(include "include-input.txt");
+
+#[test]
+fn include_in_expression_position() {
+    assert_eq!(line_count!(include "include-input.txt"), 3);
+}
+
+#[test]
+fn include_in_item_position() {
+    assert_eq!(LINE_COUNT, 3);
+}
+
+#[test]
+fn include_needs_opt_in() {
+    assert_eq!(
+        stringify_input!(include "include-input.txt"),
+        "include \"include-input.txt\""
+    );
+}
@@ -0,0 +1,94 @@
+//! Attribute macros of inline modules, applied with `inline_attr`.
+
+mod support;
+
+use support::{assert_contains, position, Fixture};
+
+const MAIN_RS: &str = r#"#[inline_proc::inline_proc]
+mod attributes {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: { "proc-macro2": "1", "syn": "2" },
+        exports: (
+            attributes: { "reject": ( function: "reject", proc_macro2: true ) },
+        ),
+    );
+    use proc_macro2::TokenStream;
+
+    pub fn reject(_: TokenStream, item: TokenStream) -> TokenStream {
+        let item: syn::DeriveInput = syn::parse2(item).unwrap();
+        syn::Error::new_spanned(item.ident, "rejected struct").to_compile_error()
+    }
+}
+
+#[inline_proc::inline_attr[reject]]
+struct Rejected;
+
+fn main() {}
+"#;
+
+/// An error on part of the item points at that part, and not at the attribute.
+#[test]
+fn error_points_at_item() {
+    let stderr = Fixture::new("attr_error_span", MAIN_RS).compile_fail();
+    let (line, column) = position(MAIN_RS, "Rejected;");
+    assert_contains(
+        &stderr,
+        &[
+            "error: rejected struct",
+            &format!("--> src/main.rs:{}:{}", line, column),
+        ],
+    );
+}
+
+/// An attribute macro can return several items, which replace the item in order, in its module and
+/// with the visibility the macro gave them.
+#[test]
+fn several_items_replace_the_item() {
+    let fixture = Fixture::new(
+        "attr_several_items",
+        r#"
+        #[inline_proc::inline_proc]
+        mod attributes {
+            metadata::ron!(
+                edition: "2021",
+                dependencies: { "proc-macro2": "1", "quote": "1", "syn": "2" },
+                exports: (
+                    attributes: { "add_new": ( function: "add_new", proc_macro2: true ) },
+                ),
+            );
+            use proc_macro2::TokenStream;
+
+            pub fn add_new(_: TokenStream, item: TokenStream) -> TokenStream {
+                let input: syn::DeriveInput = syn::parse2(item.clone()).unwrap();
+                let name = input.ident;
+                quote::quote! {
+                    #item
+                    impl #name {
+                        pub fn new() -> Self {
+                            Self
+                        }
+                    }
+                    macro_rules! defined_after {
+                        () => { "after the item" };
+                    }
+                }
+            }
+        }
+
+        mod shapes {
+            #[inline_proc::inline_attr[add_new]]
+            #[derive(Debug)]
+            pub struct Unit;
+
+            // Only visible textually after the macro's output.
+            pub const ORDER: &str = defined_after!();
+        }
+
+        fn main() {
+            println!("{:?} {}", shapes::Unit::new(), shapes::ORDER);
+        }
+        "#,
+    );
+    assert_eq!(fixture.run(), "Unit after the item\n");
+}
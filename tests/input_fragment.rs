@@ -0,0 +1,47 @@
+//! `input_fragment`, which makes a function-like macro match a single fragment.
+
+mod support;
+
+use support::{assert_contains, Fixture};
+
+/// A `main.rs` with a passthrough macro `checked` that takes an expression, and the body of
+/// `main`.
+fn main_rs(main: &str) -> String {
+    format!(
+        r#"
+        #[inline_proc::inline_proc]
+        mod fragments {{
+            metadata::ron!(
+                edition: "2021",
+                dependencies: {{}},
+                exports: ( bang_macros: {{ "checked": ( passthrough: true, input_fragment: "expr" ) }} ),
+            );
+        }}
+        fn main() {{
+            {}
+        }}
+        "#,
+        main
+    )
+}
+
+#[test]
+fn fragment_is_a_single_group() {
+    let fixture = Fixture::new(
+        "input_fragment",
+        &main_rs(r#"println!("{}", checked!(1 + 2) * 2);"#),
+    );
+    assert_eq!(fixture.run(), "6\n");
+}
+
+#[test]
+fn other_input_is_rejected() {
+    let fixture = Fixture::new(
+        "input_fragment_rejected",
+        &main_rs("checked!(struct NotAnExpression;);"),
+    );
+    assert_contains(
+        &fixture.compile_fail(),
+        &["no rules expected keyword `struct`"],
+    );
+}
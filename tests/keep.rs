@@ -0,0 +1,66 @@
+//! `#[inline_proc(keep)]` copies the inner crate outside of the cache.
+
+mod support;
+
+use std::env;
+use std::fs;
+use support::{assert_contains, Fixture};
+
+const MAIN_RS: &str = r#"
+#[inline_proc::inline_proc(keep)]
+mod kept {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: {},
+        exports: (),
+    );
+}
+
+#[inline_proc::inline_proc]
+mod not_kept {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: {},
+        exports: (),
+    );
+}
+
+fn main() {}
+"#;
+
+#[test]
+fn keep_copies_the_inner_crate() {
+    let kept_dir = env::temp_dir().join("inline-proc-kept");
+    let kept = kept_dir.join("keep_attribute-0.0-kept");
+    let not_kept = kept_dir.join("keep_attribute-0.0-not_kept");
+    let _ = fs::remove_dir_all(&kept);
+    let _ = fs::remove_dir_all(&not_kept);
+
+    let fixture = Fixture::new("keep_attribute", MAIN_RS);
+    let output = fixture.build();
+    assert_contains(
+        &output,
+        &[&format!(
+            "inline-proc: a copy of the inner crate of `kept` is kept in {}",
+            kept.display()
+        )],
+    );
+
+    assert!(kept.join("Cargo.toml").is_file());
+    assert!(kept.join("lib.rs").is_file());
+    assert!(!kept.join("target").exists());
+    assert!(fs::read_dir(&kept)
+        .unwrap()
+        .any(|file| file.unwrap().path().extension() == Some(env::consts::DLL_EXTENSION.as_ref())));
+    assert!(!not_kept.exists());
+}
+
+#[test]
+fn unknown_argument_is_an_error() {
+    let main_rs = MAIN_RS.replace("inline_proc(keep)", "inline_proc(kept)");
+    let stderr = Fixture::new("keep_misspelled", &main_rs).compile_fail();
+    assert_contains(
+        &stderr,
+        &["error: Unknown `inline_proc` argument `kept`, expected one of keep, verbose, clippy"],
+    );
+}
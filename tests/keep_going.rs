@@ -0,0 +1,50 @@
+//! Modules that fail to build report their errors without hiding those of other modules.
+
+mod support;
+
+use support::{assert_contains, Fixture};
+
+const MAIN_RS: &str = r#"
+#[inline_proc::inline_proc]
+mod first {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: {},
+        exports: (bang_macros: { "first": "first" }),
+    );
+
+    pub fn first(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+        let error: u8 = "first error";
+        input
+    }
+}
+
+#[inline_proc::inline_proc]
+mod second {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: {},
+        exports: (attributes: { "second": "second" }),
+    );
+
+    pub fn second(
+        _: proc_macro::TokenStream,
+        item: proc_macro::TokenStream,
+    ) -> proc_macro::TokenStream {
+        let error: u8 = "second error";
+        item
+    }
+}
+
+#[inline_proc::inline_attr[second]]
+fn main() {
+    first!(println!());
+}
+"#;
+
+#[test]
+fn every_failing_module_is_reported() {
+    let stderr = Fixture::new("keep_going", MAIN_RS).compile_fail();
+    assert_contains(&stderr, &["\"first error\"", "\"second error\""]);
+    assert!(!stderr.contains("cannot find"), "{}", stderr);
+}
@@ -0,0 +1,68 @@
+//! `links` and `link_search`, which link the inner crate to native libraries.
+#![cfg(unix)]
+
+mod support;
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use support::{assert_contains, module_with_options, Fixture};
+
+/// Build a static library with a C function returning 42, named `libinline_proc_answer.a`, into
+/// the directory.
+fn build_static_library(dir: &Path) {
+    fs::create_dir_all(dir).unwrap();
+    fs::write(
+        dir.join("answer.c"),
+        "unsigned inline_proc_answer(void) { return 42; }\n",
+    )
+    .unwrap();
+    let run = |command: &mut Command| assert!(command.current_dir(dir).status().unwrap().success());
+    run(Command::new("cc").args(["-c", "-fPIC", "answer.c", "-o", "answer.o"]));
+    run(Command::new("ar").args(["rcs", "libinline_proc_answer.a", "answer.o"]));
+}
+
+#[test]
+fn static_library_is_linked() {
+    let native = Path::new(env!("CARGO_TARGET_TMPDIR")).join("links_native");
+    build_static_library(&native);
+    let main_rs = format!(
+        r#"
+        #[inline_proc::inline_proc]
+        mod native {{
+            metadata::ron!(
+                edition: "2021",
+                links: ["static=inline_proc_answer"],
+                link_search: ["native={}"],
+                dependencies: {{ "proc-macro2": "1" }},
+                exports: ( bang_macros: {{ "answer": ( function: "answer", proc_macro2: true ) }} ),
+            );
+            unsafe extern "C" {{
+                fn inline_proc_answer() -> u32;
+            }}
+            pub fn answer(_: proc_macro2::TokenStream) -> proc_macro2::TokenStream {{
+                let answer = unsafe {{ inline_proc_answer() }};
+                proc_macro2::TokenTree::from(proc_macro2::Literal::u32_unsuffixed(answer)).into()
+            }}
+        }}
+        fn main() {{
+            println!("{{}}", answer!());
+        }}
+        "#,
+        native.display()
+    );
+    assert_eq!(Fixture::new("links", &main_rs).run(), "42\n");
+}
+
+#[test]
+fn unknown_kind_is_an_error() {
+    let fixture = Fixture::new(
+        "links_unknown_kind",
+        &module_with_options(r#"links: ["shared=z"],"#),
+    );
+    assert_contains(
+        &fixture.compile_fail(),
+        &["Invalid links entry `shared=z`: unknown kind `shared`, expected one of static, dylib, framework"],
+    );
+}
@@ -0,0 +1,69 @@
+//! `location: true` passes macro functions the file, line and column they were invoked at.
+
+mod support;
+
+use support::{position, Fixture};
+
+const MAIN_RS: &str = r#"#[inline_proc::inline_proc]
+mod located {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: { "proc-macro2": "1", "quote": "1" },
+        exports: (
+            bang_macros: { "here": ( function: "here", location: true, proc_macro2: true ) },
+            attributes: {
+                "located_fn": ( function: "located_fn", location: true, proc_macro2: true ),
+            },
+        ),
+    );
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    pub fn here(_input: TokenStream, file: &str, line: usize, column: usize) -> TokenStream {
+        let location = format!("{}:{}:{}", file, line, column);
+        quote!(#location)
+    }
+
+    pub fn located_fn(
+        _attr: TokenStream,
+        item: TokenStream,
+        file: &str,
+        line: usize,
+        column: usize,
+    ) -> TokenStream {
+        let location = format!("{}:{}:{}", file, line, column);
+        quote! {
+            #item
+            const ATTRIBUTE_LOCATION: &str = #location;
+        }
+    }
+}
+
+#[inline_proc::inline_attr[located_fn]]
+fn located() {}
+
+fn main() {
+    located();
+    println!("{}", here!(input));
+    println!("{}", ATTRIBUTE_LOCATION);
+    println!("{}", here!());
+}
+"#;
+
+#[test]
+fn location_is_the_invocation() {
+    let stdout = Fixture::new("location", MAIN_RS).run();
+    let lines: Vec<_> = stdout.lines().collect();
+    // The bang macro's input starts after `here!(`, and the attribute's item at `fn`. Without
+    // input, the location is that of the `#[inline_proc]` module.
+    let (here_line, here_column) = position(MAIN_RS, "here!(input)");
+    let (attribute_line, _) = position(MAIN_RS, "fn located()");
+    assert_eq!(
+        lines,
+        [
+            format!("src/main.rs:{}:{}", here_line, here_column + "here!(".len()),
+            format!("src/main.rs:{}:1", attribute_line),
+            "src/main.rs:1:1".to_owned(),
+        ]
+    );
+}
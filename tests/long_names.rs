@@ -0,0 +1,48 @@
+//! Package and module names too long to be used as is in the paths of the inner crates, which
+//! could exceed the path length limit of Windows.
+
+mod support;
+
+use support::Fixture;
+
+const PACKAGE: &str = "long_names_of_a_package_with_a_deep_dependency_tree";
+
+const MAIN_RS: &str = r#"
+#[inline_proc::inline_proc]
+mod a_module_with_a_name_much_longer_than_most_module_names {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: { "proc-macro2": "1" },
+        exports: ( bang_macros: { "id": ( function: "id", proc_macro2: true ) } ),
+    );
+    pub fn id(input: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        input
+    }
+}
+fn main() {
+    println!("{}", id!(42));
+}
+"#;
+
+#[test]
+fn long_names_are_shortened() {
+    let fixture = Fixture::new(PACKAGE, MAIN_RS).with_private_tmpdir();
+    assert_eq!(fixture.run(), "42\n");
+    let crate_root = fixture
+        .crates_dir()
+        .join("long_names_of_a_60996b86-0.0-a_module_with_a_a160bb1a");
+    assert!(crate_root.join("Cargo.toml").is_file());
+}
+
+#[test]
+#[cfg(windows)]
+fn long_names_build_in_a_deep_directory() {
+    // With the names in full, the paths of the dependencies in the target directory of the inner
+    // crate would be well over 260 characters long.
+    let tmpdir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("long_names_deep_tmp")
+        .join("a_deeply_nested_temporary_directory")
+        .join("of_a_continuous_integration_runner");
+    let fixture = Fixture::new(PACKAGE, MAIN_RS).with_tmpdir(tmpdir);
+    assert_eq!(fixture.run(), "42\n");
+}
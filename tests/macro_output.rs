@@ -0,0 +1,95 @@
+//! Macros with `macro_output` report diagnostics along with their output.
+
+mod support;
+
+use support::{assert_contains, Fixture};
+
+/// A module whose `no_unit` attribute warns about unit structs and whose `positive!` macro reports
+/// an error for negative numbers, both still returning their input.
+fn main_rs(main: &str) -> String {
+    format!(
+        r#"
+#[inline_proc::inline_proc]
+mod diagnosing {{
+    metadata::ron!(
+        edition: "2021",
+        dependencies: {{ "proc-macro2": "1" }},
+        exports: (
+            bang_macros: {{
+                "positive": ( function: "positive", macro_output: true, proc_macro2: true ),
+            }},
+            attributes: {{
+                "no_unit": ( function: "no_unit", macro_output: true, proc_macro2: true ),
+            }},
+        ),
+    );
+    use proc_macro2::{{Span, TokenStream, TokenTree}};
+
+    type MacroOutput = crate::inline_proc::MacroOutput<TokenStream, Span>;
+
+    pub fn no_unit(_attr: TokenStream, item: TokenStream) -> MacroOutput {{
+        let is_unit = matches!(
+            item.clone().into_iter().last(),
+            Some(TokenTree::Punct(punct)) if punct.as_char() == ';'
+        );
+        let span = item.clone().into_iter().next().unwrap().span();
+        let output = MacroOutput::new(item);
+        if is_unit {{
+            output.warning(span, "unit structs aren't supported yet")
+        }} else {{
+            output
+        }}
+    }}
+
+    pub fn positive(input: TokenStream) -> MacroOutput {{
+        let first = input.clone().into_iter().next().unwrap();
+        let output = MacroOutput::new(input);
+        match first {{
+            TokenTree::Punct(punct) if punct.as_char() == '-' => {{
+                output.error(punct.span(), "the number must be positive")
+            }}
+            _ => output,
+        }}
+    }}
+}}
+
+#[inline_proc::inline_attr[no_unit]]
+struct Unit;
+
+fn main() {{
+    let _ = Unit;
+    {}
+}}
+"#,
+        main
+    )
+}
+
+/// Warnings don't fail the build, and the output is used.
+#[test]
+fn output_with_warning() {
+    let stdout = Fixture::new(
+        "output_with_warning",
+        &main_rs(r#"println!("{}", positive!(1));"#),
+    )
+    .run();
+    assert_eq!(stdout, "1\n");
+}
+
+/// Errors point at their span, and the output is still used, so there are no errors about it.
+#[test]
+fn output_with_error() {
+    let stderr = Fixture::new(
+        "output_with_error",
+        &main_rs("let number: i32 = positive!(-1);"),
+    )
+    .compile_fail();
+    assert_contains(
+        &stderr,
+        &[
+            "error: the number must be positive",
+            "let number: i32 = positive!(-1);",
+        ],
+    );
+    assert!(!stderr.contains("error[E"), "{}", stderr);
+}
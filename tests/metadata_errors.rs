@@ -0,0 +1,184 @@
+//! Errors in the metadata point at the key that caused them.
+
+mod support;
+
+use support::{assert_contains, position, Fixture};
+
+/// A `main.rs` with a module that has the metadata options besides an edition, and exports a bang
+/// macro `m` with the options.
+fn main_rs(options: &str, macro_options: &str) -> String {
+    format!(
+        r#"#[inline_proc::inline_proc]
+mod inline {{
+    metadata::ron!(
+        edition: "2021",
+        {}
+        exports: ( bang_macros: {{ "m": ( {} ) }} ),
+    );
+    pub fn m(input: proc_macro::TokenStream) -> proc_macro::TokenStream {{
+        input
+    }}
+}}
+fn main() {{}}
+"#,
+        options, macro_options
+    )
+}
+
+/// Assert that the crate fails to compile with the error, pointing at `key`.
+#[track_caller]
+fn assert_error_at(name: &str, main_rs: &str, error: &str, key: &str) {
+    let stderr = Fixture::new(name, main_rs).compile_fail();
+    let (line, column) = position(main_rs, key);
+    assert_contains(
+        &stderr,
+        &[
+            &format!("error: {}", error),
+            &format!("--> src/main.rs:{}:{}", line, column),
+        ],
+    );
+}
+
+#[test]
+fn invalid_dependency_rename() {
+    assert_error_at(
+        "error_at_package",
+        &main_rs(
+            r#"dependencies: { "serde": ( version: "1", package: "not a crate" ) },"#,
+            r#"function: "m""#,
+        ),
+        "Dependency `serde` renames invalid crate name `not a crate`",
+        "package:",
+    );
+}
+
+#[test]
+fn invalid_rust_version() {
+    assert_error_at(
+        "error_at_rust_version",
+        &main_rs(
+            r#"rust_version: "1.x", dependencies: {},"#,
+            r#"function: "m""#,
+        ),
+        "Invalid rust_version `1.x`",
+        "rust_version:",
+    );
+}
+
+#[test]
+fn invalid_package_metadata_key() {
+    assert_error_at(
+        "error_at_package_metadata",
+        &main_rs(
+            r#"package_metadata: { "bad key": "value" }, dependencies: {},"#,
+            r#"function: "m""#,
+        ),
+        "Invalid package metadata key `bad key`",
+        "\"bad key\"",
+    );
+}
+
+#[test]
+fn empty_cargo_subcommand() {
+    assert_error_at(
+        "error_at_cargo_subcommand",
+        &main_rs(
+            r#"cargo_subcommand: [], dependencies: {},"#,
+            r#"function: "m""#,
+        ),
+        "`cargo_subcommand` can't be empty",
+        "cargo_subcommand:",
+    );
+}
+
+#[test]
+fn macro_option() {
+    assert_error_at(
+        "error_at_macro_option",
+        &main_rs("dependencies: {},", r#"function: "m", exclusive: true"#),
+        "Macro `m` is `exclusive` but isn't in a `group`",
+        "exclusive:",
+    );
+}
+
+#[test]
+fn public_visibility() {
+    assert_error_at(
+        "error_at_public_visibility",
+        &main_rs("dependencies: {},", r#"function: "m", visibility: "pub""#),
+        "Macro `m` can't have `visibility: \"pub\"`, since only exported macros can be used \
+         outside the crate; use `export: true` instead",
+        "visibility:",
+    );
+}
+
+#[test]
+fn empty_visibility() {
+    assert_error_at(
+        "error_at_empty_visibility",
+        &main_rs("dependencies: {},", r#"function: "m", visibility: """#),
+        "Macro `m` has an empty `visibility`",
+        "visibility:",
+    );
+}
+
+#[test]
+fn parse_without_syn() {
+    assert_error_at(
+        "error_at_parse",
+        &main_rs(
+            "dependencies: {},",
+            r#"function: "m", parse: "syn::DeriveInput""#,
+        ),
+        "Macro `m` needs `syn` as a dependency to use `parse`",
+        "parse:",
+    );
+}
+
+#[test]
+fn deserialize_without_ron() {
+    assert_error_at(
+        "error_at_deserialize",
+        &main_rs(
+            "dependencies: {},",
+            r#"function: "m", deserialize: ( format: "ron", type: "Constant" )"#,
+        ),
+        "Macro `m` needs `ron` as a dependency to use `deserialize`",
+        "deserialize:",
+    );
+}
+
+#[test]
+fn argument_spans_on_attribute() {
+    let main_rs = main_rs("dependencies: {},", r#"function: "m", spans: "argument""#)
+        .replace("bang_macros", "attributes")
+        .replace(
+            "input: proc_macro::TokenStream",
+            "_attr: proc_macro::TokenStream, input: proc_macro::TokenStream",
+        );
+    assert_error_at(
+        "error_at_argument_spans",
+        &main_rs,
+        "Macro `m`: only function-like macros can have `spans: \"argument\"`",
+        "spans:",
+    );
+}
+
+/// An option with a default that wasn't written points at the closest key that was.
+#[test]
+fn missing_option() {
+    assert_error_at(
+        "error_at_enclosing_key",
+        &main_rs("dependencies: {},", "export: true"),
+        "Macro `m` needs a `function`, or `passthrough: true`",
+        "\"m\"",
+    );
+}
+
+/// Cargo checks the edition, so editions it knows work without an update of `inline-proc`.
+#[test]
+fn invalid_edition_is_reported_by_cargo() {
+    let main_rs = main_rs("dependencies: {},", r#"function: "m""#).replace("2021", "2020");
+    let stderr = Fixture::new("invalid_edition", &main_rs).compile_fail();
+    assert_contains(&stderr, &["but `2020` is unknown"]);
+}
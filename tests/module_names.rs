@@ -0,0 +1,33 @@
+//! Modules whose names are sanitized to the same string must still get inner crates of their own.
+
+#[inline_proc::inline_proc]
+mod café {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: { "proc-macro2": "1", "quote": "1" },
+        exports: ( bang_macros: { "from_cafe": ( function: "name", proc_macro2: true ) } ),
+    );
+
+    pub fn name(_: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        quote::quote!("café")
+    }
+}
+
+#[inline_proc::inline_proc]
+mod caf_ue9 {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: { "proc-macro2": "1", "quote": "1" },
+        exports: ( bang_macros: { "from_caf_ue9": ( function: "name", proc_macro2: true ) } ),
+    );
+
+    pub fn name(_: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        quote::quote!("caf_ue9")
+    }
+}
+
+#[test]
+fn colliding_names_get_different_crates() {
+    assert_eq!(from_cafe!(), "café");
+    assert_eq!(from_caf_ue9!(), "caf_ue9");
+}
@@ -0,0 +1,100 @@
+//! Macros with `output_module: true`.
+
+mod support;
+
+use support::{assert_contains, Fixture};
+
+#[inline_proc::inline_proc]
+mod prolific {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: { "proc-macro2": "1", "quote": "1" },
+        exports: (
+            bang_macros: {
+                "api": ( function: "api", proc_macro2: true, output_module: true ),
+                "helpers_only": ( function: "helpers_only", proc_macro2: true, output_module: true ),
+            },
+        ),
+    );
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    pub fn api(name: TokenStream) -> TokenStream {
+        quote! {
+            fn helper() -> u32 { 2 }
+            pub fn #name() -> u32 { helper() }
+            pub(crate) const CRATE_VISIBLE: () = ();
+            pub(super) struct SuperVisible;
+        }
+    }
+
+    pub fn helpers_only(_: TokenStream) -> TokenStream {
+        quote! {
+            const fn helper() -> u32 { 1 }
+            const _: () = assert!(helper() == 1);
+        }
+    }
+}
+
+mod outputs {
+    fn helper() -> u32 {
+        10
+    }
+
+    api!(two);
+    helpers_only!();
+    helpers_only!();
+
+    pub fn call_site_helper() -> u32 {
+        helper()
+    }
+
+    pub fn super_visible() {
+        let _ = SuperVisible;
+    }
+}
+
+#[test]
+fn identical_invocations_get_different_modules() {
+    assert_eq!(outputs::two(), 2);
+    assert_eq!(outputs::call_site_helper(), 10);
+    outputs::super_visible();
+}
+
+#[test]
+fn items_keep_their_visibility() {
+    let () = outputs::CRATE_VISIBLE;
+    let errors = Fixture::new(
+        "output_module_visibility",
+        r#"
+        #[inline_proc::inline_proc]
+        mod prolific {
+            metadata::ron!(
+                edition: "2021",
+                dependencies: { "proc-macro2": "1", "quote": "1" },
+                exports: (
+                    bang_macros: { "api": ( function: "api", proc_macro2: true, output_module: true ) },
+                ),
+            );
+            pub fn api(_: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+                quote::quote!(fn private_helper() {} pub(super) fn super_visible() {})
+            }
+        }
+        mod outputs {
+            api!();
+        }
+        fn main() {
+            outputs::private_helper();
+            outputs::super_visible();
+        }
+        "#,
+    )
+    .compile_fail();
+    assert_contains(
+        &errors,
+        &[
+            "cannot find function `private_helper` in module `outputs`",
+            "function import `super_visible` is private",
+        ],
+    );
+}
@@ -0,0 +1,37 @@
+//! Panics in macros are reported as errors instead of aborting the compiler.
+
+mod support;
+
+use support::{assert_contains, Fixture};
+
+#[test]
+fn panic_is_reported() {
+    let stderr = Fixture::new(
+        "panic_is_reported",
+        r#"
+        #[inline_proc::inline_proc]
+        mod unwinding {
+            metadata::ron!(
+                edition: "2021",
+                dependencies: { "proc-macro2": "1" },
+                exports: ( bang_macros: { "panics": ( function: "panics", proc_macro2: true ) } ),
+            );
+
+            pub fn panics(_input: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+                panic!("this panic unwinds");
+            }
+        }
+
+        panics!();
+
+        fn main() {}
+        "#,
+    )
+    .compile_fail();
+    assert_contains(&stderr, &["error: The macro panicked: this panic unwinds"]);
+    assert!(
+        !stderr.contains("the compiler unexpectedly panicked"),
+        "{}",
+        stderr
+    );
+}
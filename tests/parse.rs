@@ -0,0 +1,71 @@
+//! `parse` passes macro functions their input parsed into a `syn` type.
+
+mod support;
+
+use support::{assert_contains, position, Fixture};
+
+fn main_rs(main: &str) -> String {
+    format!(
+        r#"#[inline_proc::inline_proc]
+mod parsed {{
+    metadata::ron!(
+        edition: "2021",
+        dependencies: {{ "proc-macro2": "1", "syn": "2", "quote": "1" }},
+        exports: (
+            bang_macros: {{
+                "double": ( function: "double", parse: "syn::LitInt", proc_macro2: true ),
+            }},
+            derives: {{
+                "Named": ( function: "named", parse: "syn::DeriveInput", proc_macro2: true ),
+            }},
+        ),
+    );
+    use proc_macro2::TokenStream;
+
+    pub fn double(input: syn::LitInt) -> TokenStream {{
+        let doubled = input.base10_parse::<u32>().unwrap() * 2;
+        quote::quote!(#doubled)
+    }}
+
+    pub fn named(input: syn::DeriveInput) -> TokenStream {{
+        let name = &input.ident;
+        quote::quote!(impl #name {{ pub const NAME: &str = stringify!(#name); }})
+    }}
+}}
+
+#[derive(inline_proc::InlineDerive)]
+#[inline_derive(Named)]
+struct Item;
+
+fn main() {{
+    {}
+}}
+"#,
+        main
+    )
+}
+
+#[test]
+fn parsed_input() {
+    let stdout = Fixture::new(
+        "parsed_input",
+        &main_rs(r#"println!("{} {}", Item::NAME, double!(21));"#),
+    )
+    .run();
+    assert_eq!(stdout, "Item 42\n");
+}
+
+/// Input that doesn't parse is an error at the token that doesn't, without calling the function.
+#[test]
+fn parse_error() {
+    let main_rs = main_rs("let _ = (Item::NAME, double!(twenty_one));");
+    let stderr = Fixture::new("parse_error", &main_rs).compile_fail();
+    let (line, column) = position(&main_rs, "twenty_one");
+    assert_contains(
+        &stderr,
+        &[
+            "error: expected integer literal",
+            &format!("--> src/main.rs:{}:{}", line, column),
+        ],
+    );
+}
@@ -0,0 +1,116 @@
+//! Modules whose macros are all passthrough macros, which aren't built.
+
+mod support;
+
+use std::env;
+use std::fs;
+use std::time::{Duration, Instant};
+use support::{assert_contains, Fixture};
+
+/// A `main.rs` with a module that has the metadata options, followed by its export of `same!`,
+/// which is invoked.
+fn main_rs(options: &str, same: &str, code: &str) -> String {
+    format!(
+        r#"
+        #[inline_proc::inline_proc]
+        mod inline {{
+            metadata::ron!(
+                edition: "2021",
+                {}
+                exports: ( bang_macros: {{ "same": {} }} ),
+            );
+            {}
+        }}
+        fn main() {{
+            println!("{{}}", same!(1));
+        }}
+        "#,
+        options, same, code
+    )
+}
+
+#[test]
+fn passthrough_expands_to_input() {
+    let stdout = Fixture::new(
+        "passthrough",
+        &main_rs("dependencies: {},", "( passthrough: true )", ""),
+    )
+    .run();
+    assert_eq!(stdout, "1\n");
+}
+
+#[test]
+fn options_of_functions_are_rejected() {
+    let main_rs = main_rs(
+        "dependencies: {},",
+        "( passthrough: true, location: true )",
+        "",
+    );
+    let stderr = Fixture::new("passthrough_location", &main_rs).compile_fail();
+    assert_contains(
+        &stderr,
+        &[
+            "Passthrough macro `same` can't have `location`, since it doesn't call a function",
+            "location: true",
+        ],
+    );
+}
+
+#[test]
+fn metadata_is_validated() {
+    let main_rs = main_rs(
+        "shared_cache: true, shared_workspace: true, dependencies: {},",
+        "( passthrough: true )",
+        "",
+    );
+    let stderr = Fixture::new("passthrough_validated", &main_rs).compile_fail();
+    assert_contains(
+        &stderr,
+        &["`shared_cache` and `shared_workspace` can't be used together"],
+    );
+}
+
+/// The time to build a fixture whose inner crate was never built, and then to build it again after
+/// a change to the outer crate.
+fn build_times(name: &str, main_rs: &str) -> (Duration, Duration) {
+    let crates_dir = env::temp_dir().join("inline-proc-crates");
+    let _ = fs::remove_dir_all(crates_dir.join(format!("{}-0.0-inline", name)));
+    // The outer crate is built first, so that only the module's expansion is timed.
+    Fixture::new(name, "fn main() {}").build();
+
+    let start = Instant::now();
+    Fixture::new(name, main_rs).build();
+    let cold = start.elapsed();
+    let start = Instant::now();
+    Fixture::new(name, &format!("{}\n// changed", main_rs)).build();
+    (cold, start.elapsed())
+}
+
+/// Measures the time saved by not building a module, to be run with
+/// `cargo test --test passthrough -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn passthrough_saves_build_time() {
+    // A typical module, whose dependencies have to be built too.
+    let dependencies = r#"dependencies: { "proc-macro2": "1", "quote": "1", "syn": "2" },"#;
+    let function = main_rs(
+        dependencies,
+        r#"( function: "same", proc_macro2: true )"#,
+        "pub fn same(input: proc_macro2::TokenStream) -> proc_macro2::TokenStream { input }",
+    );
+    let passthrough = main_rs("dependencies: {},", "( passthrough: true )", "");
+    let (function_cold, function_warm) = build_times("passthrough_fn", &function);
+    let (passthrough_cold, passthrough_warm) = build_times("passthrough_only", &passthrough);
+    println!(
+        "cold builds: {:.2}s with a function, {:.2}s with passthrough",
+        function_cold.as_secs_f64(),
+        passthrough_cold.as_secs_f64(),
+    );
+    println!(
+        "rebuilds: {:.2}s with a function, {:.2}s with passthrough",
+        function_warm.as_secs_f64(),
+        passthrough_warm.as_secs_f64(),
+    );
+    assert!(passthrough_cold < function_cold);
+    assert!(passthrough_warm < function_warm);
+}
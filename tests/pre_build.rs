@@ -0,0 +1,64 @@
+//! `pre_build`, a command run in the inner crate before it is built.
+#![cfg(unix)]
+
+mod support;
+
+use support::{assert_contains, module_with_options, Fixture};
+
+/// A `main.rs` with a module that includes the file generated by its `pre_build` command, which
+/// holds the value of `INLINE_PROC_TEST_ANSWER`, and a macro that expands to it.
+fn main_rs(comment: &str) -> String {
+    format!(
+        r#"
+        #[inline_proc::inline_proc]
+        mod generated {{
+            metadata::ron!(
+                edition: "2021",
+                pre_build: [
+                    "sh",
+                    "-c",
+                    "echo \"pub const ANSWER: u32 = $INLINE_PROC_TEST_ANSWER;\" > answer.rs",
+                ],
+                pre_build_outputs: ["answer.rs"],
+                dependencies: {{ "proc-macro2": "1", "quote": "1" }},
+                exports: ( bang_macros: {{ "answer": ( function: "answer", proc_macro2: true ) }} ),
+            );
+            include!("answer.rs");
+            pub fn answer(_: proc_macro2::TokenStream) -> proc_macro2::TokenStream {{
+                quote::quote!(#ANSWER)
+            }}
+        }}
+        fn main() {{
+            println!("{{}}", answer!());
+        }}
+        // {}
+        "#,
+        comment
+    )
+}
+
+#[test]
+fn generated_file_is_included() {
+    let fixture = Fixture::new("pre_build", &main_rs("")).env("INLINE_PROC_TEST_ANSWER", "42");
+    assert_eq!(fixture.run(), "42\n");
+
+    // Changing the crate makes Cargo expand the module again, and the output changed with it.
+    let fixture =
+        Fixture::new("pre_build", &main_rs("changed")).env("INLINE_PROC_TEST_ANSWER", "43");
+    assert_eq!(fixture.run(), "43\n");
+}
+
+#[test]
+fn failing_command_is_an_error() {
+    let fixture = Fixture::new(
+        "pre_build_fails",
+        &module_with_options(r#"pre_build: ["sh", "-c", "echo no schema >&2; exit 3"],"#),
+    );
+    assert_contains(
+        &fixture.compile_fail(),
+        &[
+            "Pre-build command `sh -c echo no schema >&2; exit 3` failed with exit status: 3",
+            "no schema",
+        ],
+    );
+}
@@ -0,0 +1,57 @@
+//! Macro functions with `proc_macro2: true`, which take and return `proc_macro2` token streams.
+
+mod support;
+
+use support::Fixture;
+
+#[test]
+fn every_kind_of_macro() {
+    let fixture = Fixture::new(
+        "proc_macro2_kinds",
+        r#"
+        #[inline_proc::inline_proc]
+        mod kinds {
+            metadata::ron!(
+                edition: "2021",
+                dependencies: { "proc-macro2": "1", "quote": "1", "syn": "2" },
+                exports: (
+                    bang_macros: { "double": ( function: "double", proc_macro2: true ) },
+                    derives: { "Named": ( function: "named", proc_macro2: true ) },
+                    attributes: { "rename": ( function: "rename", proc_macro2: true ) },
+                ),
+            );
+            use proc_macro2::TokenStream;
+
+            pub fn double(input: TokenStream) -> TokenStream {
+                quote::quote!(2 * (#input))
+            }
+
+            pub fn named(item: TokenStream) -> TokenStream {
+                let name = syn::parse2::<syn::DeriveInput>(item).unwrap().ident;
+                let text = name.to_string();
+                quote::quote!(impl #name { fn name() -> &'static str { #text } })
+            }
+
+            pub fn rename(attr: TokenStream, item: TokenStream) -> TokenStream {
+                let name = syn::parse2::<syn::Ident>(attr).unwrap();
+                let mut item = syn::parse2::<syn::DeriveInput>(item).unwrap();
+                item.ident = name;
+                quote::quote!(#item)
+            }
+        }
+
+        #[derive(inline_proc::InlineDerive)]
+        #[inline_derive(Named)]
+        struct Derived;
+
+        #[inline_proc::inline_attr[rename(Renamed)]]
+        struct Original;
+
+        fn main() {
+            let _ = Renamed;
+            println!("{} {}", double!(21), Derived::name());
+        }
+        "#,
+    );
+    assert_eq!(fixture.run(), "42 Derived\n");
+}
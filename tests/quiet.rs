@@ -0,0 +1,33 @@
+//! `quiet`, which keeps Cargo's progress messages about the macro's crate out of the build output.
+
+mod support;
+
+use support::{module_with_options, Fixture};
+
+#[test]
+fn quiet_by_default() {
+    let stderr = Fixture::new("quiet_default", &module_with_options(""))
+        .with_private_tmpdir()
+        .env_remove("INLINE_PROC_VERBOSE")
+        .build();
+    assert!(!stderr.contains("Checking"), "{}", stderr);
+    assert!(!stderr.contains("Finished"), "{}", stderr);
+}
+
+#[test]
+fn progress_without_quiet() {
+    let stderr = Fixture::new("quiet_off", &module_with_options("quiet: false,"))
+        .with_private_tmpdir()
+        .env_remove("INLINE_PROC_VERBOSE")
+        .build();
+    assert!(stderr.contains("Checking inline-proc-macro"), "{}", stderr);
+}
+
+#[test]
+fn verbose_environment_variable_overrides_quiet() {
+    let stderr = Fixture::new("quiet_verbose", &module_with_options(""))
+        .with_private_tmpdir()
+        .env("INLINE_PROC_VERBOSE", "1")
+        .build();
+    assert!(stderr.contains("Checking inline-proc-macro"), "{}", stderr);
+}
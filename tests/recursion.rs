@@ -0,0 +1,78 @@
+//! Bang macros that expand to invocations of themselves.
+
+mod support;
+
+use support::{assert_contains, Fixture};
+
+/// `count!(n)` expands to `n` plus `count!(n - 1)`, down to 0, and `forever!(n)` to `forever!(n + 1)`.
+const MAIN_RS: &str = r#"
+#[inline_proc::inline_proc]
+mod recursive {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: { "proc-macro2": "1", "quote": "1", "syn": "2" },
+        exports: (
+            bang_macros: {
+                "count": ( function: "count", proc_macro2: true ),
+                "forever": ( function: "forever", proc_macro2: true ),
+                "same": ( function: "same", proc_macro2: true ),
+            },
+        ),
+    );
+    use proc_macro2::TokenStream;
+
+    fn number(input: TokenStream) -> u32 {
+        syn::parse2::<syn::LitInt>(input).unwrap().base10_parse().unwrap()
+    }
+
+    pub fn count(input: TokenStream) -> TokenStream {
+        match number(input) {
+            0 => quote::quote!(0),
+            n => {
+                let rest = proc_macro2::Literal::u32_unsuffixed(n - 1);
+                quote::quote!(#n + count!(#rest))
+            }
+        }
+    }
+
+    pub fn forever(input: TokenStream) -> TokenStream {
+        let next = proc_macro2::Literal::u32_unsuffixed(number(input) + 1);
+        quote::quote!(forever!(#next))
+    }
+
+    pub fn same(input: TokenStream) -> TokenStream {
+        quote::quote!(same!(#input))
+    }
+}
+
+fn main() {
+    MAIN
+}
+"#;
+
+#[test]
+fn bounded_recursion_expands() {
+    let main_rs = MAIN_RS.replace("MAIN", r#"println!("{}", count!(10));"#);
+    assert_eq!(Fixture::new("recursion_bounded", &main_rs).run(), "55\n");
+}
+
+#[test]
+fn unbounded_recursion_is_an_error() {
+    let main_rs = MAIN_RS.replace("MAIN", "let _ = forever!(0);");
+    let stderr = Fixture::new("recursion_unbounded", &main_rs).compile_fail();
+    assert_contains(
+        &stderr,
+        &["error: Recursive inline macro invocation detected: `forever!` expanded to itself 32 times"],
+    );
+    assert!(!stderr.contains("recursion limit reached"), "{}", stderr);
+}
+
+#[test]
+fn expanding_to_the_same_invocation_is_an_error() {
+    let main_rs = MAIN_RS.replace("MAIN", "let _ = same!(0);");
+    let stderr = Fixture::new("recursion_same", &main_rs).compile_fail();
+    assert_contains(
+        &stderr,
+        &["error: Recursive inline macro invocation detected: `same!` expands to itself"],
+    );
+}
@@ -0,0 +1,53 @@
+//! Macros registered with `export_bang!`, `export_derive!` and `export_attribute!`.
+
+mod support;
+
+use support::{assert_contains, position, Fixture};
+
+#[test]
+fn registered_macro_is_exported() {
+    let fixture = Fixture::new(
+        "registered",
+        r#"
+        #[inline_proc::inline_proc]
+        mod registered {
+            metadata::ron!(
+                edition: "2021",
+                dependencies: { "proc-macro2": "1", "quote": "1" },
+                exports: (),
+            );
+            inline_proc::export_bang!("double", |input: proc_macro2::TokenStream| {
+                quote::quote!((#input) * 2)
+            });
+        }
+        fn main() {
+            println!("{}", double!(1 + 2));
+        }
+        "#,
+    );
+    assert_eq!(fixture.run(), "6\n");
+}
+
+#[test]
+fn registered_and_in_exports() {
+    let main_rs = r#"#[inline_proc::inline_proc]
+mod registered_twice {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: {},
+        exports: ( bang_macros: { "double": "double" } ),
+    );
+    inline_proc::export_bang!(double, |input| input);
+}
+fn main() {}
+"#;
+    let (line, column) = position(main_rs, "double, |input|");
+    assert_contains(
+        &Fixture::new("registered_twice", main_rs).compile_fail(),
+        &[
+            "error: Macro `double` is registered here, but it is also in the `exports` of the \
+             metadata",
+            &format!("--> src/main.rs:{}:{}", line, column),
+        ],
+    );
+}
@@ -0,0 +1,32 @@
+//! Dependencies renamed with `package`, which the macro uses by their new name.
+
+mod support;
+
+use support::Fixture;
+
+#[test]
+fn renamed_dependency_is_used_by_its_name() {
+    let fixture = Fixture::new(
+        "renamed_dependency",
+        r#"
+        #[inline_proc::inline_proc]
+        mod renamed {
+            metadata::ron!(
+                edition: "2021",
+                dependencies: {
+                    "proc-macro2": "1",
+                    "my_quote": ( version: "1", package: "quote" ),
+                },
+                exports: ( bang_macros: { "answer": ( function: "answer", proc_macro2: true ) } ),
+            );
+            pub fn answer(_: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+                my_quote::quote!(42)
+            }
+        }
+        fn main() {
+            println!("{}", answer!());
+        }
+        "#,
+    );
+    assert_eq!(fixture.run(), "42\n");
+}
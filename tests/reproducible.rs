@@ -0,0 +1,26 @@
+//! `reproducible`, which gives the dylib a fixed modification time.
+
+mod support;
+
+use std::fs;
+use std::time::{Duration, UNIX_EPOCH};
+
+use support::{module_with_options, Fixture};
+
+#[test]
+fn dylib_has_the_source_date_epoch() {
+    let fixture = Fixture::new("reproducible", &module_with_options("reproducible: true,"))
+        .with_private_tmpdir()
+        .env("SOURCE_DATE_EPOCH", "1000000000");
+    fixture.build();
+    let crate_root = fixture.crates_dir().join("reproducible-0.0-inline");
+    let dylib = fs::read_dir(&crate_root)
+        .unwrap()
+        .map(|file| file.unwrap().path())
+        .find(|path| path.extension() == Some(std::env::consts::DLL_EXTENSION.as_ref()))
+        .unwrap();
+    assert_eq!(
+        fs::metadata(dylib).unwrap().modified().unwrap(),
+        UNIX_EPOCH + Duration::from_secs(1_000_000_000),
+    );
+}
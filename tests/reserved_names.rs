@@ -0,0 +1,71 @@
+//! Items of the module whose names collide with the glue added to the inner crate.
+
+mod support;
+
+use support::{assert_contains, position, Fixture};
+
+/// A `main.rs` with a module that exports the bang macro `m`, with the macro options, and contains
+/// the items.
+fn main_rs(macro_options: &str, items: &str) -> String {
+    format!(
+        r#"#[inline_proc::inline_proc]
+mod reserved {{
+    metadata::ron!(
+        edition: "2021",
+        dependencies: {{}},
+        exports: ( bang_macros: {{ "m": ( function: "m", {} ) }} ),
+    );
+    pub fn m(input: proc_macro::TokenStream) -> proc_macro::TokenStream {{
+        input
+    }}
+    {}
+}}
+fn main() {{}}
+"#,
+        macro_options, items
+    )
+}
+
+/// Assert that the crate fails to compile with the error, pointing at `pattern`.
+#[track_caller]
+fn assert_error_at(name: &str, main_rs: &str, error: &str, pattern: &str) {
+    let stderr = Fixture::new(name, main_rs).compile_fail();
+    let (line, column) = position(main_rs, pattern);
+    assert_contains(
+        &stderr,
+        &[
+            &format!("error: {}", error),
+            &format!("--> src/main.rs:{}:{}", line, column),
+        ],
+    );
+}
+
+#[test]
+fn glue_function_name() {
+    assert_error_at(
+        "reserved_glue_function",
+        &main_rs("", "fn __exported_macro_bang_m() {}"),
+        "`__exported_macro_bang_m` collides with the names reserved by inline-proc",
+        "__exported_macro_bang_m",
+    );
+}
+
+#[test]
+fn extern_crate_proc_macro() {
+    assert_error_at(
+        "reserved_extern_crate",
+        &main_rs("", "extern crate proc_macro;"),
+        "`extern crate proc_macro;` is already added to the inline proc crate",
+        "extern crate",
+    );
+}
+
+#[test]
+fn macro_output_module() {
+    assert_error_at(
+        "reserved_macro_output",
+        &main_rs("macro_output: true", "mod inline_proc {}"),
+        "`inline_proc` collides with the module added for macros with `macro_output`",
+        "inline_proc {}",
+    );
+}
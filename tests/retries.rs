@@ -0,0 +1,76 @@
+//! `build_retries`, which retries Cargo commands that fail with transient errors.
+
+mod support;
+
+use std::fs;
+use support::{assert_contains, module_with_options, test_file, Fixture};
+
+const TRANSIENT_ERROR: &str = "error: failed to download `serde v1.0.0`";
+
+/// A crate whose Cargo fails the first time it runs each of the commands with the error.
+fn fixture(name: &str, build_retries: u32, commands: &str, error: &str) -> Fixture {
+    let options = format!(
+        "cargo: {:?}, build_retries: {},",
+        test_file("flaky-cargo.sh"),
+        build_retries
+    );
+    let fixture = Fixture::new(name, &module_with_options(&options));
+    let marker = fixture.dir().join("flaky-cargo-failed");
+    for command in ["check", "build"] {
+        let _ = fs::remove_file(marker.with_extension(command));
+    }
+    fixture
+        .env("FLAKY_CARGO_MARKER", marker)
+        .env("FLAKY_CARGO_COMMANDS", commands)
+        .env("FLAKY_CARGO_ERROR", error)
+        .env_remove("INLINE_PROC_VERBOSE")
+}
+
+#[test]
+fn transient_failures_are_retried() {
+    let stderr = fixture(
+        "transient_failures_retried",
+        1,
+        "check build",
+        TRANSIENT_ERROR,
+    )
+    .build();
+    assert!(!stderr.contains("retrying"), "{}", stderr);
+}
+
+#[test]
+fn transient_failures_fail_without_retries() {
+    let stderr = fixture("transient_failures_fail", 0, "build", TRANSIENT_ERROR).compile_fail();
+    assert_contains(&stderr, &["Failed to build the macro", TRANSIENT_ERROR]);
+}
+
+#[test]
+fn other_failures_are_not_retried() {
+    let stderr = fixture(
+        "other_failures_not_retried",
+        2,
+        "check",
+        "error: the manifest is invalid",
+    )
+    .compile_fail();
+    assert_contains(&stderr, &["Failed to check the inline proc macro"]);
+}
+
+#[test]
+fn retries_are_reported_when_verbose() {
+    let stderr = fixture(
+        "retries_reported_verbose",
+        1,
+        "check build",
+        TRANSIENT_ERROR,
+    )
+    .env("INLINE_PROC_VERBOSE", "1")
+    .build();
+    assert_contains(
+        &stderr,
+        &[
+            "inline-proc: Cargo failed to check `inline`, retrying (1 of 1)",
+            "inline-proc: Cargo failed to build `inline`, retrying (1 of 1)",
+        ],
+    );
+}
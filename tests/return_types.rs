@@ -0,0 +1,59 @@
+//! Exported macro functions that clearly don't return a token stream are rejected at their
+//! signature.
+
+mod support;
+
+use support::{assert_contains, position, Fixture};
+
+/// A `main.rs` with a module that exports the function as a bang macro.
+fn main_rs(function: &str) -> String {
+    format!(
+        r#"#[inline_proc::inline_proc]
+mod inline {{
+    metadata::ron!(
+        edition: "2021",
+        dependencies: {{}},
+        exports: (bang_macros: {{ "my_macro": "my_macro" }}),
+    );
+    {}
+}}
+fn main() {{}}
+"#,
+        function
+    )
+}
+
+#[track_caller]
+fn assert_rejected(name: &str, function: &str, found: &str) {
+    let main_rs = main_rs(function);
+    let (line, column) = position(&main_rs, "fn my_macro");
+    assert_contains(
+        &Fixture::new(name, &main_rs).compile_fail(),
+        &[
+            &format!(
+                "error: exported macro function `my_macro` must return `proc_macro::TokenStream`, \
+                 found `{}`",
+                found
+            ),
+            &format!("--> src/main.rs:{}:{}", line, column),
+        ],
+    );
+}
+
+#[test]
+fn unit_return_type() {
+    assert_rejected(
+        "return_type_unit",
+        "pub fn my_macro(_input: proc_macro::TokenStream) {}",
+        "()",
+    );
+}
+
+#[test]
+fn reference_return_type() {
+    assert_rejected(
+        "return_type_reference",
+        r#"pub fn my_macro(_input: proc_macro::TokenStream) -> &'static str { "" }"#,
+        "& 'static str",
+    );
+}
@@ -0,0 +1,41 @@
+//! `rlib`, which builds the inner crate as an `rlib` as well as a dylib.
+
+mod support;
+
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+
+use support::Fixture;
+
+#[test]
+fn both_artifacts_are_built() {
+    // A crates directory of its own, to find the inner crate in.
+    let fixture = Fixture::new(
+        "rlib",
+        r#"
+        #[inline_proc::inline_proc]
+        mod both {
+            metadata::ron!(
+                edition: "2021",
+                rlib: true,
+                dependencies: { "proc-macro2": "1" },
+                exports: ( bang_macros: { "id": ( function: "id", proc_macro2: true ) } ),
+            );
+            pub fn id(input: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+                input
+            }
+        }
+        fn main() {
+            println!("{}", id!(42));
+        }
+        "#,
+    )
+    .with_private_tmpdir();
+    // The dylib is still the one that is loaded.
+    assert_eq!(fixture.run(), "42\n");
+
+    let target = fixture.crates_dir().join("rlib-0.0-both/target/debug");
+    assert!(target.join("libinline_proc_macro.rlib").is_file());
+    assert!(target
+        .join(format!("{}inline_proc_macro{}", DLL_PREFIX, DLL_SUFFIX))
+        .is_file());
+}
@@ -0,0 +1,24 @@
+//! `rust_version`, which Cargo checks against the compiler that builds the macro.
+
+mod support;
+
+use support::{assert_contains, module_with_options, Fixture};
+
+#[test]
+fn too_old_compiler_is_reported() {
+    let stderr = Fixture::new(
+        "rust_version_too_high",
+        &module_with_options(r#"rust_version: "1.999","#),
+    )
+    .compile_fail();
+    assert_contains(&stderr, &["requires rustc 1.999"]);
+}
+
+#[test]
+fn current_compiler_is_accepted() {
+    Fixture::new(
+        "rust_version_accepted",
+        &module_with_options(r#"rust_version: "1.56","#),
+    )
+    .build();
+}
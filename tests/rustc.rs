@@ -0,0 +1,21 @@
+//! The compiler the inner crate is built with.
+
+mod support;
+
+use support::{assert_contains, module_with_options, Fixture};
+
+#[test]
+fn missing_rustc_is_reported() {
+    let stderr = Fixture::new(
+        "missing_rustc",
+        &module_with_options(r#"rustc: "/nonexistent/rustc","#),
+    )
+    .compile_fail();
+    assert_contains(
+        &stderr,
+        &[
+            "error: could not execute process `/nonexistent/rustc -vV`",
+            "error: Failed to check the inline proc macro",
+        ],
+    );
+}
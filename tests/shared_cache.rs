@@ -0,0 +1,29 @@
+//! `shared_cache`, which lets identical modules of different packages share one inner crate.
+
+mod support;
+
+use std::fs;
+
+use support::{assert_contains, module_with_options, Fixture};
+
+#[test]
+fn identical_modules_share_one_crate() {
+    // A crates directory of its own, so that the first build is the one that creates the crate.
+    let main_rs = module_with_options("shared_cache: true,");
+    let first = Fixture::new("shared_cache_first", &main_rs)
+        .with_private_tmpdir()
+        .env("INLINE_PROC_STATS", "1");
+    assert_contains(&first.build(), &["cold build in"]);
+
+    let second = Fixture::new("shared_cache_second", &main_rs)
+        .env("TMPDIR", first.tmpdir())
+        .env("INLINE_PROC_STATS", "1");
+    assert_contains(&second.build(), &["cache hit in"]);
+
+    let crates: Vec<_> = fs::read_dir(first.crates_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .filter(|name| name.to_string_lossy().starts_with("shared-"))
+        .collect();
+    assert_eq!(crates.len(), 1, "{:?}", crates);
+}
@@ -0,0 +1,91 @@
+//! `spans`, which sets the spans of the tokens returned by a macro, and with them its hygiene.
+
+mod support;
+
+use support::{assert_contains, Fixture};
+
+/// A `main.rs` with a macro that expands to `let x = 0;`, exported with the `spans` options, and
+/// the body of `main`.
+fn main_rs(spans: &str, main: &str) -> String {
+    format!(
+        r#"
+        #[inline_proc::inline_proc]
+        mod inline {{
+            metadata::ron!(
+                edition: "2021",
+                dependencies: {{ "proc-macro2": "1", "quote": "1" }},
+                exports: (
+                    bang_macros: {{ "let_x": ( function: "let_x", proc_macro2: true, {} ) }},
+                ),
+            );
+            pub fn let_x(_: proc_macro2::TokenStream) -> proc_macro2::TokenStream {{
+                quote::quote!(let x = 0;)
+            }}
+        }}
+        fn main() {{
+            {}
+        }}
+        "#,
+        spans, main
+    )
+}
+
+// The macro ignores its input, which only gives the output the hygiene of the caller.
+const REFERENCES_X: &str = r#"let_x!(input); println!("{}", x);"#;
+
+const SHADOWS_X: &str = r#"let x = 42; let_x!(input); println!("{}", x);"#;
+
+#[test]
+fn preserved_spans_can_be_referenced() {
+    let fixture = Fixture::new("spans_preserve", &main_rs("", REFERENCES_X));
+    assert_eq!(fixture.run(), "0\n");
+}
+
+#[test]
+fn call_site_spans_can_be_referenced() {
+    let fixture = Fixture::new(
+        "spans_call_site",
+        &main_rs(r#"spans: "call_site","#, REFERENCES_X),
+    );
+    assert_eq!(fixture.run(), "0\n");
+}
+
+#[test]
+fn mixed_site_spans_cant_be_referenced() {
+    let fixture = Fixture::new(
+        "spans_mixed_site_reference",
+        &main_rs(r#"spans: "mixed_site","#, REFERENCES_X),
+    );
+    assert_contains(
+        &fixture.compile_fail(),
+        &["cannot find value `x` in this scope"],
+    );
+}
+
+#[test]
+fn mixed_site_spans_are_hygienic() {
+    let fixture = Fixture::new(
+        "spans_mixed_site_shadow",
+        &main_rs(r#"spans: "mixed_site","#, SHADOWS_X),
+    );
+    assert_eq!(fixture.run(), "42\n");
+}
+
+#[test]
+fn preserved_spans_shadow() {
+    let fixture = Fixture::new("spans_preserve_shadow", &main_rs("", SHADOWS_X));
+    assert_eq!(fixture.run(), "0\n");
+}
+
+#[test]
+fn call_site_spans_without_input_are_hygienic() {
+    // Without input, the only call site there is is inside the generated `macro_rules!` macro.
+    let fixture = Fixture::new(
+        "spans_call_site_no_input",
+        &main_rs(r#"spans: "call_site","#, r#"let_x!(); println!("{}", x);"#),
+    );
+    assert_contains(
+        &fixture.compile_fail(),
+        &["cannot find value `x` in this scope"],
+    );
+}
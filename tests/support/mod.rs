@@ -0,0 +1,207 @@
+//! Building crates of their own, for tests of code that fails to compile or that must be built in
+//! a particular environment.
+#![allow(dead_code)]
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// A binary crate that uses `inline-proc`, built in the temporary directory of the tests. Its
+/// package is named after the test, so its inner crates don't clash with those of other tests.
+pub struct Fixture {
+    dir: PathBuf,
+    envs: Vec<(String, Option<OsString>)>,
+    tmpdir: Option<PathBuf>,
+}
+
+impl Fixture {
+    pub fn new(name: &str, main_rs: &str) -> Self {
+        let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        let cargo_toml = format!(
+            "[package]\n\
+             name = {:?}\n\
+             version = \"0.0.0\"\n\
+             edition = \"2021\"\n\
+             [dependencies]\n\
+             inline-proc = {{ path = {:?} }}\n\
+             [workspace]\n",
+            name,
+            env!("CARGO_MANIFEST_DIR"),
+        );
+        fs::write(dir.join("Cargo.toml"), cargo_toml).unwrap();
+        fs::write(dir.join("src/main.rs"), main_rs).unwrap();
+        Self {
+            dir,
+            envs: Vec::new(),
+            tmpdir: None,
+        }
+    }
+
+    /// The directory of the crate, with `Cargo.toml` and `src/main.rs`.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Give the crate a temporary directory of its own, named after it, so that its crates directory
+    /// only has the inner crates it builds, and they are built from scratch.
+    pub fn with_private_tmpdir(self) -> Self {
+        let name = self.dir.file_name().unwrap().to_str().unwrap();
+        let tmpdir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}_tmp", name));
+        self.with_tmpdir(tmpdir)
+    }
+
+    /// Like `with_private_tmpdir`, but in the given directory.
+    pub fn with_tmpdir(self, tmpdir: PathBuf) -> Self {
+        let _ = fs::remove_dir_all(&tmpdir);
+        fs::create_dir_all(&tmpdir).unwrap();
+        let mut fixture = self.env("TMPDIR", &tmpdir);
+        fixture.tmpdir = Some(tmpdir);
+        fixture
+    }
+
+    /// The temporary directory of the crate's own, from `with_private_tmpdir`.
+    pub fn tmpdir(&self) -> &Path {
+        self.tmpdir
+            .as_deref()
+            .expect("the fixture has no temporary directory of its own")
+    }
+
+    /// The crates directory in the crate's own temporary directory, with the inner crates in it.
+    pub fn crates_dir(&self) -> PathBuf {
+        self.tmpdir().join("inline-proc-crates")
+    }
+
+    pub fn env(mut self, key: &str, value: impl Into<OsString>) -> Self {
+        self.envs.push((key.to_owned(), Some(value.into())));
+        self
+    }
+
+    pub fn env_remove(mut self, key: &str) -> Self {
+        self.envs.push((key.to_owned(), None));
+        self
+    }
+
+    /// Use `tests/expect-arg-cargo.sh`, which fails unless the Cargo commands contain the argument.
+    /// The crate must use it as its `cargo`.
+    pub fn expect_cargo_arg(self, commands: &str, arg: &str) -> Self {
+        self.env("EXPECT_CARGO_COMMANDS", commands)
+            .env("EXPECT_CARGO_ARG", arg)
+    }
+
+    /// Build and run the crate, returning its standard output, or panicking with the errors.
+    pub fn run(&self) -> String {
+        let output = self.cargo("run");
+        assert!(
+            output.status.success(),
+            "the fixture failed:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Build the crate, returning what the compiler printed to stderr, or panicking with the errors.
+    pub fn build(&self) -> String {
+        let output = self.cargo("build");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(output.status.success(), "the fixture failed:\n{}", stderr);
+        stderr
+    }
+
+    /// Document the crate, returning the directory of its documentation.
+    pub fn doc(&self) -> PathBuf {
+        let output = self.cargo("doc");
+        assert!(
+            output.status.success(),
+            "the fixture failed:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let name = self.dir.file_name().unwrap().to_str().unwrap();
+        Path::new(env!("CARGO_TARGET_TMPDIR"))
+            .join("fixtures-target/doc")
+            .join(name.replace('-', "_"))
+    }
+
+    /// Build the crate, returning the errors it fails with, or panicking if it compiles.
+    pub fn compile_fail(&self) -> String {
+        let output = self.cargo("build");
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            !output.status.success(),
+            "the fixture compiled:\n{}",
+            stderr
+        );
+        stderr
+    }
+
+    fn cargo(&self, subcommand: &str) -> Output {
+        let mut command = Command::new(env!("CARGO"));
+        // The target directory is shared so that `inline-proc` is only built once. It must be an
+        // argument: the inner crates would inherit `CARGO_TARGET_DIR` and wait for the lock on it.
+        command
+            .args([subcommand, "--quiet", "--color=never", "--manifest-path"])
+            .arg(self.dir.join("Cargo.toml"))
+            .arg("--target-dir")
+            .arg(Path::new(env!("CARGO_TARGET_TMPDIR")).join("fixtures-target"))
+            .env_remove("CARGO_TARGET_DIR");
+        for (key, value) in &self.envs {
+            match value {
+                Some(value) => command.env(key, value),
+                None => command.env_remove(key),
+            };
+        }
+        command.output().unwrap()
+    }
+}
+
+/// The absolute path of a file in `tests`, such as a fake Cargo.
+pub fn test_file(name: &str) -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// A `main.rs` with an inline module named `inline` that has the metadata options, besides an
+/// edition, and no dependencies or exports.
+pub fn module_with_options(options: &str) -> String {
+    format!(
+        r#"
+        #[inline_proc::inline_proc]
+        mod inline {{
+            metadata::ron!(
+                edition: "2021",
+                {}
+                dependencies: {{}},
+                exports: (),
+            );
+        }}
+        fn main() {{}}
+        "#,
+        options
+    )
+}
+
+/// The line and column of the first occurrence of `pattern` in `source`, both starting at 1.
+pub fn position(source: &str, pattern: &str) -> (usize, usize) {
+    source
+        .lines()
+        .enumerate()
+        .find_map(|(line, text)| Some((line + 1, text.find(pattern)? + 1)))
+        .unwrap_or_else(|| panic!("`{}` isn't in the source", pattern))
+}
+
+/// Assert that the output contains every message.
+#[track_caller]
+pub fn assert_contains(output: &str, messages: &[&str]) {
+    for message in messages {
+        assert!(
+            output.contains(message),
+            "expected `{}` in:\n{}",
+            message,
+            output
+        );
+    }
+}
@@ -0,0 +1,88 @@
+//! `targets`, which builds the macro for other targets too, and loads the one built for the host.
+
+mod support;
+
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+use std::fs;
+
+use support::{test_file, Fixture};
+
+#[test]
+fn host_dylib_is_named_like_the_native_one() {
+    // A crates directory of its own, to find the inner crate in.
+    let fixture = Fixture::new(
+        "targets",
+        r#"
+        #[inline_proc::inline_proc]
+        mod host {
+            metadata::ron!(
+                edition: "2021",
+                targets: ["host-tuple"],
+                dependencies: { "proc-macro2": "1" },
+                exports: ( bang_macros: { "id": ( function: "id", proc_macro2: true ) } ),
+            );
+            pub fn id(input: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+                input
+            }
+        }
+        fn main() {
+            println!("{}", id!(42));
+        }
+        "#,
+    )
+    .with_private_tmpdir();
+    assert_eq!(fixture.run(), "42\n");
+
+    let dylib = format!("{}inline_proc_macro{}", DLL_PREFIX, DLL_SUFFIX);
+    let crate_root = fixture.crates_dir().join("targets-0.0-host");
+    assert!(crate_root.join("target/debug").join(&dylib).is_file());
+    assert!(crate_root
+        .join("dist")
+        .join(env!("INLINE_PROC_HOST"))
+        .join(&dylib)
+        .is_file());
+}
+
+/// A `main.rs` whose module has the targets and a macro that expands to the answer.
+#[cfg(unix)]
+fn answer_main_rs(targets: &str, answer: u8) -> String {
+    format!(
+        r#"
+        #[inline_proc::inline_proc]
+        mod host {{
+            metadata::ron!(
+                edition: "2021",
+                targets: [{}],
+                cargo_subcommand: [{:?}],
+                dependencies: {{ "proc-macro2": "1" }},
+                exports: ( bang_macros: {{ "answer": ( function: "answer", proc_macro2: true ) }} ),
+            );
+            pub fn answer(_: proc_macro2::TokenStream) -> proc_macro2::TokenStream {{
+                "{}".parse().unwrap()
+            }}
+        }}
+        fn main() {{
+            println!("{{}}", answer!());
+        }}
+        "#,
+        targets,
+        test_file("fake-target-cargo.sh"),
+        answer
+    )
+}
+
+#[cfg(unix)]
+#[test]
+fn host_dylib_is_removed_with_the_host_target() {
+    let main_rs = answer_main_rs(r#""host-tuple", "fake-target""#, 1);
+    let fixture = Fixture::new("targets_removed", &main_rs).with_private_tmpdir();
+    assert_eq!(fixture.run(), "1\n");
+
+    // Without the host, the dylib built for it in `dist` is stale and mustn't be loaded.
+    let main_rs = answer_main_rs(r#""fake-target""#, 2);
+    fs::write(fixture.dir().join("src/main.rs"), main_rs).unwrap();
+    assert_eq!(fixture.run(), "2\n");
+    let dist = fixture.crates_dir().join("targets_removed-0.0-host/dist");
+    assert!(dist.join("fake-target").is_dir());
+    assert!(!dist.join(env!("INLINE_PROC_HOST")).exists());
+}
@@ -0,0 +1,67 @@
+//! `INLINE_PROC_TIMINGS` reports how long each module took to build.
+
+mod support;
+
+use support::{assert_contains, Fixture};
+
+/// Two modules with no dependencies or exports.
+const MAIN_RS: &str = r#"
+#[inline_proc::inline_proc]
+mod first {
+    metadata::ron!(edition: "2021", dependencies: {}, exports: ());
+}
+#[inline_proc::inline_proc]
+mod second {
+    metadata::ron!(edition: "2021", dependencies: {}, exports: ());
+}
+fn main() {}
+"#;
+
+#[test]
+fn text_lines_and_summary_table() {
+    let fixture = Fixture::new("timings_text", MAIN_RS).env("INLINE_PROC_TIMINGS", "1");
+    let stderr = fixture.build();
+    assert_contains(
+        &stderr,
+        &[
+            "inline-proc: built `first` at src/main.rs:2:1",
+            "inline-proc: built `second` at src/main.rs:6:1",
+            "dependencies ",
+        ],
+    );
+    let table = &stderr[stderr
+        .find("inline-proc: build times of 2 inline modules\n")
+        .expect("no summary table")..];
+    let mut lines = table.lines().skip(1);
+    let header = lines.next().unwrap();
+    assert!(header.starts_with("module  location"), "{}", header);
+    let mut modules: Vec<_> = lines
+        .take(2)
+        .map(|row| row.split_whitespace().next().unwrap())
+        .collect();
+    modules.sort_unstable();
+    assert_eq!(modules, ["first", "second"]);
+}
+
+#[test]
+fn json_lines() {
+    let fixture = Fixture::new("timings_json", MAIN_RS).env("INLINE_PROC_TIMINGS", "json");
+    let stderr = fixture.build();
+    let objects: Vec<serde_json::Value> = stderr
+        .lines()
+        .filter_map(|line| line.strip_prefix("inline-proc: {"))
+        .map(|object| serde_json::from_str(&format!("{{{}", object)).unwrap())
+        .collect();
+    assert_eq!(objects.len(), 2, "{}", stderr);
+    let mut modules: Vec<_> = objects
+        .iter()
+        .map(|object| object["module"].as_str().unwrap())
+        .collect();
+    modules.sort_unstable();
+    assert_eq!(modules, ["first", "second"]);
+    for field in ["dependencies_ms", "check_ms", "build_ms"] {
+        assert!(objects[0][field].is_u64(), "{}", objects[0]);
+    }
+    assert!(objects[0]["cold"].is_boolean(), "{}", objects[0]);
+    assert!(!stderr.contains("build times of"), "{}", stderr);
+}
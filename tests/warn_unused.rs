@@ -0,0 +1,54 @@
+//! `warn_unused`, which warns about the macros of a module that are never invoked.
+
+mod support;
+
+use support::{assert_contains, Fixture};
+
+/// A `main.rs` with a module that exports `used` and `unused`, with the metadata options, and
+/// invokes `used`.
+fn main_rs(options: &str) -> String {
+    format!(
+        r#"#[inline_proc::inline_proc]
+mod maybe_unused {{
+    metadata::ron!(
+        edition: "2021",
+        {}
+        dependencies: {{ "proc-macro2": "1" }},
+        exports: (bang_macros: {{
+            "used": ( function: "identity", proc_macro2: true ),
+            "unused": ( function: "identity", proc_macro2: true ),
+        }}),
+    );
+    pub fn identity(input: proc_macro2::TokenStream) -> proc_macro2::TokenStream {{
+        input
+    }}
+}}
+used!(fn main() {{}});
+"#,
+        options
+    )
+}
+
+#[test]
+fn unused_macro_is_reported() {
+    let main_rs = main_rs("warn_unused: true,");
+    let stderr = Fixture::new("warn_unused", &main_rs).build();
+    assert_contains(&stderr, &["warning: unused macro definition: `unused`"]);
+    assert!(!stderr.contains("`used`"), "{}", stderr);
+}
+
+#[test]
+fn environment_variable_enables_it() {
+    let stderr = Fixture::new("warn_unused_env", &main_rs(""))
+        .env("INLINE_PROC_WARN_UNUSED", "1")
+        .build();
+    assert_contains(&stderr, &["warning: unused macro definition: `unused`"]);
+}
+
+#[test]
+fn off_by_default() {
+    let stderr = Fixture::new("warn_unused_default", &main_rs(""))
+        .env_remove("INLINE_PROC_WARN_UNUSED")
+        .build();
+    assert!(!stderr.contains("unused macro definition"), "{}", stderr);
+}
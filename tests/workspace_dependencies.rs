@@ -0,0 +1,101 @@
+//! Dependencies inherited from the `[workspace.dependencies]` of the package's workspace.
+
+mod support;
+
+use std::fs;
+use std::path::Path;
+
+use support::{assert_contains, Fixture};
+
+const MAIN_RS: &str = r#"
+#[inline_proc::inline_proc]
+mod inherited {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: {
+            "proc-macro2": ( workspace: true ),
+            "quote": ( workspace: true ),
+            "helper": ( workspace: true ),
+        },
+        exports: ( bang_macros: { "answer": ( function: "answer", proc_macro2: true ) } ),
+    );
+    pub fn answer(_: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+        let answer = helper::answer();
+        quote::quote!(#answer)
+    }
+}
+fn main() {
+    println!("{}", answer!());
+}
+"#;
+
+/// A fixture whose workspace has the dependencies, including `helper`, a path dependency relative
+/// to the workspace root.
+fn fixture(name: &str, main_rs: &str) -> Fixture {
+    let fixture = Fixture::new(name, main_rs);
+    let dir = fixture.dir();
+    let cargo_toml = fs::read_to_string(dir.join("Cargo.toml")).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        format!(
+            "{}[workspace.dependencies]\n\
+             proc-macro2 = \"1\"\n\
+             quote = \"1\"\n\
+             helper = {{ path = \"helper\" }}\n",
+            cargo_toml
+        ),
+    )
+    .unwrap();
+    fs::create_dir_all(dir.join("helper/src")).unwrap();
+    fs::write(
+        dir.join("helper/Cargo.toml"),
+        "[package]\nname = \"helper\"\nversion = \"0.0.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("helper/src/lib.rs"),
+        "pub fn answer() -> i32 { 42 }\n",
+    )
+    .unwrap();
+    fixture
+}
+
+#[test]
+fn dependencies_are_inherited() {
+    assert_eq!(fixture("workspace_inherited", MAIN_RS).run(), "42\n");
+}
+
+#[test]
+fn missing_workspace_dependency() {
+    let main_rs = MAIN_RS.replace(
+        "\"helper\": ( workspace: true ),",
+        "\"syn\": ( workspace: true ),",
+    );
+    let stderr = fixture("workspace_missing", &main_rs).compile_fail();
+    assert_contains(
+        &stderr,
+        &["error: Dependency `syn` is not in the workspace dependencies of"],
+    );
+}
+
+#[test]
+fn dependencies_are_rendered_with_the_workspace_definition() {
+    let main_rs = r#"
+        fn main() {
+            print!("{}", inline_proc::generated_cargo_toml!(metadata::ron!(
+                edition: "2021",
+                dependencies: { "quote": ( workspace: true ), "helper": ( workspace: true ) },
+                exports: (),
+            )));
+        }
+    "#;
+    let fixture = fixture("workspace_rendered", main_rs);
+    let manifest: toml::Table = fixture.run().parse().unwrap();
+    let dependencies = &manifest["dependencies"];
+    assert_eq!(dependencies["quote"].as_str(), Some("1"));
+    // The inner crate isn't in the workspace, so the path is made absolute.
+    assert_eq!(
+        dependencies["helper"]["path"].as_str().map(Path::new),
+        Some(&*fixture.dir().join("helper"))
+    );
+}
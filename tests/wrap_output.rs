@@ -0,0 +1,52 @@
+//! `wrap_output` and `output_attributes`, applied to the output of every macro of a module.
+
+mod support;
+
+use support::Fixture;
+
+/// Both invocations of `make_fn!` output a private `helper`, which would clash if they weren't
+/// wrapped in different modules, and `value!` outputs an expression, which isn't wrapped.
+const MAIN_RS: &str = r#"
+#![deny(dead_code)]
+
+#[inline_proc::inline_proc]
+mod wrapped {
+    metadata::ron!(
+        edition: "2021",
+        dependencies: { "proc-macro2": "1", "quote": "1" },
+        wrap_output: "generated",
+        output_attributes: ["allow(dead_code)"],
+        exports: (
+            bang_macros: {
+                "make_fn": ( function: "make_fn", proc_macro2: true ),
+                "value": ( function: "value", proc_macro2: true ),
+            },
+        ),
+    );
+    use proc_macro2::TokenStream;
+
+    pub fn make_fn(name: TokenStream) -> TokenStream {
+        quote::quote! {
+            pub fn #name() -> u32 { helper() }
+            fn helper() -> u32 { 21 }
+            fn unused() {}
+        }
+    }
+
+    pub fn value(_: TokenStream) -> TokenStream {
+        quote::quote!(21)
+    }
+}
+
+make_fn!(first);
+make_fn!(second);
+
+fn main() {
+    println!("{}", first() + second() + value!());
+}
+"#;
+
+#[test]
+fn output_is_wrapped_per_invocation() {
+    assert_eq!(Fixture::new("wrap_output", MAIN_RS).run(), "63\n");
+}